@@ -0,0 +1,231 @@
+// Animated textures are vertical strips of square frames, with a sidecar
+// next to the image (`water.png` and `water.txt`) saying how to play them,
+// one `key value` per line, e.g.
+//
+// frametime 0.25
+// interpolate true
+// frames 0 1 2 3:1.0 2 1
+//
+// `frametime` is the default seconds per frame, `frames` is the order to
+// show them in, with an optional duration after a `:`. Without `frames`
+// every frame in the strip is shown once, top to bottom.
+
+const DEFAULT_FRAME_TIME: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    // Which frame in the strip, from the top
+    pub index: usize,
+    // Seconds
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationMeta {
+    pub frame_time: f32,
+    // Blend into the next frame instead of jumping to it
+    pub interpolate: bool,
+    // Frame indices with optional durations, None plays the whole strip
+    pub frames: Option<Vec<(usize, Option<f32>)>>,
+}
+
+impl Default for AnimationMeta {
+    fn default() -> Self {
+        Self {
+            frame_time: DEFAULT_FRAME_TIME,
+            interpolate: false,
+            frames: None,
+        }
+    }
+}
+
+fn parse_duration(value: &str) -> Result<f32, ()> {
+    match value.parse::<f32>() {
+        Ok(seconds) if seconds > 0.0 => Ok(seconds),
+        _ => Err(()),
+    }
+}
+
+impl AnimationMeta {
+    pub fn parse(contents: &str) -> Result<Self, ()> {
+        let mut meta = Self::default();
+
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line.trim().split_once(' ').ok_or(())?;
+            match key {
+                "frametime" => meta.frame_time = parse_duration(value.trim())?,
+                "interpolate" => meta.interpolate = value.trim().parse().map_err(|_| ())?,
+                "frames" => {
+                    let frames = value
+                        .split_whitespace()
+                        .map(|frame| match frame.split_once(':') {
+                            Some((index, duration)) => Ok((
+                                index.parse().map_err(|_| ())?,
+                                Some(parse_duration(duration)?),
+                            )),
+                            None => Ok((frame.parse().map_err(|_| ())?, None)),
+                        })
+                        .collect::<Result<Vec<_>, ()>>()?;
+                    if frames.is_empty() {
+                        return Err(());
+                    }
+                    meta.frames = Some(frames);
+                }
+                _ => log::warn!("Unknown animation key {key}"),
+            }
+        }
+
+        Ok(meta)
+    }
+}
+
+// Where an animation is up to at some point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationSample {
+    pub frame: usize,
+    pub next: usize,
+    // How far to blend into `next`, always 0 without interpolation
+    pub blend: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    frames: Vec<Frame>,
+    interpolate: bool,
+    // Seconds for one full loop
+    length: f32,
+}
+
+impl Animation {
+    // Fails if the sidecar asks for frames the strip doesn't have
+    pub fn new(meta: &AnimationMeta, frame_count: usize) -> Result<Self, ()> {
+        let frames: Vec<Frame> = match &meta.frames {
+            Some(frames) => frames
+                .iter()
+                .map(|&(index, duration)| Frame {
+                    index,
+                    duration: duration.unwrap_or(meta.frame_time),
+                })
+                .collect(),
+            None => (0..frame_count)
+                .map(|index| Frame {
+                    index,
+                    duration: meta.frame_time,
+                })
+                .collect(),
+        };
+
+        if frames.is_empty() || frames.iter().any(|f| f.index >= frame_count) {
+            return Err(());
+        }
+
+        Ok(Self {
+            length: frames.iter().map(|f| f.duration).sum(),
+            frames,
+            interpolate: meta.interpolate,
+        })
+    }
+
+    pub fn sample(&self, time: f32) -> AnimationSample {
+        let mut time = time.rem_euclid(self.length);
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            if time < frame.duration || i == self.frames.len() - 1 {
+                let next = self.frames[(i + 1) % self.frames.len()].index;
+                let blend = if self.interpolate {
+                    (time / frame.duration).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+
+                return AnimationSample {
+                    frame: frame.index,
+                    next,
+                    blend,
+                };
+            }
+            time -= frame.duration;
+        }
+
+        unreachable!("animations always have a frame")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_parse_meta() {
+        assert_eq!(AnimationMeta::parse(""), Ok(AnimationMeta::default()));
+        assert_eq!(
+            AnimationMeta::parse("frametime 0.5\ninterpolate true\nframes 0 2:1.5 1\n"),
+            Ok(AnimationMeta {
+                frame_time: 0.5,
+                interpolate: true,
+                frames: Some(vec![(0, None), (2, Some(1.5)), (1, None)]),
+            })
+        );
+        assert_eq!(AnimationMeta::parse("frametime 0\n"), Err(()));
+        assert_eq!(AnimationMeta::parse("frames 0 a\n"), Err(()));
+        assert_eq!(AnimationMeta::parse("frames 0:-1\n"), Err(()));
+        assert_eq!(AnimationMeta::parse("interpolate maybe\n"), Err(()));
+    }
+
+    #[test]
+    fn test_whole_strip_in_order() {
+        let meta = AnimationMeta {
+            frame_time: 0.5,
+            ..Default::default()
+        };
+        let animation = Animation::new(&meta, 3).unwrap();
+        assert!(approx(animation.length, 1.5));
+
+        let frame_at = |t| animation.sample(t).frame;
+        assert_eq!(frame_at(0.0), 0);
+        assert_eq!(frame_at(0.49), 0);
+        assert_eq!(frame_at(0.5), 1);
+        assert_eq!(frame_at(1.2), 2);
+        // Loops back around, including for times before the start
+        assert_eq!(frame_at(1.5), 0);
+        assert_eq!(frame_at(3.7), 1);
+        assert_eq!(frame_at(-0.2), 2);
+        assert_eq!(animation.sample(0.3).blend, 0.0);
+    }
+
+    #[test]
+    fn test_custom_frame_durations() {
+        let meta = AnimationMeta::parse("frametime 1\nframes 2 0:3 1:0.5\n").unwrap();
+        let animation = Animation::new(&meta, 3).unwrap();
+        assert!(approx(animation.length, 4.5));
+
+        let frame_at = |t| animation.sample(t).frame;
+        assert_eq!(frame_at(0.5), 2);
+        assert_eq!(frame_at(1.0), 0);
+        assert_eq!(frame_at(3.9), 0);
+        assert_eq!(frame_at(4.0), 1);
+        assert_eq!(frame_at(4.6), 2);
+
+        // Frames past the end of the strip
+        assert_eq!(Animation::new(&meta, 2), Err(()));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let meta = AnimationMeta::parse("frametime 2\ninterpolate true\nframes 0 1\n").unwrap();
+        let animation = Animation::new(&meta, 2).unwrap();
+
+        let sample = animation.sample(0.5);
+        assert_eq!((sample.frame, sample.next), (0, 1));
+        assert!(approx(sample.blend, 0.25));
+
+        // The last frame blends back into the first
+        let sample = animation.sample(3.5);
+        assert_eq!((sample.frame, sample.next), (1, 0));
+        assert!(approx(sample.blend, 0.75));
+    }
+}
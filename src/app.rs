@@ -65,9 +65,7 @@ impl<T: 'static> ApplicationHandler<T> for StateApplication {
     ) {
         if let Some(state) = self.state.as_mut() {
             if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
-                if state.mouse_pressed {
-                    state.camera_controller.process_mouse(dx, dy);
-                }
+                state.mouse_motion(dx, dy);
             }
         }
     }
@@ -1,6 +1,7 @@
 use cgmath::*;
 use std::{f32::consts::FRAC_PI_2, time::Duration};
-use winit::{event::*, keyboard::KeyCode};
+
+use crate::input::MouseLook;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::from_cols(
@@ -20,6 +21,7 @@ pub const WGPU_TO_WORLD_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::from_col
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub position: Point3<f32>,
     pub yaw: Rad<f32>,
@@ -56,12 +58,47 @@ impl Camera {
 
         Vector3::new(cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch).normalize()
     }
+
+    // Part of the way from this camera to another, `t` from 0 to 1
+    pub fn lerp(&self, to: &Camera, t: f32) -> Camera {
+        Camera {
+            position: self.position + (to.position - self.position) * t,
+            yaw: self.yaw + (to.yaw - self.yaw) * t,
+            pitch: self.pitch + (to.pitch - self.pitch) * t,
+        }
+    }
+
+    // Moves the camera relative to where it's facing, `movement` being
+    // x forward, y right and z up, and turns it by `look` radians of
+    // yaw and pitch
+    pub fn fly(&mut self, movement: Vector3<f32>, look: Vector2<f32>, speed: f32, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        // Move forward/backward and left/right
+        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        let forward = Vector3::new(yaw_cos, yaw_sin, 0.0).normalize();
+        let right = Vector3::new(-yaw_sin, yaw_cos, 0.0).normalize();
+        self.position += forward * movement.x * speed * dt;
+        self.position += right * movement.y * speed * dt;
+        self.position.z += movement.z * speed * dt;
+
+        // Rotate
+        self.yaw += Rad(look.x);
+        self.pitch += Rad(look.y);
+
+        // Keep the camera's angle from going too high/low.
+        if self.pitch < -Rad(SAFE_FRAC_PI_2) {
+            self.pitch = -Rad(SAFE_FRAC_PI_2);
+        } else if self.pitch > Rad(SAFE_FRAC_PI_2) {
+            self.pitch = Rad(SAFE_FRAC_PI_2);
+        }
+    }
 }
 
 pub struct Projection {
-    aspect: f32,
+    pub aspect: f32,
     pub fovy: Rad<f32>,
-    znear: f32,
+    pub znear: f32,
     zfar: f32,
 }
 
@@ -84,10 +121,17 @@ impl Projection {
     }
 }
 
+// World space straight to clip space
+pub fn view_proj(camera: &Camera, projection: &Projection) -> Matrix4<f32> {
+    projection.calc_matrix() * WGPU_TO_WORLD_MATRIX * camera.calc_matrix()
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
+    // w is unused, it's only there for alignment
+    pub view_position: [f32; 4],
 }
 
 impl Default for CameraUniform {
@@ -102,113 +146,51 @@ impl CameraUniform {
 
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0; 4],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera, projection: &Projection) {
-        self.view_proj =
-            (projection.calc_matrix() * WGPU_TO_WORLD_MATRIX * camera.calc_matrix()).into();
+        self.view_proj = view_proj(camera, projection).into();
+        self.view_position = camera.position.to_homogeneous().into();
     }
 }
 
+// Adds up how far the mouse has moved, for the world to turn the
+// camera with
 pub struct CameraController {
-    amount_left: f32,
-    amount_right: f32,
-    amount_up: f32,
-    amount_down: f32,
-    amount_forward: f32,
-    amount_backward: f32,
+    look: MouseLook,
     rotate_horizontal: f32,
     rotate_vertical: f32,
-    sensitivity: f32,
-    speed: f32,
-    pub forward: Vector3<f32>,
-    pub right: Vector3<f32>,
 }
 
 impl CameraController {
-    pub fn new(speed: f32, sensitivity: f32) -> Self {
+    pub fn new(look: MouseLook) -> Self {
         Self {
-            speed,
-            sensitivity,
-            amount_left: 0.0,
-            amount_right: 0.0,
-            amount_up: 0.0,
-            amount_down: 0.0,
-            amount_forward: 0.0,
-            amount_backward: 0.0,
+            look,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
-            forward: Vector3::zero(),
-            right: Vector3::zero(),
-        }
-    }
-
-    pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) {
-        let amount = if state == ElementState::Pressed {
-            1.0
-        } else {
-            0.0
-        };
-
-        match key {
-            KeyCode::KeyW | KeyCode::ArrowUp => {
-                self.amount_forward = amount;
-            }
-            KeyCode::KeyS | KeyCode::ArrowDown => {
-                self.amount_backward = amount;
-            }
-            KeyCode::KeyA | KeyCode::ArrowLeft => {
-                self.amount_left = amount;
-            }
-            KeyCode::KeyD | KeyCode::ArrowRight => {
-                self.amount_right = amount;
-            }
-            KeyCode::Space => {
-                self.amount_up = amount;
-            }
-            KeyCode::ShiftLeft => {
-                self.amount_down = amount;
-            }
-            _ => (),
         }
     }
 
+    // Adds up every movement until the next tick takes them
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+        self.rotate_horizontal += mouse_dx as f32;
+        self.rotate_vertical += mouse_dy as f32;
     }
 
-    pub fn process_scroll(&mut self, _delta: &MouseScrollDelta) {}
-
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
-        let dt = dt.as_secs_f32();
-
-        // Move forward/backward and left/right
-        let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
-        self.forward = Vector3::new(yaw_cos, yaw_sin, 0.0).normalize();
-        self.right = Vector3::new(-yaw_sin, yaw_cos, 0.0).normalize();
-        camera.position +=
-            self.forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += self.right * (self.amount_right - self.amount_left) * self.speed * dt;
-        camera.position.z += (self.amount_up - self.amount_down) * self.speed * dt;
-
-        // Rotate
-        camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
-        camera.pitch += Rad(self.rotate_vertical) * self.sensitivity * dt;
-
-        // If process_mouse isn't called every frame, these values
-        // will not get set to zero, and the camera will rotate
-        // when moving in a non cardinal direction.
+    // How far to turn since this was last called, in radians of yaw
+    // and pitch. Moving the mouse down looks down unless inverted.
+    pub fn take_look(&mut self) -> Vector2<f32> {
+        let pitch = if self.look.invert_y {
+            self.rotate_vertical
+        } else {
+            -self.rotate_vertical
+        };
+        let look = Vector2::new(self.rotate_horizontal, pitch) * self.look.sensitivity;
         self.rotate_horizontal = 0.0;
         self.rotate_vertical = 0.0;
-
-        // Keep the camera's angle from going too high/low.
-        if camera.pitch < -Rad(SAFE_FRAC_PI_2) {
-            camera.pitch = -Rad(SAFE_FRAC_PI_2);
-        } else if camera.pitch > Rad(SAFE_FRAC_PI_2) {
-            camera.pitch = Rad(SAFE_FRAC_PI_2);
-        }
+        look
     }
 }
 
@@ -222,4 +204,20 @@ mod tests {
 
         assert_eq!(camera.front(), Vector3::new(1.0, 0.0, 0.0));
     }
+
+    #[test]
+    fn test_mouse_down_looks_down() {
+        let mut controller = CameraController::new(MouseLook::default());
+        controller.process_mouse(10.0, 20.0);
+        let look = controller.take_look();
+        assert!(look.x > 0.0 && look.y < 0.0);
+        assert_eq!(controller.take_look(), Vector2::zero());
+
+        let mut controller = CameraController::new(MouseLook {
+            invert_y: true,
+            ..Default::default()
+        });
+        controller.process_mouse(0.0, 20.0);
+        assert!(controller.take_look().y > 0.0);
+    }
 }
@@ -1,199 +1,316 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::{
-    camera, model,
-    raycasting::{get_colliding_face, BlockFace, Ray, RayResult},
+    camera,
+    frustum::Frustum,
+    lighting::{self, Light},
+    lod::{self, Lod},
+    occlusion::{self, ChunkVisibility},
+    raycasting::{march_ray, BlockFace, Ray, RayResult},
 };
-use cgmath::{prelude::*, Point2, Point3, Vector2};
+use cgmath::{prelude::*, Point2, Point3, Vector3};
 use log::debug;
 
-const CHUNK_WIDTH: usize = 16;
-const CHUNK_HEIGHT: usize = 256;
-const BOTTOM_DEPTH: i32 = -128;
+// Chunks are cubes, stacked up and down as well as side by side, so
+// the world has no top or bottom. They're keyed by their lowest corner.
+pub const CHUNK_WIDTH: usize = 16;
+const CHUNK_VOLUME: usize = CHUNK_WIDTH * CHUNK_WIDTH * CHUNK_WIDTH;
 
-#[derive(Debug, Clone, Copy)]
+// The highest opaque block in each column of a chunk, indexed [y][x]
+pub type Heightmap = [[Option<i32>; CHUNK_WIDTH]; CHUNK_WIDTH];
+const EMPTY_HEIGHTMAP: Heightmap = [[None; CHUNK_WIDTH]; CHUNK_WIDTH];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BlockType {
     Dirt,
     Stone,
+    Door { open: bool },
+    Lamp,
+    Water,
+    Glass,
+    Leaves,
+}
+
+// How a block's faces get drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Material {
+    Opaque,
+    // Either fully solid or fully see-through, by the texture's alpha
+    Cutout,
+    // Blended over whatever is behind it
+    Translucent,
 }
 
 impl BlockType {
-    fn tex_label(&self) -> &'static str {
+    pub fn tex_label(&self) -> &'static str {
         match self {
             Self::Dirt => "dirt",
             Self::Stone => "stone",
+            Self::Door { .. } => "door",
+            Self::Lamp => "lamp",
+            Self::Water => "water",
+            Self::Glass => "glass",
+            Self::Leaves => "leaves",
         }
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-enum BlockExposure {
-    Internal,
-    External,
-    ChunkBorder,
+    pub fn material(&self) -> Material {
+        match self {
+            Self::Dirt | Self::Stone | Self::Lamp => Material::Opaque,
+            Self::Door { .. } | Self::Leaves => Material::Cutout,
+            Self::Water | Self::Glass => Material::Translucent,
+        }
+    }
+
+    // Whether the block stops light, and hides the faces of blocks
+    // next to it
+    pub fn is_opaque(&self) -> bool {
+        self.material() == Material::Opaque
+    }
+
+    pub fn is_fluid(&self) -> bool {
+        matches!(self, Self::Water)
+    }
+
+    pub fn light_emission(&self) -> u8 {
+        match self {
+            Self::Lamp => lighting::MAX_LIGHT,
+            _ => 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Block {
     block_type: BlockType,
-    exposure: BlockExposure,
 }
 
 impl Block {
     pub fn new(block_type: BlockType) -> Self {
-        Self {
-            block_type,
-            //FIXME: implement
-            exposure: BlockExposure::External,
-        }
+        Self { block_type }
+    }
+    pub fn block_type(&self) -> BlockType {
+        self.block_type
+    }
+
+    // Whether the block stops entities moving through it
+    pub fn is_solid(&self) -> bool {
+        !matches!(
+            self.block_type,
+            BlockType::Door { open: true } | BlockType::Water
+        )
     }
-    fn visible(&self) -> bool {
-        //TODO: this is extremely basic, but might
-        //be good enough to keep us going for a while
-        match self.exposure {
-            BlockExposure::ChunkBorder => false,
-            BlockExposure::Internal => false,
-            BlockExposure::External => true,
+
+    pub fn toggle_door(&mut self) {
+        if let BlockType::Door { open } = self.block_type {
+            self.block_type = BlockType::Door { open: !open };
         }
     }
 }
 
+// How a block adds to its chunk's counts: whether it's there at all,
+// whether it's opaque, and whether it glows
+fn block_counts(block: Option<Block>) -> [u16; 3] {
+    block.map_or([0; 3], |block| {
+        let block_type = block.block_type;
+        [
+            1,
+            block_type.is_opaque() as u16,
+            (block_type.light_emission() > 0) as u16,
+        ]
+    })
+}
+
+// A 16 block cube of the world. Keeps count of what's in it, so chunks
+// that are all air or all rock can be skipped over.
 #[derive(Debug, Clone)]
 pub struct Chunk {
-    origin: Point2<i32>,
-    blocks: [[[Option<Block>; CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_HEIGHT],
+    origin: Point3<i32>,
+    blocks: [[[Option<Block>; CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_WIDTH],
+    light: [[[Light; CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_WIDTH],
+    non_air: u16,
+    opaque: u16,
+    emitting: u16,
+    // Worked out when first needed, and thrown away when a block changes
+    visibility: Option<ChunkVisibility>,
 }
 
 impl Chunk {
-    fn get(&self, loc: Point3<usize>) -> Option<Block> {
+    pub fn get(&self, loc: Point3<usize>) -> Option<Block> {
         self.blocks[loc.z][loc.y][loc.x]
     }
 
-    fn idx_to_world(&self, x: usize, y: usize, z: usize) -> Point3<i32> {
-        Point3::new(
-            x as i32 + self.origin.x,
-            y as i32 + self.origin.y,
-            BOTTOM_DEPTH + z as i32,
-        )
+    pub fn get_light(&self, loc: Point3<usize>) -> Light {
+        self.light[loc.z][loc.y][loc.x]
     }
 
-    pub fn gen_instances(&self) -> Vec<model::RenderInstance> {
-        //FIXME: Surely this can be done nice with some sort of mapping
-        let mut result = Vec::new();
+    fn light_mut(&mut self, loc: Point3<usize>) -> &mut Light {
+        &mut self.light[loc.z][loc.y][loc.x]
+    }
 
-        for x in 0..CHUNK_WIDTH {
-            for y in 0..CHUNK_WIDTH {
-                for z in 0..CHUNK_HEIGHT {
-                    if let Some(block) = self.get(Point3::new(x, y, z)) {
-                        if block.visible() {
-                            let position =
-                                self.idx_to_world(x, y, z).cast::<f32>().unwrap().to_vec();
-
-                            let rotation = cgmath::Quaternion::from_axis_angle(
-                                cgmath::Vector3::unit_z(),
-                                cgmath::Deg(0.0),
-                            );
-                            let scale = 0.5;
-
-                            result.push(model::RenderInstance {
-                                position,
-                                rotation,
-                                scale,
-                                //TODO: faster if we can use the static strings everywhere
-                                label: block.block_type.tex_label().to_string(),
-                            });
-                        }
-                    }
-                }
-            }
-        }
+    fn set(&mut self, loc: Point3<usize>, block: Option<Block>) {
+        let slot = &mut self.blocks[loc.z][loc.y][loc.x];
+        let [old_non_air, old_opaque, old_emitting] = block_counts(*slot);
+        let [non_air, opaque, emitting] = block_counts(block);
+        *slot = block;
 
-        result
+        self.non_air = self.non_air - old_non_air + non_air;
+        self.opaque = self.opaque - old_opaque + opaque;
+        self.emitting = self.emitting - old_emitting + emitting;
+        self.visibility = None;
     }
 
-    // Probably only going to be used for testing
-    #[allow(dead_code)]
-    pub fn gen_empty_chunk(origin: Point2<i32>) -> Self {
-        Self {
-            origin,
-            blocks: [[[None; CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_HEIGHT],
-        }
+    pub fn is_empty(&self) -> bool {
+        self.non_air == 0
     }
 
-    pub fn gen_default_chunk(origin: Point2<i32>) -> Self {
-        debug!("Generating new chunk at ({:?}", origin);
-        let solid_fill_height: usize = (-5 - BOTTOM_DEPTH) as usize;
+    // Every block is opaque, so nothing inside can be seen
+    pub fn is_solid(&self) -> bool {
+        self.opaque as usize == CHUNK_VOLUME
+    }
 
-        let mut chunk = Chunk {
-            origin,
-            blocks: [[[None; CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_HEIGHT],
+    // Whether light could be coming from anywhere in the chunk. Solid
+    // chunks hold no light, unless something in them glows.
+    pub fn is_lit(&self) -> bool {
+        !self.is_solid() || self.emitting > 0
+    }
+
+    fn idx_to_world(&self, x: usize, y: usize, z: usize) -> Point3<i32> {
+        self.origin + Vector3::new(x as i32, y as i32, z as i32)
+    }
+
+    // Every block in the chunk, along with its world location
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Point3<i32>, Block)> + '_ {
+        let z_range = if self.is_empty() {
+            0..0
+        } else {
+            0..CHUNK_WIDTH
         };
 
-        for i in 0..CHUNK_WIDTH {
-            for j in 0..CHUNK_WIDTH {
-                for k in 0..solid_fill_height {
-                    let block_type = if k == solid_fill_height - 1 {
-                        BlockType::Stone
+        z_range.flat_map(move |z| {
+            (0..CHUNK_WIDTH).flat_map(move |y| {
+                (0..CHUNK_WIDTH).filter_map(move |x| {
+                    self.blocks[z][y][x].map(|block| (self.idx_to_world(x, y, z), block))
+                })
+            })
+        })
+    }
+
+    // The light of every space in the chunk, along with its world
+    // location
+    pub fn iter_light(&self) -> impl Iterator<Item = (Point3<i32>, Light)> + '_ {
+        (0..CHUNK_WIDTH).flat_map(move |z| {
+            (0..CHUNK_WIDTH).flat_map(move |y| {
+                (0..CHUNK_WIDTH).map(move |x| (self.idx_to_world(x, y, z), self.light[z][y][x]))
+            })
+        })
+    }
+
+    // The height of the highest opaque block in each column, if any
+    pub fn column_tops(&self) -> Heightmap {
+        let mut tops = EMPTY_HEIGHTMAP;
+        if self.opaque == 0 {
+            return tops;
+        }
+
+        for (y, row) in tops.iter_mut().enumerate() {
+            for (x, top) in row.iter_mut().enumerate() {
+                *top = (0..CHUNK_WIDTH)
+                    .rev()
+                    .find(|z| self.blocks[*z][y][x].is_some_and(|b| b.block_type.is_opaque()))
+                    .map(|z| self.origin.z + z as i32);
+            }
+        }
+        tops
+    }
+
+    // Gives full skylight to everything above the highest opaque block
+    // in its column, which may be in a chunk further up, and sets the
+    // block light of anything that glows. Spreading the light around is
+    // left to the lighting engine.
+    pub fn init_light(&mut self, heights: &Heightmap) {
+        for z in 0..CHUNK_WIDTH {
+            let height = self.origin.z + z as i32;
+            for (y, row) in heights.iter().enumerate() {
+                for (x, top) in row.iter().enumerate() {
+                    let sky = if top.is_none_or(|top| height > top) {
+                        lighting::MAX_LIGHT
                     } else {
-                        BlockType::Stone
+                        0
                     };
-
-                    let exposure = if k == solid_fill_height - 1 {
-                        BlockExposure::External
-                    } else if i == 0 || i == CHUNK_WIDTH - 1 || j == 0 || j == CHUNK_WIDTH - 1 {
-                        BlockExposure::ChunkBorder
+                    // Nothing in an empty chunk to glow
+                    let emission = if self.non_air == 0 {
+                        0
                     } else {
-                        BlockExposure::Internal
+                        self.blocks[z][y][x].map_or(0, |b| b.block_type.light_emission())
                     };
-                    chunk.blocks[k][j][i] = Some(Block {
-                        block_type,
-                        exposure,
-                    });
-                }
-
-                for k in solid_fill_height..solid_fill_height + 3 {
-                    // now do some random scattering of blocks on the next row up
-                    if rand::random_ratio(4, 10) && chunk.blocks[k - 1][j][i].is_some() {
-                        chunk.blocks[k][j][i] = Some(Block {
-                            block_type: BlockType::Dirt,
-                            exposure: BlockExposure::External,
-                        });
-                    }
+                    self.light[z][y][x] = Light::new(sky, emission);
                 }
             }
         }
+    }
 
-        chunk
+    // Which faces of the chunk can see each other, for cave culling.
+    // Only chunks with some opaque blocks, but not all, need the flood
+    // fill.
+    fn visibility(&mut self) -> ChunkVisibility {
+        let (opaque, solid, blocks) = (self.opaque, self.is_solid(), &self.blocks);
+        *self.visibility.get_or_insert_with(|| {
+            if opaque == 0 {
+                ChunkVisibility::OPEN
+            } else if solid {
+                ChunkVisibility::default()
+            } else {
+                ChunkVisibility::compute(|x, y, z| {
+                    blocks[z][y][x].is_none_or(|b| !b.block_type.is_opaque())
+                })
+            }
+        })
     }
 
-    fn update_exposure(&mut self) {
-        //NOTE: should only be called when required, not every tick (if can be avoided)
-        todo!()
+    pub fn gen_empty_chunk(origin: Point3<i32>) -> Self {
+        Self {
+            origin,
+            blocks: [[[None; CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_WIDTH],
+            light: [[[Light::default(); CHUNK_WIDTH]; CHUNK_WIDTH]; CHUNK_WIDTH],
+            non_air: 0,
+            opaque: 0,
+            emitting: 0,
+            visibility: None,
+        }
     }
 
-    pub fn cast_ray(&self, ray: Ray) -> RayResult {
-        let iter_dist = ray.max_dist / ray.n_tests as f32;
-        let iter_ray = ray.dir.normalize() * iter_dist;
-        let mut test_pos_f32 = ray.pos.clone();
-        for _ in 0..ray.n_tests {
-            let test_pos = point_to_world(test_pos_f32);
-            if let Ok(test_pos_block) = self.world_to_local(test_pos) {
-                if let Some(_) = self.get(test_pos_block) {
-                    let result = RayResult::Block {
-                        loc: test_pos,
-                        face: get_colliding_face(ray, test_pos_f32, test_pos).unwrap(),
-                        dist: test_pos_f32.to_vec().magnitude(),
-                    };
+    pub fn gen_default_chunk(origin: Point3<i32>) -> Self {
+        debug!("Generating new chunk at ({:?}", origin);
+        // Solid stone up to here, with dirt scattered on top
+        let solid_fill_height = -5;
 
-                    debug!("{:?}", result);
+        let mut chunk = Chunk::gen_empty_chunk(origin);
 
-                    return result;
+        for i in 0..CHUNK_WIDTH {
+            for j in 0..CHUNK_WIDTH {
+                for k in 0..CHUNK_WIDTH {
+                    let z = origin.z + k as i32;
+                    let block_type = if z < solid_fill_height {
+                        BlockType::Stone
+                    } else if z < solid_fill_height + 3 {
+                        // now do some random scattering of blocks on the next row up.
+                        // The first row sits on stone, which may be in the chunk below.
+                        let on_ground = z == solid_fill_height
+                            || (k > 0 && chunk.get(Point3::new(i, j, k - 1)).is_some());
+                        if !(on_ground && rand::random_ratio(4, 10)) {
+                            continue;
+                        }
+                        BlockType::Dirt
+                    } else {
+                        break;
+                    };
+                    chunk.set(Point3::new(i, j, k), Some(Block::new(block_type)));
                 }
             }
-
-            test_pos_f32 += iter_ray;
         }
-        RayResult::None
+
+        chunk
     }
 
     pub fn mutate_block<F>(&mut self, block_loc: Point3<i32>, f: F)
@@ -202,23 +319,21 @@ impl Chunk {
     {
         if let Ok(local_pos) = self.world_to_local(block_loc) {
             let mut block = self.get(local_pos);
-            f(&mut block)
+            f(&mut block);
+            self.set(local_pos, block);
         }
     }
 
     fn world_to_local(&self, pos: Point3<i32>) -> Result<Point3<usize>, ()> {
-        let point = Point3::new(
-            pos.x - self.origin.x,
-            pos.y - self.origin.y,
-            pos.z - BOTTOM_DEPTH,
-        );
+        let point = pos - self.origin.to_vec();
+        let width = CHUNK_WIDTH as i32;
 
         if point.x < 0
-            || point.x >= CHUNK_WIDTH as i32
+            || point.x >= width
             || point.y < 0
-            || point.y >= CHUNK_WIDTH as i32
+            || point.y >= width
             || point.z < 0
-            || point.z >= CHUNK_HEIGHT as i32
+            || point.z >= width
         {
             Err(())
         } else {
@@ -229,10 +344,10 @@ impl Chunk {
     pub fn set_block(&mut self, loc: Point3<i32>, block: Block) -> Result<(), ()> {
         if let Ok(local_pos) = self.world_to_local(loc) {
             // Can only place in an empty location
-            if let Some(_) = self.get(local_pos) {
+            if self.get(local_pos).is_some() {
                 Err(())
             } else {
-                self.blocks[local_pos.z][local_pos.y][local_pos.x] = Some(block);
+                self.set(local_pos, Some(block));
                 Ok(())
             }
         } else {
@@ -244,7 +359,7 @@ impl Chunk {
         if let Ok(local_pos) = self.world_to_local(loc) {
             // Can only place in an empty location
             if let Some(block) = self.get(local_pos) {
-                self.blocks[local_pos.z][local_pos.y][local_pos.x] = None;
+                self.set(local_pos, None);
                 return Ok(block);
             }
         }
@@ -257,73 +372,278 @@ pub struct ChunkManagerConfig {
     render_dist: u32,
 }
 
-impl Default for ChunkManagerConfig {
-    fn default() -> Self {
+impl ChunkManagerConfig {
+    // Both in chunks
+    pub fn new(gen_dist: u32, render_dist: u32) -> Self {
         Self {
-            gen_dist: 2,
-            render_dist: 2,
+            gen_dist,
+            render_dist,
         }
     }
+
+    pub fn render_dist(&self) -> u32 {
+        self.render_dist
+    }
+}
+
+impl Default for ChunkManagerConfig {
+    fn default() -> Self {
+        Self::new(6, 6)
+    }
 }
 
 #[derive(Default)]
 pub struct ChunkManager {
-    pub chunks: HashMap<Point2<i32>, Chunk>,
-    render_keys: HashSet<Point2<i32>>,
+    pub chunks: HashMap<Point3<i32>, Chunk>,
+    // The highest opaque block in each column, out of all the chunks
+    // loaded in it, keyed by the chunks' origin in x and y. Anything
+    // above is in full sunlight.
+    heightmaps: HashMap<Point2<i32>, Heightmap>,
+    // Chunks to draw this frame
+    render_keys: HashSet<Point3<i32>>,
+    // Chunks whose mesh is out of date
+    dirty: HashSet<Point3<i32>>,
+    // The detail each chunk was last meshed with
+    lods: HashMap<Point3<i32>, Lod>,
     pub config: ChunkManagerConfig,
 }
 
 impl ChunkManager {
-    pub fn update(&mut self, camera: &camera::Camera, projection: &camera::Projection) {
-        // First, check if we need to gen any new chunks
-        let new_gen_chunks =
-            gen_chunk_origins_near_player(camera.position, self.config.gen_dist as i32)
-                .into_iter()
-                .filter(|x| !self.chunks.contains_key(x))
-                .collect::<Vec<_>>();
+    pub fn new(config: ChunkManagerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    // Gens any chunks near the player that aren't there yet
+    pub fn generate_around(&mut self, eye: Point3<f32>) {
+        let new_gen_chunks = gen_chunk_origins_near_player(eye, self.config.gen_dist as i32)
+            .into_iter()
+            .filter(|x| !self.chunks.contains_key(x))
+            .collect::<Vec<_>>();
 
-        // Gen any new chunks
         for new_origin in new_gen_chunks {
-            self.chunks
-                .insert(new_origin, Chunk::gen_default_chunk(new_origin));
+            self.insert_chunk(Chunk::gen_default_chunk(new_origin));
         }
+    }
 
-        // now update the renderable chunks
-        self.render_keys =
+    // Picks the chunks to draw from where the camera is looking
+    pub fn update_view(&mut self, camera: &camera::Camera, projection: &camera::Projection) {
+        let frustum = Frustum::from_camera(camera, projection);
+        let in_view: HashSet<_> =
             gen_chunk_origins_near_player(camera.position, self.config.render_dist as i32)
                 .into_iter()
                 .filter(|x| self.chunks.contains_key(x))
                 .filter(|x| {
-                    in_camera_view(camera, projection.fovy, self.chunks.get(x).unwrap().origin)
+                    let (min, max) = chunk_aabb(*x);
+                    frustum.intersects_aabb(min, max)
                 })
                 .collect();
+        self.render_keys = self.cull_caves(camera.position, in_view);
+        self.update_lods(camera.position);
     }
 
-    pub fn gen_instances(&self) -> Vec<model::RenderInstance> {
-        let mut instances = Vec::new();
-
-        for k in &self.render_keys {
-            if let Some(chunk) = self.chunks.get(k) {
-                instances.append(&mut chunk.gen_instances());
+    // Picks how detailed each chunk's mesh should be from how far away
+    // it is, and queues up a new mesh for any that changed
+    fn update_lods(&mut self, eye: Point3<f32>) {
+        let centre_offset = Vector3::from_value(CHUNK_WIDTH as f32 / 2.0);
+        for origin in self.chunks.keys() {
+            let centre = origin.cast::<f32>().unwrap() + centre_offset;
+            let distance = centre.distance(eye) / CHUNK_WIDTH as f32;
+
+            let current = self.lods.get(origin).copied();
+            let lod = lod::select_lod(current, distance);
+            if current != Some(lod) {
+                self.lods.insert(*origin, lod);
+                if current.is_some() {
+                    self.dirty.insert(*origin);
+                }
             }
         }
+    }
 
-        instances
+    pub fn lod(&self, origin: Point3<i32>) -> Lod {
+        self.lods.get(&origin).copied().unwrap_or(Lod::Full)
     }
 
-    pub fn cast_ray(&self, ray: Ray) -> RayResult {
-        //TODO: for now, this will only allow the play to
-        //cast rays inside their own chunk. What we really need
-        //is to do a ray cast at a chunk level, then iterate throut
-        //the results, closest to furthest, looking for a collision
-        let chunk_loc = block_to_chunk(ray.pos.cast::<i32>().unwrap());
-        if let Some(chunk) = self.chunks.get(&chunk_loc) {
-            chunk.cast_ray(ray)
+    // Of the chunks in view, only keeps the ones that can be seen from
+    // the camera's chunk through open blocks. Empty chunks are walked
+    // through, but have nothing to draw.
+    fn cull_caves(
+        &mut self,
+        eye: Point3<f32>,
+        in_view: HashSet<Point3<i32>>,
+    ) -> HashSet<Point3<i32>> {
+        let start = block_to_chunk(point_to_world(eye));
+        let visible = if !in_view.contains(&start) {
+            in_view
         } else {
-            RayResult::None
+            for origin in &in_view {
+                self.chunks.get_mut(origin).unwrap().visibility();
+            }
+
+            occlusion::visible_chunks(start, |origin| {
+                if !in_view.contains(&origin) {
+                    return None;
+                }
+                self.chunks[&origin].visibility
+            })
+        };
+
+        visible
+            .into_iter()
+            .filter(|origin| !self.chunks[origin].is_empty())
+            .collect()
+    }
+
+    // Adds a chunk to the world and lights it. The chunks around it
+    // need new meshes too, as faces along the border may now be hidden.
+    pub fn insert_chunk(&mut self, mut chunk: Chunk) {
+        let origin = chunk.origin;
+
+        // Columns the chunk raises cast a shadow down into the chunk
+        // below, if that's already lit
+        let heights = self
+            .heightmaps
+            .entry(Point2::new(origin.x, origin.y))
+            .or_insert(EMPTY_HEIGHTMAP);
+        let mut shaded = Vec::new();
+        for (y, row) in chunk.column_tops().iter().enumerate() {
+            for (x, top) in row.iter().enumerate() {
+                let height = &mut heights[y][x];
+                if let Some(top) = *top {
+                    if height.is_none_or(|height| top > height) {
+                        *height = Some(top);
+                        shaded.push(origin + Vector3::new(x as i32, y as i32, -1));
+                    }
+                }
+            }
+        }
+
+        chunk.init_light(heights);
+        self.chunks.insert(origin, chunk);
+        lighting::light_chunk(self, origin);
+        for loc in shaded {
+            lighting::update_block(self, loc);
+        }
+
+        self.dirty.insert(origin);
+        for face in BlockFace::ALL {
+            let neighbour = occlusion::step(origin, face);
+            if self.chunks.contains_key(&neighbour) {
+                self.dirty.insert(neighbour);
+            }
+        }
+    }
+
+    pub fn render_keys(&self) -> impl Iterator<Item = &Point3<i32>> {
+        self.render_keys.iter()
+    }
+
+    // Hands over the chunks that need their meshes rebuilt
+    pub fn take_dirty(&mut self) -> Vec<Point3<i32>> {
+        self.dirty.drain().collect()
+    }
+
+    // Marks the chunk containing a block as needing a new mesh, along
+    // with any chunk the block touches, even just at a corner, as its
+    // faces may be lit or shaded by it
+    fn mark_dirty(&mut self, loc: Point3<i32>) {
+        let low = block_to_chunk(loc - Vector3::new(1, 1, 1));
+        let high = block_to_chunk(loc + Vector3::new(1, 1, 1));
+        let width = CHUNK_WIDTH;
+        for x in (low.x..=high.x).step_by(width) {
+            for y in (low.y..=high.y).step_by(width) {
+                for z in (low.z..=high.z).step_by(width) {
+                    let origin = Point3::new(x, y, z);
+                    if self.chunks.contains_key(&origin) {
+                        self.dirty.insert(origin);
+                    }
+                }
+            }
         }
     }
 
+    // Whether none of a chunk's faces could ever be seen, as it and
+    // everything around it is solid. Faces against unloaded chunks
+    // aren't drawn either.
+    pub fn chunk_hidden(&self, origin: Point3<i32>) -> bool {
+        let solid = |origin: Point3<i32>| self.chunks.get(&origin).is_none_or(Chunk::is_solid);
+
+        solid(origin)
+            && BlockFace::ALL
+                .into_iter()
+                .all(|face| solid(occlusion::step(origin, face)))
+    }
+
+    // The highest opaque block in the column a location is in, out of
+    // the loaded chunks. None if there's nothing opaque, or nothing
+    // loaded.
+    pub fn column_height(&self, loc: Point3<i32>) -> Option<i32> {
+        let origin = block_to_chunk(loc);
+        let local = (loc - origin).cast::<usize>().unwrap();
+        self.heightmaps.get(&Point2::new(origin.x, origin.y))?[local.y][local.x]
+    }
+
+    // Whether a location has nothing above it to block the sky. Only
+    // known for columns with some chunks loaded.
+    pub fn open_to_sky(&self, loc: Point3<i32>) -> bool {
+        let origin = block_to_chunk(loc);
+        self.heightmaps
+            .contains_key(&Point2::new(origin.x, origin.y))
+            && self.column_height(loc).is_none_or(|top| loc.z > top)
+    }
+
+    // The highest opaque block in any column of a chunk
+    pub fn highest_opaque(&self, origin: Point3<i32>) -> Option<i32> {
+        self.heightmaps
+            .get(&Point2::new(origin.x, origin.y))?
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .max()
+    }
+
+    // Keeps the heightmap right after the block at `loc` changes. When
+    // the top of a column goes, the next one down is found by looking
+    // down through the loaded chunks.
+    fn update_height(&mut self, loc: Point3<i32>) {
+        let top = self.column_height(loc);
+        let opaque = self
+            .get_block(loc)
+            .is_some_and(|block| block.block_type().is_opaque());
+
+        let new_top = if opaque {
+            Some(top.map_or(loc.z, |top| top.max(loc.z)))
+        } else if top == Some(loc.z) {
+            let mut below = loc - Vector3::unit_z();
+            while self.is_loaded(below)
+                && !self
+                    .get_block(below)
+                    .is_some_and(|block| block.block_type().is_opaque())
+            {
+                below.z -= 1;
+            }
+            self.is_loaded(below).then_some(below.z)
+        } else {
+            return;
+        };
+
+        let origin = block_to_chunk(loc);
+        let local = (loc - origin).cast::<usize>().unwrap();
+        self.heightmaps
+            .entry(Point2::new(origin.x, origin.y))
+            .or_insert(EMPTY_HEIGHTMAP)[local.y][local.x] = new_top;
+    }
+
+    // Follows the ray through every chunk it crosses, so blocks in the
+    // chunks above and below the eye are in reach too
+    pub fn cast_ray(&self, ray: Ray) -> RayResult {
+        march_ray(ray, |loc| self.get_block(loc).is_some())
+    }
+
     pub fn mutate_block<F>(&mut self, block_loc: Point3<i32>, f: F)
     where
         F: FnOnce(&mut Option<Block>),
@@ -332,14 +652,58 @@ impl ChunkManager {
 
         let chunk_loc = block_to_chunk(block_loc);
         if let Some(chunk) = self.chunks.get_mut(&chunk_loc) {
-            chunk.mutate_block(block_loc, f)
+            chunk.mutate_block(block_loc, f);
+            self.update_height(block_loc);
+            lighting::update_block(self, block_loc);
+            self.mark_dirty(block_loc);
+        }
+    }
+
+    pub fn get_block(&self, loc: Point3<i32>) -> Option<Block> {
+        let chunk = self.chunks.get(&block_to_chunk(loc))?;
+        let local_pos = chunk.world_to_local(loc).ok()?;
+        chunk.get(local_pos)
+    }
+
+    pub fn is_solid(&self, loc: Point3<i32>) -> bool {
+        self.get_block(loc).is_some_and(|block| block.is_solid())
+    }
+
+    pub fn is_loaded(&self, loc: Point3<i32>) -> bool {
+        self.chunks.contains_key(&block_to_chunk(loc))
+    }
+
+    // None if the location isn't in a loaded chunk
+    pub fn get_light(&self, loc: Point3<i32>) -> Option<Light> {
+        let chunk = self.chunks.get(&block_to_chunk(loc))?;
+        let local_pos = chunk.world_to_local(loc).ok()?;
+        Some(chunk.get_light(local_pos))
+    }
+
+    pub fn set_light(&mut self, loc: Point3<i32>, light: Light) {
+        let chunk_loc = block_to_chunk(loc);
+        let Some(chunk) = self.chunks.get_mut(&chunk_loc) else {
+            return;
+        };
+        let Ok(local_pos) = chunk.world_to_local(loc) else {
+            return;
+        };
+
+        let current = chunk.light_mut(local_pos);
+        if *current != light {
+            *current = light;
+            self.mark_dirty(loc);
         }
     }
 
     pub fn set_block(&mut self, loc: Point3<i32>, block: Block) -> Result<(), ()> {
         let chunk_loc = block_to_chunk(loc);
         if let Some(chunk) = self.chunks.get_mut(&chunk_loc) {
-            chunk.set_block(loc, block)
+            chunk.set_block(loc, block)?;
+            self.update_height(loc);
+            lighting::update_block(self, loc);
+            self.mark_dirty(loc);
+            Ok(())
         } else {
             Err(())
         }
@@ -348,14 +712,18 @@ impl ChunkManager {
     pub fn remove_block(&mut self, loc: Point3<i32>) -> Result<Block, ()> {
         let chunk_loc = block_to_chunk(loc);
         if let Some(chunk) = self.chunks.get_mut(&chunk_loc) {
-            chunk.remove_block(loc)
+            let block = chunk.remove_block(loc)?;
+            self.update_height(loc);
+            lighting::update_block(self, loc);
+            self.mark_dirty(loc);
+            Ok(block)
         } else {
             Err(())
         }
     }
 }
 
-fn point_to_world(point: Point3<f32>) -> Point3<i32> {
+pub fn point_to_world(point: Point3<f32>) -> Point3<i32> {
     Point3::new(
         point.x.floor() as i32,
         point.y.floor() as i32,
@@ -363,45 +731,44 @@ fn point_to_world(point: Point3<f32>) -> Point3<i32> {
     )
 }
 
-fn block_to_chunk(block_pos: Point3<i32>) -> Point2<i32> {
-    Point2 {
-        x: block_pos.x / CHUNK_WIDTH as i32,
-        y: block_pos.y / CHUNK_WIDTH as i32,
+// Finds the origin of the chunk containing a block, which is
+// how chunks are keyed in the ChunkManager
+pub fn block_to_chunk(block_pos: Point3<i32>) -> Point3<i32> {
+    let width = CHUNK_WIDTH as i32;
+    Point3 {
+        x: block_pos.x.div_euclid(width) * width,
+        y: block_pos.y.div_euclid(width) * width,
+        z: block_pos.z.div_euclid(width) * width,
     }
 }
 
 fn gen_chunk_origins_near_player(
     player_pos: cgmath::Point3<f32>,
     dist: i32,
-) -> HashSet<Point2<i32>> {
-    // Draws a circle around the player, and returns all the chunk origins in this
-    // circle. This can be used to calculate which chunks should be rendered,
+) -> HashSet<Point3<i32>> {
+    // Draws a sphere around the player, and returns all the chunk origins in
+    // it. This can be used to calculate which chunks should be rendered,
     // or which new chunks should be generated.
 
     let mut origins = HashSet::new();
 
-    // First, gen all the candidates in the possible square
-    let min_x = player_pos.x as i32 - (CHUNK_WIDTH as i32 * dist);
-    let max_x = player_pos.x as i32 + (CHUNK_WIDTH as i32 * dist);
-    let start_x = lowest_multiple_above(CHUNK_WIDTH as i32, min_x);
-    let min_y = player_pos.y as i32 - (CHUNK_WIDTH as i32 * dist);
-    let max_y = player_pos.y as i32 + (CHUNK_WIDTH as i32 * dist);
-    let start_y = lowest_multiple_above(CHUNK_WIDTH as i32, min_y);
+    // First, gen all the candidates in the possible cube
+    let reach = CHUNK_WIDTH as i32 * dist;
+    let min = player_pos.map(|v| lowest_multiple_above(CHUNK_WIDTH as i32, v as i32 - reach));
+    let max = player_pos.map(|v| v as i32 + reach);
 
     let check_dist = CHUNK_WIDTH as f32 * dist as f32;
-    let center_offset = CHUNK_WIDTH as f32 / 2.0;
-
-    for origin_x in (start_x..max_x).step_by(CHUNK_WIDTH) {
-        for origin_y in (start_y..max_y).step_by(CHUNK_WIDTH) {
-            // check for the distances
-            if Vector2::new(
-                player_pos.x - origin_x as f32 - center_offset,
-                player_pos.y - origin_y as f32 - center_offset,
-            )
-            .magnitude()
-                < check_dist
-            {
-                origins.insert(Point2::new(origin_x, origin_y));
+    let center_offset = Vector3::from_value(CHUNK_WIDTH as f32 / 2.0);
+
+    for origin_x in (min.x..max.x).step_by(CHUNK_WIDTH) {
+        for origin_y in (min.y..max.y).step_by(CHUNK_WIDTH) {
+            for origin_z in (min.z..max.z).step_by(CHUNK_WIDTH) {
+                // check for the distances
+                let origin = Point3::new(origin_x, origin_y, origin_z);
+                let centre = origin.cast::<f32>().unwrap() + center_offset;
+                if centre.distance(player_pos) < check_dist {
+                    origins.insert(origin);
+                }
             }
         }
     }
@@ -425,66 +792,217 @@ fn lowest_multiple_above(x: i32, n: i32) -> i32 {
     }
 }
 
-fn in_camera_view(
-    camera: &camera::Camera,
-    fov: cgmath::Rad<f32>,
-    chunk_origin: Point2<i32>,
-) -> bool {
-    if camera.position.x >= chunk_origin.x as f32
-        && camera.position.x <= chunk_origin.x as f32 + CHUNK_WIDTH as f32
-        && camera.position.y >= chunk_origin.y as f32
-        && camera.position.y <= chunk_origin.y as f32 + CHUNK_WIDTH as f32
-    {
-        return true;
-    }
-    let corners = vec![
-        Vector2::new(chunk_origin.x, chunk_origin.y),
-        Vector2::new(chunk_origin.x, chunk_origin.y + CHUNK_WIDTH as i32),
-        Vector2::new(chunk_origin.x + CHUNK_WIDTH as i32, chunk_origin.y),
-        Vector2::new(
-            chunk_origin.x + CHUNK_WIDTH as i32,
-            chunk_origin.y + CHUNK_WIDTH as i32,
-        ),
-    ];
+fn chunk_aabb(chunk_origin: Point3<i32>) -> (Point3<f32>, Point3<f32>) {
+    let min = chunk_origin.cast::<f32>().unwrap();
+    (min, min + Vector3::from_value(CHUNK_WIDTH as f32))
+}
 
-    let camera_pos = Vector2::new(camera.position.x as f32, camera.position.y as f32);
-    let forward = Vector2::new(camera.yaw.cos(), camera.yaw.sin());
+// A stone floor at z = 0 across the chunks at each `(x, y)` origin,
+// with empty chunks above and below it, for other modules' tests
+#[cfg(test)]
+pub fn flat_world(origins: &[(i32, i32)]) -> ChunkManager {
+    let mut world = ChunkManager::default();
+
+    for &(ox, oy) in origins {
+        let mut chunk = Chunk::gen_empty_chunk(Point3::new(ox, oy, 0));
+        for x in ox..ox + CHUNK_WIDTH as i32 {
+            for y in oy..oy + CHUNK_WIDTH as i32 {
+                chunk
+                    .set_block(Point3::new(x, y, 0), Block::new(BlockType::Stone))
+                    .unwrap();
+            }
+        }
+        world.insert_chunk(chunk);
 
-    for c in corners {
-        let c = c.cast::<f32>().unwrap();
-        let view = c - camera_pos;
-        let angle = view.angle(forward);
-        // have to transform the vertex
-        if angle.0.abs() < fov.0 {
-            return true;
+        for oz in [-16, 16] {
+            world.insert_chunk(Chunk::gen_empty_chunk(Point3::new(ox, oy, oz)));
         }
     }
 
-    false
+    world
 }
 
 #[cfg(test)]
 mod tests {
     use cgmath::{Deg, Point3, Rad, Vector3};
 
-    use crate::camera::Camera;
+    use crate::camera::{Camera, Projection};
 
     use super::*;
 
     #[test]
-    fn test_in_camera_view() {
-        let camera = Camera::new([0.0, 0.0, 0.0], Rad(0.0), Rad(0.0));
-        let fov = Deg(45.0);
+    fn test_render_keys_follow_camera() {
+        let projection = Projection::new(1600, 900, Deg(45.0), 0.1, 100.0);
+        let mut chunk_manager = ChunkManager {
+            config: ChunkManagerConfig {
+                gen_dist: 3,
+                render_dist: 3,
+            },
+            ..Default::default()
+        };
+        let visible = |chunk_manager: &ChunkManager| {
+            chunk_manager
+                .render_keys()
+                .map(|key| Point2::new(key.x, key.y))
+                .collect::<HashSet<_>>()
+        };
 
-        assert_eq!(in_camera_view(&camera, fov.into(), Point2::new(0, 0)), true);
-        assert_eq!(
-            in_camera_view(&camera, fov.into(), Point2::new(-50, 50)),
-            false
-        );
+        // Looking along +x, the ground ahead. The ground right underneath
+        // is out of view, and the empty sky above it has nothing to draw.
+        let camera = Camera::new([8.0, 8.0, 10.0], Rad(0.0), Rad(0.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        let ahead = visible(&chunk_manager);
+        assert!(!ahead.contains(&Point2::new(0, 0)));
+        assert!(ahead.contains(&Point2::new(32, 0)));
+        assert!(!ahead.contains(&Point2::new(-32, 0)));
+        assert!(!ahead.contains(&Point2::new(0, 32)));
+        assert!(chunk_manager.render_keys().all(|key| key.z < 0));
+
+        // Looking straight down, the column underneath
+        let camera = Camera::new([8.0, 8.0, 10.0], Rad(0.0), Deg(-89.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        assert!(visible(&chunk_manager).contains(&Point2::new(0, 0)));
+
+        // High up in the sky looking up, there's nothing to draw
+        let camera = Camera::new([8.0, 8.0, 200.0], Rad(0.0), Deg(89.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        assert!(visible(&chunk_manager).is_empty());
+    }
+
+    #[test]
+    fn test_caves_behind_rock_are_culled() {
+        let projection = Projection::new(1600, 900, Deg(45.0), 0.1, 100.0);
+        let mut chunk_manager = ChunkManager {
+            config: ChunkManagerConfig {
+                gen_dist: 3,
+                render_dist: 3,
+            },
+            ..Default::default()
+        };
+
+        // Deep underground looking along +x, everything around is stone
+        let camera = Camera::new([8.5, 8.5, -59.5], Rad(0.0), Rad(0.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        let visible = chunk_manager.render_keys().copied().collect::<HashSet<_>>();
+        assert!(visible.contains(&Point3::new(0, 0, -64)));
+        // The rock face next to the camera's chunk
+        assert!(visible.contains(&Point3::new(16, 0, -64)));
+        assert!(!visible.contains(&Point3::new(32, 0, -64)));
+
+        // Dig a tunnel through to the next chunk along
+        for x in 8..48 {
+            let _ = chunk_manager.remove_block(Point3::new(x, 8, -60));
+        }
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        assert!(chunk_manager
+            .render_keys()
+            .any(|key| *key == Point3::new(32, 0, -64)));
+    }
+
+    #[test]
+    fn test_chunk_counts() {
+        let mut chunk = Chunk::gen_empty_chunk(Point3::new(0, 0, 16));
+        assert!(chunk.is_empty());
+
+        let loc = Point3::new(3, 3, 20);
+        chunk.set_block(loc, Block::new(BlockType::Lamp)).unwrap();
+        assert!(!chunk.is_empty());
+        assert_eq!(chunk.iter_blocks().collect::<Vec<_>>().len(), 1);
+        assert_eq!(chunk.iter_blocks().next().unwrap().0, loc);
+
+        // Swapping a block in place keeps the count right
+        chunk.mutate_block(loc, |block| *block = Some(Block::new(BlockType::Stone)));
+        assert_eq!(chunk.non_air, 1);
+        assert_eq!(chunk.emitting, 0);
+        assert_eq!(chunk.column_tops()[3][3], Some(20));
+        chunk.remove_block(loc).unwrap();
+        assert!(chunk.is_empty());
+
+        let chunk = Chunk::gen_default_chunk(Point3::new(0, 0, -32));
+        assert!(chunk.is_solid());
+        assert!(!chunk.is_lit());
+        assert!(Chunk::gen_default_chunk(Point3::new(0, 0, 0)).is_empty());
+        let surface = Chunk::gen_default_chunk(Point3::new(0, 0, -16));
+        assert!(surface
+            .column_tops()
+            .iter()
+            .flatten()
+            .all(|top| top.is_some_and(|top| (-6..-2).contains(&top))));
+    }
+
+    #[test]
+    fn test_dirty_chunks() {
+        let mut chunk_manager = ChunkManager::default();
+        for x in [0, 16] {
+            for z in [-64, -48, -16] {
+                chunk_manager.insert_chunk(Chunk::gen_default_chunk(Point3::new(x, 0, z)));
+            }
+        }
+        chunk_manager.take_dirty();
+        // Deep in the rock, so the light doesn't change
+        let to_dirt = |block: &mut Option<Block>| *block = Some(Block::new(BlockType::Dirt));
+
+        // In the middle of a chunk, only that one needs remeshing
+        let middle = Point3::new(8, 8, -56);
+        chunk_manager.mutate_block(middle, to_dirt);
+        let dirty = chunk_manager.take_dirty();
+        assert_eq!(dirty, vec![block_to_chunk(middle)]);
+
+        // On a corner it touches the chunks above and across the border
+        let corner = Point3::new(15, 8, -49);
+        chunk_manager.mutate_block(corner, to_dirt);
+        let dirty = chunk_manager
+            .take_dirty()
+            .into_iter()
+            .collect::<HashSet<_>>();
         assert_eq!(
-            in_camera_view(&camera, fov.into(), Point2::new(-50, -50)),
-            false
+            dirty,
+            HashSet::from([
+                Point3::new(0, 0, -64),
+                Point3::new(0, 0, -48),
+                Point3::new(16, 0, -64),
+                Point3::new(16, 0, -48),
+            ])
         );
+
+        // Buried chunks have nothing to mesh, the surface does
+        assert!(chunk_manager.chunk_hidden(Point3::new(0, 0, -64)));
+        assert!(!chunk_manager.chunk_hidden(Point3::new(0, 0, -16)));
+    }
+
+    #[test]
+    fn test_lod_changes_remesh() {
+        let projection = Projection::new(1600, 900, Deg(45.0), 0.1, 100.0);
+        let mut chunk_manager = ChunkManager::default();
+        let camera = Camera::new([8.0, 8.0, 8.0], Rad(0.0), Rad(0.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        chunk_manager.take_dirty();
+
+        assert_eq!(chunk_manager.lod(Point3::new(0, 0, 0)), Lod::Full);
+        assert_eq!(chunk_manager.lod(Point3::new(64, 0, 0)), Lod::Half);
+        assert_eq!(chunk_manager.lod(Point3::new(80, 0, 0)), Lod::Quarter);
+        // Distance counts up and down too
+        assert_eq!(chunk_manager.lod(Point3::new(0, 0, -64)), Lod::Half);
+
+        // Backing off, the chunk that was 4 away is now 5.5 and gets
+        // coarser, while stepping a little way back changes nothing
+        let camera = Camera::new([-16.0, 8.0, 8.0], Rad(0.0), Rad(0.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        let far = Point3::new(64, 0, 0);
+        assert!(chunk_manager.take_dirty().contains(&far));
+        assert_eq!(chunk_manager.lod(far), Lod::Quarter);
+
+        let camera = Camera::new([-12.0, 8.0, 8.0], Rad(0.0), Rad(0.0));
+        chunk_manager.generate_around(camera.position);
+        chunk_manager.update_view(&camera, &projection);
+        assert!(!chunk_manager.take_dirty().contains(&far));
     }
 
     #[test]
@@ -506,95 +1024,109 @@ mod tests {
 
     #[test]
     fn test_gen_origins_near_player() {
-        let cases = vec![
-            (
-                Point3::new(CHUNK_WIDTH as f32 / 2.0, CHUNK_WIDTH as f32 / 2.0, 0.0),
-                1,
-                HashSet::from_iter(vec![Point2::new(0, 0)]),
-            ),
-            (
-                Point3::new(CHUNK_WIDTH as f32 / 2.0, CHUNK_WIDTH as f32 / 2.0, 0.0),
-                2,
-                HashSet::from_iter(vec![
-                    Point2::new(0, 0),
-                    Point2::new(-16, 0),
-                    Point2::new(16, 0),
-                    Point2::new(0, -16),
-                    Point2::new(0, 16),
-                    Point2::new(-16, -16),
-                    Point2::new(-16, 16),
-                    Point2::new(16, -16),
-                    Point2::new(16, 16),
-                ]),
-            ),
-        ];
+        let centre = Point3::from_value(CHUNK_WIDTH as f32 / 2.0);
+        assert_eq!(
+            gen_chunk_origins_near_player(centre, 1),
+            HashSet::from([Point3::new(0, 0, 0)])
+        );
 
-        for (player_pos, dist, res) in cases {
-            assert_eq!(gen_chunk_origins_near_player(player_pos, dist), res);
+        // Every chunk touching the middle one, corners included
+        let mut around = HashSet::new();
+        for x in [-16, 0, 16] {
+            for y in [-16, 0, 16] {
+                for z in [-16, 0, 16] {
+                    around.insert(Point3::new(x, y, z));
+                }
+            }
         }
+        assert_eq!(gen_chunk_origins_near_player(centre, 2), around);
     }
 
     #[test]
     fn test_chunk_raycasting() {
         let camera = Camera::new(Point3::new(1.0, 1.5, 1.5), Rad(0.0), Rad(0.0));
-        let chunk_origin = Point2::new(0, 0);
-        let mut chunk = Chunk::gen_empty_chunk(chunk_origin);
+        let mut world = ChunkManager::default();
+        world.insert_chunk(Chunk::gen_empty_chunk(Point3::new(0, 0, 0)));
 
         // now, the chunk is empty, so casting a ray now
         // should return a None
-        assert_eq!(chunk.cast_ray(Ray::from(&camera)), RayResult::None);
+        assert_eq!(world.cast_ray(Ray::from(&camera)), RayResult::None);
 
         // insert a block that the camera SHOULD be able to see
         let block = Block::new(BlockType::Dirt);
         let block_pos = Point3::new(2, 1, 1);
-        let _ = chunk.set_block(block_pos, block);
-        if let RayResult::Block { loc, .. } = chunk.cast_ray(Ray::from(&camera)) {
+        let _ = world.set_block(block_pos, block);
+        if let RayResult::Block { loc, .. } = world.cast_ray(Ray::from(&camera)) {
             assert_eq!(loc, block_pos);
         } else {
-            assert!(false);
+            panic!("expected the ray to hit a block");
         }
 
         // now insert a block that camera ray SHOULDN'T hit
-        let _ = chunk.remove_block(block_pos);
+        let _ = world.remove_block(block_pos);
         let block_pos = Point3::new(1, 2, 1);
-        let _ = chunk.set_block(block_pos, block);
-        assert_eq!(chunk.cast_ray(Ray::from(&camera)), RayResult::None);
+        let _ = world.set_block(block_pos, block);
+        assert_eq!(world.cast_ray(Ray::from(&camera)), RayResult::None);
+    }
+
+    #[test]
+    fn test_cast_ray_across_chunks() {
+        let mut world = ChunkManager::default();
+        let mut ground = Chunk::gen_empty_chunk(Point3::new(0, 0, -16));
+        let target = Point3::new(8, 8, -4);
+        ground
+            .set_block(target, Block::new(BlockType::Stone))
+            .unwrap();
+        world.insert_chunk(ground);
+        world.insert_chunk(Chunk::gen_empty_chunk(Point3::new(0, 0, 0)));
+
+        // Looking down from the chunk above
+        let camera = Camera::new((8.5, 8.5, 0.5), Deg(0.0), Deg(-90.0));
+        match world.cast_ray(Ray::from(&camera)) {
+            RayResult::Block { loc, face, .. } => {
+                assert_eq!(loc, target);
+                assert_eq!(face, BlockFace::ZPos);
+            }
+            other => panic!("expected the ray to hit the ground, got {other:?}"),
+        }
     }
 
     #[test]
     fn test_chunk_insert_remove() {
-        let mut chunk = Chunk::gen_empty_chunk(Point2::new(0, 0));
+        let mut chunk = Chunk::gen_empty_chunk(Point3::new(0, 0, 0));
 
         // check the chunk is currently empty
         for x in 0..CHUNK_WIDTH {
             for y in 0..CHUNK_WIDTH {
-                for z in 0..CHUNK_HEIGHT {
-                    if let Ok(_) = chunk.remove_block(Point3::new(x as i32, y as i32, z as i32)) {
-                        assert!(false);
-                    }
+                for z in 0..CHUNK_WIDTH {
+                    assert!(chunk
+                        .remove_block(Point3::new(x as i32, y as i32, z as i32))
+                        .is_err());
                 }
             }
         }
 
         // test insert
         let pos = Point3::new(1, 2, 3);
-        if let Err(_) = chunk.set_block(pos, Block::new(BlockType::Dirt)) {
-            assert!(false, "set block failed");
-        }
+        assert!(
+            chunk.set_block(pos, Block::new(BlockType::Dirt)).is_ok(),
+            "set block failed"
+        );
 
         // test remove
-        if let Err(_) = chunk.remove_block(pos) {
-            assert!(false, "remove block failed");
-        }
+        assert!(chunk.remove_block(pos).is_ok(), "remove block failed");
     }
 
     #[test]
     fn test_world_to_local() {
-        let chunk = Chunk::gen_empty_chunk(Point2::new(0, 0));
+        let chunk = Chunk::gen_empty_chunk(Point3::new(16, 0, -16));
 
-        let test_pos = Point3::new(1, 2, 3);
+        let test_pos = Point3::new(17, 2, -13);
         let chunk_coords = chunk.world_to_local(test_pos).unwrap();
-        assert_eq!(chunk_coords, Point3::new(1, 2, (3 - BOTTOM_DEPTH) as usize));
+        assert_eq!(chunk_coords, Point3::new(1, 2, 3));
+        // Anything up or down out of the cube belongs to another chunk
+        assert!(chunk.world_to_local(Point3::new(17, 2, 0)).is_err());
+        assert!(chunk.world_to_local(Point3::new(17, 2, -17)).is_err());
     }
 
     #[test]
@@ -625,11 +1157,30 @@ mod tests {
         // rather, it clips to the integer portion
     }
 
+    #[test]
+    fn test_block_to_chunk() {
+        let cases = [
+            (Point3::new(0, 0, 0), Point3::new(0, 0, 0)),
+            (Point3::new(15, 16, 0), Point3::new(0, 16, 0)),
+            (Point3::new(33, 5, -3), Point3::new(32, 0, -16)),
+            (Point3::new(-1, -16, 0), Point3::new(-16, -16, 0)),
+            (Point3::new(-17, 0, 300), Point3::new(-32, 0, 288)),
+        ];
+
+        for (block, chunk) in cases {
+            assert_eq!(block_to_chunk(block), chunk);
+        }
+    }
+
     #[test]
     fn test_point_to_world() {
-        let cases = vec![
+        let cases = [
             (Point3::new(1.01, 1.9, 1.5), Point3::new(1, 1, 1)),
             (Point3::new(-1.01, -1.9, -1.5), Point3::new(-2, -2, -2)),
         ];
+
+        for (point, res) in cases {
+            assert_eq!(point_to_world(point), res);
+        }
     }
 }
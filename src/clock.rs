@@ -0,0 +1,258 @@
+use std::{fs, path::Path, time::Duration};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::sun::Sun;
+
+// A full day-night cycle takes 20 minutes by default
+pub const DEFAULT_DAY_LENGTH: f32 = 1200.0; // s
+const SAVE_FILE: &str = "clock.txt";
+
+// Tilts the sun's path off the x axis so opposite faces don't shade the same
+const SUN_TILT: f32 = 0.3;
+// Skylight left over in the middle of the night
+const NIGHT_SKY_LIGHT: f32 = 0.25;
+
+const SUN_COLOUR: [f32; 3] = [0.5, 0.48, 0.45];
+const MOON_COLOUR: [f32; 3] = [0.15, 0.17, 0.25];
+const DAY_AMBIENT: [f32; 3] = [0.5, 0.5, 0.55];
+const NIGHT_AMBIENT: [f32; 3] = [0.35, 0.38, 0.5];
+
+const DAY_ZENITH: [f32; 3] = [0.25, 0.5, 0.95];
+const DAY_HORIZON: [f32; 3] = [0.65, 0.8, 1.0];
+const NIGHT_ZENITH: [f32; 3] = [0.01, 0.01, 0.04];
+const NIGHT_HORIZON: [f32; 3] = [0.04, 0.05, 0.1];
+const SUNSET_HORIZON: [f32; 3] = [0.95, 0.5, 0.25];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyColours {
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+}
+
+// Time of day as a fraction of a day, where 0 is midnight, 0.25 is sunrise,
+// 0.5 is noon and 0.75 is sunset
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldClock {
+    time: f32,
+    pub day: u32,
+    pub day_length: f32, // s
+    pub frozen: bool,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self {
+            time: 0.3,
+            day: 0,
+            day_length: DEFAULT_DAY_LENGTH,
+            frozen: false,
+        }
+    }
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl WorldClock {
+    pub fn advance(&mut self, dt: Duration) {
+        if self.frozen {
+            return;
+        }
+
+        self.time += dt.as_secs_f32() / self.day_length;
+        while self.time >= 1.0 {
+            self.time -= 1.0;
+            self.day += 1;
+        }
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time.rem_euclid(1.0);
+    }
+
+    // Hours and minutes on a 24 hour clock
+    pub fn hours_minutes(&self) -> (u32, u32) {
+        let minutes = (self.time * 24.0 * 60.0) as u32;
+        (minutes / 60, minutes % 60)
+    }
+
+    // Points from the world towards the sun, which rises in +x and sets in -x
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.time - 0.25) * std::f32::consts::TAU;
+        Vector3::new(angle.cos(), SUN_TILT, angle.sin()).normalize()
+    }
+
+    // 0 at night up to 1 once the sun is properly up
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.1, 0.2, self.sun_direction().z)
+    }
+
+    // Scales the voxel sky light
+    pub fn sky_light(&self) -> f32 {
+        NIGHT_SKY_LIGHT + (1.0 - NIGHT_SKY_LIGHT) * self.daylight()
+    }
+
+    // Stars only come out once the sun is well below the horizon
+    pub fn star_visibility(&self) -> f32 {
+        1.0 - smoothstep(-0.25, 0.0, self.sun_direction().z)
+    }
+
+    pub fn sky_colours(&self) -> SkyColours {
+        let daylight = self.daylight();
+        // Strongest with the sun right on the horizon
+        let twilight = 1.0 - (self.sun_direction().z.abs() / 0.3).min(1.0);
+
+        SkyColours {
+            zenith: lerp(NIGHT_ZENITH, DAY_ZENITH, daylight),
+            horizon: lerp(
+                lerp(NIGHT_HORIZON, DAY_HORIZON, daylight),
+                SUNSET_HORIZON,
+                twilight * 0.7,
+            ),
+        }
+    }
+
+    // The sun lights the world by day and the moon by night
+    pub fn sun(&self) -> Sun {
+        let sun_direction = self.sun_direction();
+        let daylight = self.daylight();
+
+        let (direction, colour) = if sun_direction.z > 0.0 {
+            (sun_direction, SUN_COLOUR.map(|c| c * daylight))
+        } else {
+            (-sun_direction, MOON_COLOUR)
+        };
+
+        Sun {
+            direction,
+            colour,
+            ambient: lerp(NIGHT_AMBIENT, DAY_AMBIENT, daylight),
+            sky_light: self.sky_light(),
+        }
+    }
+
+    fn to_save(&self) -> String {
+        format!(
+            "time {}\nday {}\nday_length {}\nfrozen {}\n",
+            self.time, self.day, self.day_length, self.frozen
+        )
+    }
+
+    // Anything missing keeps its default, so older saves still load
+    fn from_save(contents: &str) -> Result<Self, ()> {
+        let mut clock = Self::default();
+
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line.trim().split_once(' ').ok_or(())?;
+            match key {
+                "time" => clock.set_time(value.parse().map_err(|_| ())?),
+                "day" => clock.day = value.parse().map_err(|_| ())?,
+                "day_length" => {
+                    clock.day_length = value.parse().map_err(|_| ())?;
+                    if clock.day_length <= 0.0 {
+                        return Err(());
+                    }
+                }
+                "frozen" => clock.frozen = value.parse().map_err(|_| ())?,
+                _ => log::warn!("Unknown clock save key {key}"),
+            }
+        }
+
+        Ok(clock)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), ()> {
+        fs::create_dir_all(dir).map_err(|_| ())?;
+        fs::write(dir.join(SAVE_FILE), self.to_save()).map_err(|_| ())
+    }
+
+    pub fn load(dir: &Path) -> Result<Self, ()> {
+        let contents = fs::read_to_string(dir.join(SAVE_FILE)).map_err(|_| ())?;
+        Self::from_save(&contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_wraps_into_next_day() {
+        let mut clock = WorldClock {
+            day_length: 100.0,
+            ..Default::default()
+        };
+        clock.set_time(0.9);
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(clock.day, 1);
+        assert!((clock.time - 0.1).abs() < 1e-4);
+
+        clock.frozen = true;
+        clock.advance(Duration::from_secs(20));
+        assert!((clock.time - 0.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_day_and_night() {
+        let mut clock = WorldClock::default();
+
+        clock.set_time(0.5);
+        assert_eq!(clock.hours_minutes(), (12, 0));
+        assert!(clock.sun_direction().z > 0.9);
+        assert_eq!(clock.daylight(), 1.0);
+        assert_eq!(clock.sky_light(), 1.0);
+        assert_eq!(clock.star_visibility(), 0.0);
+        let noon = clock.sky_colours();
+
+        clock.set_time(0.0);
+        assert!(clock.sun_direction().z < -0.9);
+        assert_eq!(clock.sky_light(), NIGHT_SKY_LIGHT);
+        assert_eq!(clock.star_visibility(), 1.0);
+        // The moon takes over, from above
+        assert!(clock.sun().direction.z > 0.9);
+        let midnight = clock.sky_colours();
+        assert!(midnight.zenith[2] < noon.zenith[2]);
+
+        // Sunset reddens the horizon
+        clock.set_time(0.75);
+        let sunset = clock.sky_colours();
+        assert!(sunset.horizon[0] > sunset.horizon[2]);
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let clock = WorldClock {
+            time: 0.625,
+            day: 12,
+            day_length: 600.0,
+            frozen: true,
+        };
+
+        let dir = std::env::temp_dir().join(format!("mcrs_clock_test_{}", std::process::id()));
+        clock.save(&dir).unwrap();
+        assert_eq!(WorldClock::load(&dir), Ok(clock));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            WorldClock::from_save("day 3\n"),
+            Ok(WorldClock {
+                day: 3,
+                ..Default::default()
+            })
+        );
+        assert_eq!(WorldClock::from_save("time dusk\n"), Err(()));
+        assert_eq!(WorldClock::from_save("day_length 0\n"), Err(()));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{inventory::GameMode, texture::MAX_ANISOTROPY};
+
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, NamedKey},
+};
+
+// Debug commands typed into the command line, opened with `/`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    // Fraction of a day, 0 being midnight
+    SetTime(f32),
+    FreezeTime(bool),
+    DayLength(f32),
+    Shadows(bool),
+    GpuCulling(bool),
+    ShadowDistance(f32),
+    ShadowResolution(u32),
+    Anisotropy(u16),
+    GameMode(GameMode),
+}
+
+// Named times, or an hour on a 24 hour clock
+fn parse_time(value: &str) -> Result<f32, ()> {
+    let hours = match value {
+        "midnight" => 0.0,
+        "day" | "sunrise" => 6.0,
+        "noon" => 12.0,
+        "night" | "sunset" => 18.0,
+        _ => value.parse::<f32>().map_err(|_| ())?,
+    };
+
+    if (0.0..24.0).contains(&hours) {
+        Ok(hours / 24.0)
+    } else {
+        Err(())
+    }
+}
+
+pub fn parse(line: &str) -> Result<Command, ()> {
+    let line = line.trim();
+    let line = line.strip_prefix('/').unwrap_or(line);
+    let words = line.split_whitespace().collect::<Vec<_>>();
+
+    match words.as_slice() {
+        ["time", "set", value] => Ok(Command::SetTime(parse_time(value)?)),
+        ["time", "freeze"] => Ok(Command::FreezeTime(true)),
+        ["time", "unfreeze"] => Ok(Command::FreezeTime(false)),
+        ["time", "length", seconds] => match seconds.parse::<f32>() {
+            Ok(seconds) if seconds > 0.0 => Ok(Command::DayLength(seconds)),
+            _ => Err(()),
+        },
+        ["culling", "gpu"] => Ok(Command::GpuCulling(true)),
+        ["culling", "cpu"] => Ok(Command::GpuCulling(false)),
+        ["shadows", "on"] => Ok(Command::Shadows(true)),
+        ["shadows", "off"] => Ok(Command::Shadows(false)),
+        ["shadows", "distance", blocks] => match blocks.parse::<f32>() {
+            Ok(blocks) if blocks > 0.0 => Ok(Command::ShadowDistance(blocks)),
+            _ => Err(()),
+        },
+        // Bounded by the smallest max texture size wgpu guarantees
+        ["shadows", "resolution", pixels] => match pixels.parse::<u32>() {
+            Ok(pixels) if (256..=8192).contains(&pixels) => Ok(Command::ShadowResolution(pixels)),
+            _ => Err(()),
+        },
+        ["gamemode", "survival"] => Ok(Command::GameMode(GameMode::Survival)),
+        ["gamemode", "creative"] => Ok(Command::GameMode(GameMode::Creative)),
+        ["anisotropy", level] => match level.parse::<u16>() {
+            Ok(level) if (1..=MAX_ANISOTROPY).contains(&level) => Ok(Command::Anisotropy(level)),
+            _ => Err(()),
+        },
+        _ => Err(()),
+    }
+}
+
+#[derive(Default)]
+pub struct CommandLine {
+    pub open: bool,
+    pub text: String,
+    // Result of the last command, shown in the debug view
+    pub output: String,
+}
+
+impl CommandLine {
+    pub fn open(&mut self) {
+        self.open = true;
+        self.text.clear();
+    }
+
+    // Returns the line once it's submitted with enter
+    pub fn handle_key(&mut self, event: &KeyEvent) -> Option<String> {
+        if event.state != ElementState::Pressed {
+            return None;
+        }
+
+        match &event.logical_key {
+            Key::Named(NamedKey::Enter) => {
+                self.open = false;
+                return Some(std::mem::take(&mut self.text));
+            }
+            Key::Named(NamedKey::Escape) => self.open = false,
+            Key::Named(NamedKey::Backspace) => {
+                self.text.pop();
+            }
+            _ => {
+                if let Some(text) = &event.text {
+                    self.text.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_commands() {
+        assert_eq!(parse("/time set noon"), Ok(Command::SetTime(0.5)));
+        assert_eq!(parse("time set 18"), Ok(Command::SetTime(0.75)));
+        assert_eq!(parse("  time   set 0 "), Ok(Command::SetTime(0.0)));
+        assert_eq!(parse("/time freeze"), Ok(Command::FreezeTime(true)));
+        assert_eq!(parse("/time unfreeze"), Ok(Command::FreezeTime(false)));
+        assert_eq!(parse("/time length 600"), Ok(Command::DayLength(600.0)));
+    }
+
+    #[test]
+    fn test_parse_shadow_commands() {
+        assert_eq!(parse("/shadows off"), Ok(Command::Shadows(false)));
+        assert_eq!(parse("/shadows on"), Ok(Command::Shadows(true)));
+        assert_eq!(
+            parse("/shadows distance 96"),
+            Ok(Command::ShadowDistance(96.0))
+        );
+        assert_eq!(
+            parse("/shadows resolution 4096"),
+            Ok(Command::ShadowResolution(4096))
+        );
+        assert_eq!(parse("/shadows distance -1"), Err(()));
+        assert_eq!(parse("/shadows resolution 100000"), Err(()));
+    }
+
+    #[test]
+    fn test_parse_culling() {
+        assert_eq!(parse("/culling gpu"), Ok(Command::GpuCulling(true)));
+        assert_eq!(parse("/culling cpu"), Ok(Command::GpuCulling(false)));
+        assert_eq!(parse("/culling off"), Err(()));
+    }
+
+    #[test]
+    fn test_parse_anisotropy() {
+        assert_eq!(parse("/anisotropy 8"), Ok(Command::Anisotropy(8)));
+        assert_eq!(parse("/anisotropy 0"), Err(()));
+        assert_eq!(parse("/anisotropy 32"), Err(()));
+    }
+
+    #[test]
+    fn test_parse_gamemode() {
+        assert_eq!(
+            parse("/gamemode creative"),
+            Ok(Command::GameMode(GameMode::Creative))
+        );
+        assert_eq!(
+            parse("/gamemode survival"),
+            Ok(Command::GameMode(GameMode::Survival))
+        );
+        assert_eq!(parse("/gamemode spectator"), Err(()));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_commands() {
+        assert_eq!(parse(""), Err(()));
+        assert_eq!(parse("/time"), Err(()));
+        assert_eq!(parse("/time set 24"), Err(()));
+        assert_eq!(parse("/time set dusk"), Err(()));
+        assert_eq!(parse("/time length 0"), Err(()));
+        assert_eq!(parse("/time freeze now"), Err(()));
+    }
+}
@@ -0,0 +1,123 @@
+// One thread per chunk draw. Hidden draws get an instance count of 0, so
+// a single multi-draw over every slot only draws what's visible.
+
+struct DrawInfo {
+    min: vec3<f32>,
+    index_count: u32,
+    max: vec3<f32>,
+    first_index: u32,
+    base_vertex: i32,
+    // Set on the CPU for chunks in range and not behind rock
+    enabled: u32,
+    _pad0: u32,
+    _pad1: u32,
+};
+
+struct DrawArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
+struct CullUniform {
+    // Facing in, from the current view-projection
+    planes: array<vec4<f32>, 6>,
+    // What the depth pyramid was drawn with
+    hiz_view_proj: mat4x4<f32>,
+    draw_count: u32,
+    hiz_levels: u32,
+    hiz_width: u32,
+    hiz_height: u32,
+    occlusion: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> cull: CullUniform;
+@group(0) @binding(1)
+var<storage, read> draws: array<DrawInfo>;
+@group(0) @binding(2)
+var<storage, read_write> args: array<DrawArgs>;
+@group(0) @binding(3)
+var hiz: texture_2d<f32>;
+
+fn in_frustum(min: vec3<f32>, max: vec3<f32>) -> bool {
+    for (var i = 0u; i < 6u; i++) {
+        let plane = cull.planes[i];
+        // The corner furthest along the plane's normal
+        let corner = select(min, max, plane.xyz >= vec3<f32>(0.0));
+        if dot(plane.xyz, corner) + plane.w < 0.0 {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn hiz_depth(level: i32, x: i32, y: i32) -> f32 {
+    return textureLoad(hiz, vec2<i32>(x, y), level).r;
+}
+
+// Whether the box is behind what was drawn last frame. Each texel of the
+// pyramid holds the furthest depth under it, so if the nearest point of
+// the box is further than that everywhere it covers, it can't be seen.
+fn occluded(min: vec3<f32>, max: vec3<f32>) -> bool {
+    if cull.occlusion == 0u {
+        return false;
+    }
+
+    var uv_min = vec2<f32>(1.0);
+    var uv_max = vec2<f32>(0.0);
+    var nearest = 1.0;
+    for (var i = 0u; i < 8u; i++) {
+        let corner = select(min, max, vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u));
+        let clip = cull.hiz_view_proj * vec4<f32>(corner, 1.0);
+        // Crosses the camera, so it's right up close
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest = min(nearest, ndc.z);
+    }
+    if nearest <= 0.0 {
+        return false;
+    }
+
+    uv_min = clamp(uv_min, vec2<f32>(0.0), vec2<f32>(1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // Pick the level where the box covers at most 2x2 texels
+    let size = (uv_max - uv_min) * vec2<f32>(f32(cull.hiz_width), f32(cull.hiz_height));
+    let level = i32(clamp(ceil(log2(max(max(size.x, size.y), 1.0))), 0.0, f32(cull.hiz_levels - 1u)));
+    let dims = vec2<i32>(textureDimensions(hiz, level));
+    let lo = clamp(vec2<i32>(uv_min * vec2<f32>(dims)), vec2<i32>(0), dims - 1);
+    let hi = clamp(vec2<i32>(uv_max * vec2<f32>(dims)), vec2<i32>(0), dims - 1);
+
+    let furthest = max(
+        max(hiz_depth(level, lo.x, lo.y), hiz_depth(level, hi.x, lo.y)),
+        max(hiz_depth(level, lo.x, hi.y), hiz_depth(level, hi.x, hi.y)),
+    );
+    return nearest > furthest;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= cull.draw_count {
+        return;
+    }
+
+    let draw = draws[i];
+    let visible = draw.enabled != 0u
+        && draw.index_count > 0u
+        && in_frustum(draw.min, draw.max)
+        && !occluded(draw.min, draw.max);
+
+    args[i] = DrawArgs(draw.index_count, select(0u, 1u, visible), draw.first_index, draw.base_vertex, 0u);
+}
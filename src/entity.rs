@@ -0,0 +1,436 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+use cgmath::{prelude::*, Point3, Quaternion, Rad, Vector3};
+
+use crate::{
+    chunk::{block_to_chunk, point_to_world, ChunkManager, CHUNK_WIDTH},
+    inventory::Inventory,
+    lighting, model,
+    physics::{self, Aabb},
+    player::GRAVITY,
+    raycasting::Ray,
+};
+
+const GROUND_FRICTION: f32 = 8.0;
+
+// Ids are handed out in increasing order and never reused, so
+// an id held onto after its entity despawns can't refer to
+// something else later on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    // Centre of the base of the entity
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+}
+
+impl Transform {
+    pub fn new(position: Point3<f32>) -> Self {
+        Self {
+            position,
+            yaw: Rad(0.0),
+        }
+    }
+
+    // Part of the way from this transform to another, `t` from 0 to 1.
+    // Turns whichever way round is shorter.
+    pub fn lerp(&self, to: &Transform, t: f32) -> Self {
+        Self {
+            position: self.position + (to.position - self.position) * t,
+            yaw: self.yaw + (to.yaw - self.yaw).normalize_signed() * t,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub width: f32,
+    pub height: f32,
+    pub on_ground: bool,
+}
+
+impl Collider {
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            width,
+            height,
+            on_ground: false,
+        }
+    }
+}
+
+// Draws the entity with the shared cube model
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderModel {
+    pub label: String,
+    pub scale: f32,
+    // From the entity position to the centre of the model
+    pub offset: Vector3<f32>,
+}
+
+// Component storage. Each component lives in its own map keyed by
+// entity id. BTreeMaps keep iteration in spawn order, so systems
+// run the same way every time.
+#[derive(Default)]
+pub struct Entities {
+    next_id: u64,
+    pub transforms: BTreeMap<EntityId, Transform>,
+    pub velocities: BTreeMap<EntityId, Vector3<f32>>,
+    pub colliders: BTreeMap<EntityId, Collider>,
+    pub models: BTreeMap<EntityId, RenderModel>,
+    pub items: BTreeMap<EntityId, crate::item_entity::ItemData>,
+    pub mobs: BTreeMap<EntityId, crate::mob::MobData>,
+    // Where everything was before the last tick, for drawing the
+    // frames in between
+    previous: BTreeMap<EntityId, Transform>,
+    chunk_index: HashMap<Point3<i32>, HashSet<EntityId>>,
+    entity_chunks: HashMap<EntityId, Point3<i32>>,
+}
+
+impl Entities {
+    pub fn spawn(&mut self, transform: Transform) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+
+        self.transforms.insert(id, transform);
+        self.index(id);
+
+        id
+    }
+
+    pub fn despawn(&mut self, id: EntityId) {
+        self.transforms.remove(&id);
+        self.velocities.remove(&id);
+        self.colliders.remove(&id);
+        self.models.remove(&id);
+        self.items.remove(&id);
+        self.mobs.remove(&id);
+        self.previous.remove(&id);
+        self.unindex(id);
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.transforms.contains_key(&id)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn aabb(&self, id: EntityId) -> Option<Aabb> {
+        let transform = self.transforms.get(&id)?;
+        let collider = self.colliders.get(&id)?;
+
+        Some(Aabb::from_base(
+            transform.position,
+            collider.width,
+            collider.height,
+        ))
+    }
+
+    fn index(&mut self, id: EntityId) {
+        let Some(transform) = self.transforms.get(&id) else {
+            return;
+        };
+        let chunk = block_to_chunk(point_to_world(transform.position));
+
+        if self.entity_chunks.get(&id) == Some(&chunk) {
+            return;
+        }
+
+        self.unindex(id);
+        self.chunk_index.entry(chunk).or_default().insert(id);
+        self.entity_chunks.insert(id, chunk);
+    }
+
+    fn unindex(&mut self, id: EntityId) {
+        if let Some(chunk) = self.entity_chunks.remove(&id) {
+            if let Some(ids) = self.chunk_index.get_mut(&chunk) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.chunk_index.remove(&chunk);
+                }
+            }
+        }
+    }
+
+    // Brings the chunk lookup up to date after entities have moved
+    pub fn reindex(&mut self) {
+        let ids = self.transforms.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            self.index(id);
+        }
+    }
+
+    pub fn in_chunk(&self, chunk_origin: Point3<i32>) -> impl Iterator<Item = EntityId> + '_ {
+        self.chunk_index
+            .get(&chunk_origin)
+            .into_iter()
+            .flat_map(|ids| ids.iter().copied())
+    }
+
+    // Every entity whose position is within `radius` of `pos`, nearest
+    // first. Only the chunks the search sphere touches are checked.
+    pub fn near(&self, pos: Point3<f32>, radius: f32) -> Vec<EntityId> {
+        let width = CHUNK_WIDTH as i32;
+        let min = block_to_chunk(point_to_world(pos - Vector3::from_value(radius)));
+        let max = block_to_chunk(point_to_world(pos + Vector3::from_value(radius)));
+
+        let mut found = Vec::new();
+        for x in (min.x..=max.x).step_by(width as usize) {
+            for y in (min.y..=max.y).step_by(width as usize) {
+                for z in (min.z..=max.z).step_by(width as usize) {
+                    for id in self.in_chunk(Point3::new(x, y, z)) {
+                        let dist = self.transforms[&id].position.distance(pos);
+                        if dist <= radius {
+                            found.push((id, dist));
+                        }
+                    }
+                }
+            }
+        }
+
+        found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        found.into_iter().map(|(id, _)| id).collect()
+    }
+
+    // Finds the nearest mob the ray passes through, and how far along
+    // the ray it was hit. Dropped items can't be targeted, so they never
+    // get in the way of breaking or placing blocks.
+    pub fn cast_ray(&self, ray: &Ray) -> Option<(EntityId, f32)> {
+        let dir = ray.dir.normalize();
+
+        self.mobs
+            .keys()
+            .filter_map(|&id| {
+                let dist = self.aabb(id)?.ray_intersection(ray.pos, dir)?;
+                (dist <= ray.max_dist).then_some((id, dist))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Entities are drawn `alpha` of the way from where they were before
+    // the last tick to where they are now, and lit by the light at
+    // their centre
+    pub fn render_instances(
+        &self,
+        chunk_manager: &ChunkManager,
+        alpha: f32,
+    ) -> Vec<model::RenderInstance> {
+        self.models
+            .iter()
+            .filter_map(|(id, model)| {
+                let current = self.transforms.get(id)?;
+                let transform = match self.previous.get(id) {
+                    Some(previous) => previous.lerp(current, alpha),
+                    // Spawned during the last tick
+                    None => *current,
+                };
+                let position = transform.position + model.offset;
+
+                Some(model::RenderInstance {
+                    position: position.to_vec(),
+                    rotation: Quaternion::from_axis_angle(Vector3::unit_z(), transform.yaw),
+                    scale: model.scale,
+                    label: model.label.clone(),
+                    light: lighting::light_at(chunk_manager, point_to_world(position)).brightness(),
+                })
+            })
+            .collect()
+    }
+}
+
+// Everything outside the entity store that systems get to use
+pub struct TickContext<'a> {
+    pub chunk_manager: &'a ChunkManager,
+    pub player_eye: Point3<f32>,
+    pub inventory: &'a mut Inventory,
+    // Whether new animals can appear around the player
+    pub spawn_mobs: bool,
+}
+
+pub type System = fn(&mut Entities, &mut TickContext, Duration);
+
+// Owns every entity in the world along with the systems that update
+// them. Systems run once per tick, in the order they were added.
+pub struct EntityManager {
+    pub entities: Entities,
+    systems: Vec<(&'static str, System)>,
+}
+
+impl Default for EntityManager {
+    fn default() -> Self {
+        let mut manager = Self {
+            entities: Entities::default(),
+            systems: Vec::new(),
+        };
+
+        crate::mob::register_systems(&mut manager);
+        manager.add_system("physics", physics_system);
+        crate::item_entity::register_systems(&mut manager);
+
+        manager
+    }
+}
+
+impl EntityManager {
+    pub fn add_system(&mut self, name: &'static str, system: System) {
+        self.systems.push((name, system));
+    }
+
+    pub fn tick(&mut self, ctx: &mut TickContext, dt: Duration) {
+        self.entities.previous = self.entities.transforms.clone();
+        for (_name, system) in &self.systems {
+            system(&mut self.entities, ctx, dt);
+            self.entities.reindex();
+        }
+    }
+}
+
+// Applies gravity and moves everything with a velocity and a collider
+// through the world
+pub fn physics_system(entities: &mut Entities, ctx: &mut TickContext, dt: Duration) {
+    let dt = dt.as_secs_f32();
+
+    for (id, vel) in entities.velocities.iter_mut() {
+        let (Some(transform), Some(collider)) = (
+            entities.transforms.get_mut(id),
+            entities.colliders.get_mut(id),
+        ) else {
+            continue;
+        };
+
+        // Mobs set their own speed along the ground every tick, friction
+        // would only fight their AI
+        if collider.on_ground && !entities.mobs.contains_key(id) {
+            let friction = (1.0 - GROUND_FRICTION * dt).max(0.0);
+            vel.x *= friction;
+            vel.y *= friction;
+        }
+
+        let aabb = Aabb::from_base(transform.position, collider.width, collider.height);
+        let (moved, collision) = physics::integrate(aabb, vel, GRAVITY, dt, ctx.chunk_manager);
+
+        transform.position += moved;
+        collider.on_ground = collision.ground;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_box(entities: &mut Entities, pos: Point3<f32>) -> EntityId {
+        let id = entities.spawn(Transform::new(pos));
+        entities.colliders.insert(id, Collider::new(1.0, 1.0));
+        id
+    }
+
+    fn spawn_mob(entities: &mut Entities, pos: Point3<f32>) -> EntityId {
+        let id = spawn_box(entities, pos);
+        entities.mobs.insert(id, crate::mob::MobData::new(0));
+        id
+    }
+
+    #[test]
+    fn test_ids_are_stable() {
+        let mut entities = Entities::default();
+        let a = entities.spawn(Transform::new(Point3::new(0.0, 0.0, 0.0)));
+        let b = entities.spawn(Transform::new(Point3::new(0.0, 0.0, 0.0)));
+
+        entities.despawn(a);
+        let c = entities.spawn(Transform::new(Point3::new(0.0, 0.0, 0.0)));
+
+        assert_ne!(a, c);
+        assert!(!entities.contains(a));
+        assert!(entities.contains(b));
+        assert_eq!(entities.len(), 2);
+    }
+
+    #[test]
+    fn test_chunk_lookup_follows_movement() {
+        let mut entities = Entities::default();
+        let id = spawn_box(&mut entities, Point3::new(1.0, 1.0, 0.0));
+
+        assert_eq!(
+            entities.in_chunk(Point3::new(0, 0, 0)).collect::<Vec<_>>(),
+            [id]
+        );
+
+        entities.transforms.get_mut(&id).unwrap().position = Point3::new(-3.0, 20.0, 0.0);
+        entities.reindex();
+
+        assert_eq!(entities.in_chunk(Point3::new(0, 0, 0)).count(), 0);
+        assert_eq!(
+            entities
+                .in_chunk(Point3::new(-16, 16, 0))
+                .collect::<Vec<_>>(),
+            [id]
+        );
+
+        entities.despawn(id);
+        assert_eq!(entities.in_chunk(Point3::new(-16, 16, 0)).count(), 0);
+    }
+
+    #[test]
+    fn test_near_crosses_chunks() {
+        let mut entities = Entities::default();
+        let a = spawn_box(&mut entities, Point3::new(15.5, 1.0, 0.0));
+        let b = spawn_box(&mut entities, Point3::new(16.5, 1.0, 0.0));
+        let _far = spawn_box(&mut entities, Point3::new(40.0, 1.0, 0.0));
+
+        assert_eq!(entities.near(Point3::new(16.2, 1.0, 0.0), 2.0), [b, a]);
+    }
+
+    #[test]
+    fn test_transform_lerp_turns_the_short_way() {
+        let from = Transform {
+            position: Point3::new(0.0, 0.0, 0.0),
+            yaw: Rad(3.0),
+        };
+        let to = Transform {
+            position: Point3::new(2.0, 0.0, 1.0),
+            yaw: Rad(-3.0),
+        };
+
+        let halfway = from.lerp(&to, 0.5);
+        assert_eq!(halfway.position, Point3::new(1.0, 0.0, 0.5));
+        // Across the back, not round through the front
+        assert!((halfway.yaw.0 - std::f32::consts::PI).abs() < 1e-4);
+        assert_eq!(from.lerp(&to, 1.0).position, to.position);
+    }
+
+    #[test]
+    fn test_cast_ray_nearest_entity() {
+        let mut entities = Entities::default();
+        let _behind = spawn_mob(&mut entities, Point3::new(-3.0, 0.5, 0.0));
+        let far = spawn_mob(&mut entities, Point3::new(4.0, 0.5, 0.0));
+        let near = spawn_mob(&mut entities, Point3::new(2.0, 0.5, 0.0));
+        // Anything that isn't a mob, like a dropped item, is skipped
+        let _item = spawn_box(&mut entities, Point3::new(1.0, 0.5, 0.0));
+
+        let ray = Ray {
+            pos: Point3::new(0.0, 0.5, 0.5),
+            dir: Vector3::new(1.0, 0.0, 0.0),
+            max_dist: 5.0,
+            n_tests: 100,
+        };
+
+        let (id, dist) = entities.cast_ray(&ray).unwrap();
+        assert_eq!(id, near);
+        assert!((dist - 1.5).abs() < 1e-5);
+
+        entities.despawn(near);
+        assert_eq!(entities.cast_ray(&ray).map(|r| r.0), Some(far));
+
+        entities.despawn(far);
+        assert_eq!(entities.cast_ray(&ray), None);
+    }
+}
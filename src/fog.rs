@@ -0,0 +1,161 @@
+use wgpu::util::DeviceExt;
+
+use crate::chunk::CHUNK_WIDTH;
+
+// Fog starts this far into the render distance
+const FOG_START: f32 = 0.6;
+const UNDERWATER_COLOUR: [f32; 3] = [0.05, 0.2, 0.45];
+const UNDERWATER_DENSITY: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FogMode {
+    // Fades in between a start and end distance
+    Linear { start: f32, end: f32 },
+    // Thickens with distance straight away, for inside fluids
+    Exponential { density: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub colour: [f32; 3],
+    pub mode: FogMode,
+}
+
+impl Fog {
+    // Fully fogged just before the edge of the rendered chunks, so they
+    // fade in rather than popping. Chunks are rendered when their centre is
+    // in range, so half a chunk is left as margin.
+    pub fn for_render_distance(render_dist: u32, colour: [f32; 3]) -> Self {
+        let end = (render_dist as f32 - 0.5).max(0.5) * CHUNK_WIDTH as f32;
+        Self {
+            colour,
+            mode: FogMode::Linear {
+                start: end * FOG_START,
+                end,
+            },
+        }
+    }
+
+    pub fn underwater() -> Self {
+        Self {
+            colour: UNDERWATER_COLOUR,
+            mode: FogMode::Exponential {
+                density: UNDERWATER_DENSITY,
+            },
+        }
+    }
+
+    // How much of the fog colour is mixed in at a distance. Mirrors the
+    // shaders.
+    pub fn amount(&self, distance: f32) -> f32 {
+        match self.mode {
+            FogMode::Linear { start, end } => ((distance - start) / (end - start)).clamp(0.0, 1.0),
+            FogMode::Exponential { density } => 1.0 - (-density * distance).exp(),
+        }
+    }
+
+    fn to_uniform(self) -> FogUniform {
+        let (mode, start, end, density) = match self.mode {
+            FogMode::Linear { start, end } => (0, start, end, 0.0),
+            FogMode::Exponential { density } => (1, 0.0, 0.0, density),
+        };
+        FogUniform {
+            colour: self.colour,
+            mode,
+            start,
+            end,
+            density,
+            _pad: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FogUniform {
+    colour: [f32; 3],
+    mode: u32,
+    start: f32,
+    end: f32,
+    density: f32,
+    _pad: f32,
+}
+
+pub struct FogBinding {
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl FogBinding {
+    pub fn new(device: &wgpu::Device, fog: &Fog) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fog Buffer"),
+            contents: bytemuck::cast_slice(&[fog.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("fog_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("fog_bind_group"),
+        });
+
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, fog: &Fog) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[fog.to_uniform()]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_fog_ends_inside_render_distance() {
+        let fog = Fog::for_render_distance(4, [0.5, 0.6, 0.7]);
+        let FogMode::Linear { start, end } = fog.mode else {
+            panic!("{fog:?}");
+        };
+
+        assert!(start > 0.0 && start < end);
+        assert!(end < 4.0 * CHUNK_WIDTH as f32);
+        assert_eq!(fog.amount(0.0), 0.0);
+        assert_eq!(fog.amount(start), 0.0);
+        assert!((fog.amount((start + end) / 2.0) - 0.5).abs() < 1e-5);
+        assert_eq!(fog.amount(end), 1.0);
+        assert_eq!(fog.amount(1000.0), 1.0);
+    }
+
+    #[test]
+    fn test_underwater_fog_is_thick() {
+        let fog = Fog::underwater();
+        assert_eq!(fog.amount(0.0), 0.0);
+        assert!(fog.amount(2.0) > 0.2);
+        assert!(fog.amount(20.0) > 0.9);
+        assert!(fog.amount(20.0) > Fog::for_render_distance(2, [0.0; 3]).amount(20.0));
+    }
+}
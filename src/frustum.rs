@@ -0,0 +1,147 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+use crate::camera;
+
+// A plane facing into the frustum, points with `normal.dot(p) + d >= 0`
+// are on the inside
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: Vector3<f32>,
+    d: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = row.truncate();
+        let length = normal.magnitude();
+        Self {
+            normal: normal / length,
+            d: row.w / length,
+        }
+    }
+
+    fn distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+// The six planes around everything the camera can see
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Pulls the planes straight out of the rows of the matrix. wgpu clip
+    // space has -w <= x, y <= w and 0 <= z <= w.
+    pub fn from_view_proj(view_proj: Matrix4<f32>) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z].map(Plane::from_row),
+        }
+    }
+
+    pub fn from_camera(camera: &camera::Camera, projection: &camera::Projection) -> Self {
+        Self::from_view_proj(camera::view_proj(camera, projection))
+    }
+
+    // As `[nx, ny, nz, d]`, for handing to shaders
+    pub fn planes(&self) -> [[f32; 4]; 6] {
+        self.planes.map(|plane| plane.normal.extend(plane.d).into())
+    }
+
+    // Whether any of the box might be visible. Only the corner furthest
+    // along each plane's normal is checked, so boxes just off a corner of
+    // the frustum can still count as visible.
+    pub fn intersects_aabb(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = Vector3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.distance(corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, EuclideanSpace};
+
+    use super::*;
+    use crate::camera::{Camera, Projection};
+
+    fn frustum(pitch: f32) -> Frustum {
+        // At the origin looking along +x
+        Frustum::from_camera(
+            &Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(pitch)),
+            &Projection::new(1600, 900, Deg(45.0), 0.1, 100.0),
+        )
+    }
+
+    // A 16 block cube centred on the point
+    fn cube_visible(frustum: &Frustum, centre: (f32, f32, f32)) -> bool {
+        let centre = Point3::from(centre);
+        let half = Vector3::new(8.0, 8.0, 8.0);
+        frustum.intersects_aabb(centre - half, centre + half)
+    }
+
+    #[test]
+    fn test_points_inside() {
+        let frustum = frustum(0.0);
+        let visible = |p: (f32, f32, f32)| {
+            let p = Point3::from(p);
+            frustum.intersects_aabb(p, p)
+        };
+
+        assert!(visible((10.0, 0.0, 0.0)));
+        assert!(!visible((-10.0, 0.0, 0.0)));
+        // Past the far plane, and behind the near one
+        assert!(!visible((101.0, 0.0, 0.0)));
+        assert!(!visible((0.05, 0.0, 0.0)));
+        // The screen is wider than it is tall
+        assert!(visible((10.0, 6.0, 0.0)));
+        assert!(!visible((10.0, 0.0, 6.0)));
+        assert!(visible((10.0, -7.0, 0.0)));
+        assert!(!visible((10.0, -8.0, 0.0)));
+    }
+
+    #[test]
+    fn test_chunks_above_and_below() {
+        let level = frustum(0.0);
+        assert!(cube_visible(&level, (40.0, 0.0, 0.0)));
+        assert!(!cube_visible(&level, (0.0, 0.0, 40.0)));
+        assert!(!cube_visible(&level, (0.0, 0.0, -40.0)));
+        assert!(!cube_visible(&level, (-40.0, 0.0, 0.0)));
+
+        let up = frustum(89.0);
+        assert!(cube_visible(&up, (0.0, 0.0, 40.0)));
+        assert!(!cube_visible(&up, (0.0, 0.0, -40.0)));
+        assert!(!cube_visible(&up, (60.0, 0.0, 0.0)));
+
+        let down = frustum(-89.0);
+        assert!(cube_visible(&down, (0.0, 0.0, -40.0)));
+        assert!(!cube_visible(&down, (0.0, 0.0, 40.0)));
+        assert!(!cube_visible(&down, (60.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_pitched_camera() {
+        // Looking 45 degrees down at the ground in front
+        let frustum = frustum(-45.0);
+        assert!(cube_visible(&frustum, (30.0, 0.0, -30.0)));
+        assert!(!cube_visible(&frustum, (40.0, 0.0, 0.0)));
+        assert!(!cube_visible(&frustum, (40.0, 0.0, 40.0)));
+        // Yaw only, the old test would have counted this as in view
+        assert!(!cube_visible(&frustum, (60.0, 0.0, 40.0)));
+
+        // The camera inside the box always counts
+        let origin = Point3::origin();
+        assert!(frustum.intersects_aabb(
+            origin - Vector3::new(1.0, 1.0, 1.0),
+            origin + Vector3::new(1.0, 1.0, 1.0)
+        ));
+    }
+}
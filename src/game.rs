@@ -1,64 +1,47 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use cgmath::{Point3, Rad};
 use winit::{event_loop::EventLoop, platform::pump_events::EventLoopExtPumpEvents};
 
-use crate::{
-    app,
-    player::{Entity, Player},
-};
+use crate::{app, timestep::FixedTimestep, world};
 
 pub struct MCRS<T: 'static> {
     state_app: app::StateApplication,
-    last_render_time: instant::Instant,
-    last_update_time: instant::Instant,
     event_loop: EventLoop<T>,
     running: bool,
-    //Player doesn't do anything currently
-    player: Player,
+    timestep: FixedTimestep,
 }
 
 impl<T> MCRS<T> {
-    const DESIRED_FPS: u32 = 60;
+    // After a stall this many ticks are caught up on at most, and the
+    // rest are skipped
+    const MAX_CATCH_UP_TICKS: u32 = 5;
 
     pub fn new(window_state: app::StateApplication, event_loop: EventLoop<T>) -> Self {
         Self {
             state_app: window_state,
             event_loop,
-            last_render_time: Instant::now(),
-            last_update_time: Instant::now(),
             running: true,
-            player: Player::new(Point3::new(0.0, 0.0, 0.0), Rad(0.0), Rad(0.0)),
+            timestep: FixedTimestep::new(world::TICK, Self::MAX_CATCH_UP_TICKS),
         }
     }
 
     pub fn run(&mut self) {
-        //NOTE: if we start cooking CPUs, can limit the update rate
-        // as well.
-
-        let update_timestep = Duration::from_secs_f64(1.0 / Self::DESIRED_FPS as f64);
         let mut prev_time = instant::Instant::now();
-        let mut accum_time = Duration::ZERO;
 
         while self.running {
             let curr_time = instant::Instant::now();
             let elapsed_time = curr_time - prev_time;
             prev_time = curr_time;
-            accum_time += elapsed_time;
 
             self.running &= self.input();
 
-            // modification: make sure we get at least one tick in
-            // per render. Ideally this isn't something we run into
-            // a lot, but with very heavy rendering scenarios we want
-            // at least one tick per render no matter what
-            self.running &= self.update();
-
-            while accum_time >= update_timestep {
-                self.running &= self.update();
-                accum_time -= update_timestep;
+            // The world always moves on in whole ticks, however fast
+            // frames are being drawn
+            for _ in 0..self.timestep.advance(elapsed_time) {
+                self.running &= self.tick();
             }
 
+            self.running &= self.update(elapsed_time);
             let _ = self.render();
         }
 
@@ -77,15 +60,17 @@ impl<T> MCRS<T> {
         false
     }
 
-    fn update(&mut self) -> bool {
+    fn tick(&mut self) -> bool {
         if let Some(state) = self.state_app.state.as_mut() {
-            let now = instant::Instant::now();
-            let dt = now - self.last_update_time;
-            self.last_update_time = now;
-
-            state.update(dt);
-            self.player.update(dt);
+            state.tick();
+            return state.running;
+        }
+        false
+    }
 
+    fn update(&mut self, dt: Duration) -> bool {
+        if let Some(state) = self.state_app.state.as_mut() {
+            state.update(self.timestep.alpha(), dt);
             return state.running;
         }
         false
@@ -93,18 +78,25 @@ impl<T> MCRS<T> {
 
     fn render(&mut self) -> Result<(), ()> {
         if let Some(state) = self.state_app.state.as_mut() {
-            self.last_render_time = instant::Instant::now();
-            state.debug_view.update_text(
-                format!(
-                    "Debug View\nCamera pos: ({:.2}, {:.2}, {:.2})\nPitch: {:?}, Yaw: {:?}",
-                    state.camera.position.x,
-                    state.camera.position.y,
-                    state.camera.position.z,
-                    state.camera.pitch,
-                    state.camera.yaw,
-                )
-                .as_str(),
+            let (hours, minutes) = state.world.clock.hours_minutes();
+            let mut text = format!(
+                "Debug View\nCamera pos: ({:.2}, {:.2}, {:.2})\nPitch: {:?}, Yaw: {:?}\nDay {} {:02}:{:02}{}",
+                state.view.position.x,
+                state.view.position.y,
+                state.view.position.z,
+                state.view.pitch,
+                state.view.yaw,
+                state.world.clock.day,
+                hours,
+                minutes,
+                if state.world.clock.frozen { " (frozen)" } else { "" },
             );
+            if state.command_line.open {
+                text.push_str(&format!("\n> {}_", state.command_line.text));
+            } else if !state.command_line.output.is_empty() {
+                text.push_str(&format!("\n{}", state.command_line.output));
+            }
+            state.debug_view.update_text(&text);
 
             match state.render() {
                 Ok(_) => Ok(()),
@@ -130,5 +122,9 @@ impl<T> MCRS<T> {
         }
     }
 
-    fn close(&mut self) {}
+    fn close(&mut self) {
+        if let Some(state) = self.state_app.state.as_ref() {
+            state.save();
+        }
+    }
 }
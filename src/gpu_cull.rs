@@ -0,0 +1,817 @@
+use std::{collections::HashMap, ops::Range};
+
+use cgmath::{Matrix4, Point3};
+
+use crate::{
+    chunk::Material,
+    frustum::Frustum,
+    mesh::{BlockVertex, ChunkMeshData},
+    texture::{mip_level_count, DepthTexture},
+};
+
+// Starting sizes, everything grows as more chunks are loaded
+const INITIAL_VERTICES: u32 = 1 << 20;
+const INITIAL_INDICES: u32 = 3 << 19;
+const INITIAL_SLOTS: u32 = 1024;
+const CULL_WORKGROUP: u32 = 64;
+const HIZ_WORKGROUP: u32 = 8;
+
+// Whether the adapter can cull on the GPU and draw the result with one
+// multi-draw. Without it chunks are culled and drawn one by one.
+pub fn supported(features: wgpu::Features, downlevel: wgpu::DownlevelFlags) -> bool {
+    features.contains(wgpu::Features::MULTI_DRAW_INDIRECT)
+        && downlevel.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        && downlevel.contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION)
+}
+
+// First fit allocator for ranges of a buffer
+#[derive(Debug)]
+struct RangeAllocator {
+    // Sorted, and never touching each other
+    free: Vec<Range<u32>>,
+    capacity: u32,
+}
+
+impl RangeAllocator {
+    fn new(capacity: u32) -> Self {
+        Self {
+            free: std::iter::once(0..capacity).collect(),
+            capacity,
+        }
+    }
+
+    fn alloc(&mut self, len: u32) -> Option<Range<u32>> {
+        if len == 0 {
+            return Some(0..0);
+        }
+
+        let i = self.free.iter().position(|r| r.end - r.start >= len)?;
+        let start = self.free[i].start;
+        self.free[i].start += len;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+        Some(start..start + len)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let i = self.free.partition_point(|r| r.start < range.start);
+        self.free.insert(i, range);
+        // Merge with the neighbours either side
+        if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+            self.free[i].end = self.free.remove(i + 1).end;
+        }
+        if i > 0 && self.free[i - 1].end == self.free[i].start {
+            self.free[i - 1].end = self.free.remove(i).end;
+        }
+    }
+
+    // At least doubles, so growing doesn't happen too often
+    fn grow(&mut self, needed: u32) {
+        let new_capacity = (self.capacity * 2).max(self.capacity + needed);
+        self.free(self.capacity..new_capacity);
+        self.capacity = new_capacity;
+    }
+}
+
+// Opaque and cutout geometry for one chunk share a vertex range, with
+// the cutout indices straight after the opaque ones
+#[derive(Debug, PartialEq)]
+struct SolidMesh {
+    vertices: Vec<BlockVertex>,
+    indices: Vec<u32>,
+    opaque_indices: u32,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+fn combine_solid(data: &ChunkMeshData) -> SolidMesh {
+    let offset = data.opaque.vertices.len() as u32;
+    let vertices = [&data.opaque.vertices[..], &data.cutout.vertices[..]].concat();
+    let indices = data
+        .opaque
+        .indices
+        .iter()
+        .copied()
+        .chain(data.cutout.indices.iter().map(|i| i + offset))
+        .collect();
+
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in &vertices {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex.position[axis]);
+            max[axis] = max[axis].max(vertex.position[axis]);
+        }
+    }
+
+    SolidMesh {
+        vertices,
+        indices,
+        opaque_indices: data.opaque.indices.len() as u32,
+        min,
+        max,
+    }
+}
+
+// Laid out to match `DrawInfo` in cull.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawInfo {
+    min: [f32; 3],
+    index_count: u32,
+    max: [f32; 3],
+    first_index: u32,
+    base_vertex: i32,
+    enabled: u32,
+    _pad: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    planes: [[f32; 4]; 6],
+    hiz_view_proj: [[f32; 4]; 4],
+    draw_count: u32,
+    hiz_levels: u32,
+    hiz_width: u32,
+    hiz_height: u32,
+    occlusion: u32,
+    _pad: [u32; 3],
+}
+
+struct ChunkSlot {
+    slot: u32,
+    vertices: Range<u32>,
+    indices: Range<u32>,
+}
+
+// The furthest depth drawn last frame, halved at each level
+struct DepthPyramid {
+    size: (u32, u32),
+    levels: u32,
+    view: wgpu::TextureView,
+    bind_groups: Vec<wgpu::BindGroup>,
+    // The camera it was drawn from
+    view_proj: Matrix4<f32>,
+    // Nothing has been drawn into it yet
+    valid: bool,
+}
+
+impl DepthPyramid {
+    fn new(
+        device: &wgpu::Device,
+        depth: &DepthTexture,
+        from_depth: &wgpu::ComputePipeline,
+        downsample: &wgpu::ComputePipeline,
+    ) -> Self {
+        let depth_size = depth.texture.size();
+        let size = (
+            depth_size.width.div_ceil(2).max(1),
+            depth_size.height.div_ceil(2).max(1),
+        );
+        let levels = mip_level_count(size.0, size.1);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth_pyramid"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let bind_groups = (0..levels)
+            .map(|level| {
+                let dst = level_view(level);
+                if level == 0 {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("depth_pyramid_bind_group"),
+                        layout: &from_depth.get_bind_group_layout(0),
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::TextureView(&depth.view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::TextureView(&dst),
+                            },
+                        ],
+                    })
+                } else {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("depth_pyramid_bind_group"),
+                        layout: &downsample.get_bind_group_layout(0),
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(&level_view(
+                                    level - 1,
+                                )),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::TextureView(&dst),
+                            },
+                        ],
+                    })
+                }
+            })
+            .collect();
+
+        Self {
+            size,
+            levels,
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            bind_groups,
+            view_proj: Matrix4::from_scale(1.0),
+            valid: false,
+        }
+    }
+}
+
+// Everything needed to cull on the GPU
+struct GpuCuller {
+    cull_pipeline: wgpu::ComputePipeline,
+    from_depth_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    uniform_buffer: wgpu::Buffer,
+    info_buffer: wgpu::Buffer,
+    // One set of draws per material, opaque then cutout
+    indirect_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pyramid: DepthPyramid,
+}
+
+impl GpuCuller {
+    fn new(device: &wgpu::Device, depth: &DepthTexture, slots: u32) -> Self {
+        let cull_shader = device.create_shader_module(wgpu::include_wgsl!("cull.wgsl"));
+        let hiz_shader = device.create_shader_module(wgpu::include_wgsl!("hiz.wgsl"));
+        let compute_pipeline = |label, module: &wgpu::ShaderModule, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: None,
+                module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let cull_pipeline = compute_pipeline("cull_pipeline", &cull_shader, "cs_main");
+        let from_depth_pipeline = compute_pipeline("hiz_from_depth", &hiz_shader, "cs_from_depth");
+        let downsample_pipeline = compute_pipeline("hiz_downsample", &hiz_shader, "cs_downsample");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cull_uniform_buffer"),
+            size: std::mem::size_of::<CullUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pyramid = DepthPyramid::new(device, depth, &from_depth_pipeline, &downsample_pipeline);
+        let (info_buffer, indirect_buffer) = Self::create_draw_buffers(device, slots);
+        let bind_group = Self::create_bind_group(
+            device,
+            &cull_pipeline,
+            &uniform_buffer,
+            &info_buffer,
+            &indirect_buffer,
+            &pyramid,
+        );
+
+        Self {
+            cull_pipeline,
+            from_depth_pipeline,
+            downsample_pipeline,
+            uniform_buffer,
+            info_buffer,
+            indirect_buffer,
+            bind_group,
+            pyramid,
+        }
+    }
+
+    fn create_draw_buffers(device: &wgpu::Device, slots: u32) -> (wgpu::Buffer, wgpu::Buffer) {
+        let draws = 2 * slots as u64;
+        let info_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("chunk_draw_info_buffer"),
+            size: draws * std::mem::size_of::<DrawInfo>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("chunk_indirect_buffer"),
+            size: draws * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+        (info_buffer, indirect_buffer)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        cull_pipeline: &wgpu::ComputePipeline,
+        uniform_buffer: &wgpu::Buffer,
+        info_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+        pyramid: &DepthPyramid,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("cull_bind_group"),
+            layout: &cull_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: info_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: indirect_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&pyramid.view),
+                },
+            ],
+        })
+    }
+
+    fn resize_draws(&mut self, device: &wgpu::Device, slots: u32) {
+        (self.info_buffer, self.indirect_buffer) = Self::create_draw_buffers(device, slots);
+        self.rebind(device);
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.cull_pipeline,
+            &self.uniform_buffer,
+            &self.info_buffer,
+            &self.indirect_buffer,
+            &self.pyramid,
+        );
+    }
+}
+
+// Opaque and cutout chunk geometry, kept in one big vertex and index
+// buffer so every chunk can be drawn from a single indirect buffer
+pub struct ChunkGeometry {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vertex_ranges: RangeAllocator,
+    index_ranges: RangeAllocator,
+    chunks: HashMap<Point3<i32>, ChunkSlot>,
+    free_slots: Vec<u32>,
+    slot_capacity: u32,
+    // Opaque draws for each slot, then cutout draws for each slot
+    draws: Vec<DrawInfo>,
+    // None when culling on the CPU
+    gpu: Option<GpuCuller>,
+    gpu_supported: bool,
+}
+
+impl ChunkGeometry {
+    // Culls on the GPU whenever the adapter can
+    pub fn new(device: &wgpu::Device, depth: &DepthTexture, gpu_supported: bool) -> Self {
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+
+        Self {
+            vertex_buffer: buffer(
+                "chunk_vertex_buffer",
+                INITIAL_VERTICES as u64 * std::mem::size_of::<BlockVertex>() as u64,
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: buffer(
+                "chunk_index_buffer",
+                INITIAL_INDICES as u64 * 4,
+                wgpu::BufferUsages::INDEX,
+            ),
+            vertex_ranges: RangeAllocator::new(INITIAL_VERTICES),
+            index_ranges: RangeAllocator::new(INITIAL_INDICES),
+            chunks: HashMap::new(),
+            free_slots: (0..INITIAL_SLOTS).rev().collect(),
+            slot_capacity: INITIAL_SLOTS,
+            draws: vec![DrawInfo::default(); 2 * INITIAL_SLOTS as usize],
+            gpu: gpu_supported.then(|| GpuCuller::new(device, depth, INITIAL_SLOTS)),
+            gpu_supported,
+        }
+    }
+
+    pub fn gpu_culling(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    // Fails if the adapter can't cull on the GPU
+    pub fn set_gpu_culling(
+        &mut self,
+        device: &wgpu::Device,
+        depth: &DepthTexture,
+        enabled: bool,
+    ) -> Result<(), ()> {
+        if enabled && !self.gpu_supported {
+            return Err(());
+        }
+        if enabled != self.gpu_culling() {
+            self.gpu = enabled.then(|| GpuCuller::new(device, depth, self.slot_capacity));
+        }
+        Ok(())
+    }
+
+    // Swaps in a new mesh for the chunk
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: Point3<i32>,
+        data: &ChunkMeshData,
+    ) {
+        self.remove(key);
+
+        let mesh = combine_solid(data);
+        if mesh.indices.is_empty() {
+            return;
+        }
+        let vertex_count = mesh.vertices.len() as u32;
+        let index_count = mesh.indices.len() as u32;
+        let vertices = loop {
+            match self.vertex_ranges.alloc(vertex_count) {
+                Some(range) => break range,
+                None => self.grow_vertices(device, queue, vertex_count),
+            }
+        };
+        let indices = loop {
+            match self.index_ranges.alloc(index_count) {
+                Some(range) => break range,
+                None => self.grow_indices(device, queue, index_count),
+            }
+        };
+        if self.free_slots.is_empty() {
+            self.grow_slots(device);
+        }
+        let slot = self.free_slots.pop().unwrap();
+
+        queue.write_buffer(
+            &self.vertex_buffer,
+            vertices.start as u64 * std::mem::size_of::<BlockVertex>() as u64,
+            bytemuck::cast_slice(&mesh.vertices),
+        );
+        queue.write_buffer(
+            &self.index_buffer,
+            indices.start as u64 * 4,
+            bytemuck::cast_slice(&mesh.indices),
+        );
+
+        let draw = |first_index, index_count| DrawInfo {
+            min: mesh.min,
+            index_count,
+            max: mesh.max,
+            first_index,
+            base_vertex: vertices.start as i32,
+            enabled: 0,
+            _pad: [0; 2],
+        };
+        self.draws[slot as usize] = draw(indices.start, mesh.opaque_indices);
+        self.draws[(self.slot_capacity + slot) as usize] = draw(
+            indices.start + mesh.opaque_indices,
+            index_count - mesh.opaque_indices,
+        );
+
+        self.chunks.insert(
+            key,
+            ChunkSlot {
+                slot,
+                vertices,
+                indices,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: Point3<i32>) {
+        if let Some(chunk) = self.chunks.remove(&key) {
+            self.vertex_ranges.free(chunk.vertices);
+            self.index_ranges.free(chunk.indices);
+            self.draws[chunk.slot as usize] = DrawInfo::default();
+            self.draws[(self.slot_capacity + chunk.slot) as usize] = DrawInfo::default();
+            self.free_slots.push(chunk.slot);
+        }
+    }
+
+    fn grow_buffer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &mut wgpu::Buffer,
+        size: u64,
+    ) {
+        let new_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("chunk_geometry_buffer"),
+            size,
+            usage: buffer.usage(),
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Chunk Geometry Grow Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &new_buffer, 0, buffer.size());
+        queue.submit(std::iter::once(encoder.finish()));
+        *buffer = new_buffer;
+    }
+
+    fn grow_vertices(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, needed: u32) {
+        self.vertex_ranges.grow(needed);
+        let size = self.vertex_ranges.capacity as u64 * std::mem::size_of::<BlockVertex>() as u64;
+        Self::grow_buffer(device, queue, &mut self.vertex_buffer, size);
+    }
+
+    fn grow_indices(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, needed: u32) {
+        self.index_ranges.grow(needed);
+        let size = self.index_ranges.capacity as u64 * 4;
+        Self::grow_buffer(device, queue, &mut self.index_buffer, size);
+    }
+
+    fn grow_slots(&mut self, device: &wgpu::Device) {
+        let old = self.slot_capacity;
+        self.slot_capacity *= 2;
+        // Cutout draws sit after every opaque draw, so they move up
+        let cutout = self.draws.split_off(old as usize);
+        self.draws
+            .resize(self.slot_capacity as usize, DrawInfo::default());
+        self.draws.extend(cutout);
+        self.draws
+            .resize(2 * self.slot_capacity as usize, DrawInfo::default());
+        self.free_slots.extend((old..self.slot_capacity).rev());
+
+        if let Some(gpu) = &mut self.gpu {
+            gpu.resize_draws(device, self.slot_capacity);
+        }
+    }
+
+    // Points the pass at the shared buffers, before any draws
+    pub fn bind(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    // Draws one chunk straight away, for shadows and CPU culling
+    pub fn draw_chunk(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        key: Point3<i32>,
+        material: Material,
+    ) {
+        let Some(chunk) = self.chunks.get(&key) else {
+            return;
+        };
+        let draw = match material {
+            Material::Opaque => &self.draws[chunk.slot as usize],
+            Material::Cutout => &self.draws[(self.slot_capacity + chunk.slot) as usize],
+            Material::Translucent => return,
+        };
+        if draw.index_count > 0 {
+            render_pass.draw_indexed(
+                draw.first_index..draw.first_index + draw.index_count,
+                draw.base_vertex,
+                0..1,
+            );
+        }
+    }
+
+    // Works out which chunks to draw this frame. On the CPU that's just
+    // the ones passed in, on the GPU they're also checked against the
+    // frustum and what was drawn last frame.
+    pub fn cull<'a>(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        render_keys: impl Iterator<Item = &'a Point3<i32>>,
+    ) {
+        let Some(gpu) = &self.gpu else {
+            return;
+        };
+
+        for draw in &mut self.draws {
+            draw.enabled = 0;
+        }
+        for key in render_keys {
+            if let Some(chunk) = self.chunks.get(key) {
+                self.draws[chunk.slot as usize].enabled = 1;
+                self.draws[(self.slot_capacity + chunk.slot) as usize].enabled = 1;
+            }
+        }
+        queue.write_buffer(&gpu.info_buffer, 0, bytemuck::cast_slice(&self.draws));
+
+        let pyramid = &gpu.pyramid;
+        let uniform = CullUniform {
+            planes: frustum.planes(),
+            hiz_view_proj: pyramid.view_proj.into(),
+            draw_count: self.draws.len() as u32,
+            hiz_levels: pyramid.levels,
+            hiz_width: pyramid.size.0,
+            hiz_height: pyramid.size.1,
+            occlusion: pyramid.valid as u32,
+            _pad: [0; 3],
+        };
+        queue.write_buffer(&gpu.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&gpu.cull_pipeline);
+        pass.set_bind_group(0, &gpu.bind_group, &[]);
+        pass.dispatch_workgroups(uniform.draw_count.div_ceil(CULL_WORKGROUP), 1, 1);
+    }
+
+    // Draws every visible chunk of one material
+    pub fn draw<'a>(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        material: Material,
+        render_keys: impl Iterator<Item = &'a Point3<i32>>,
+    ) {
+        self.bind(render_pass);
+        match &self.gpu {
+            Some(gpu) => {
+                let first = match material {
+                    Material::Opaque => 0,
+                    Material::Cutout => self.slot_capacity as u64,
+                    Material::Translucent => return,
+                };
+                let stride = std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64;
+                render_pass.multi_draw_indexed_indirect(
+                    &gpu.indirect_buffer,
+                    first * stride,
+                    self.slot_capacity,
+                );
+            }
+            None => {
+                for key in render_keys {
+                    self.draw_chunk(render_pass, *key, material);
+                }
+            }
+        }
+    }
+
+    // Builds the depth pyramid from this frame, for culling the next one
+    pub fn build_depth_pyramid(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &DepthTexture,
+        view_proj: Matrix4<f32>,
+    ) {
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+
+        let depth_size = depth.texture.size();
+        let size = (
+            depth_size.width.div_ceil(2).max(1),
+            depth_size.height.div_ceil(2).max(1),
+        );
+        if gpu.pyramid.size != size {
+            gpu.pyramid = DepthPyramid::new(
+                device,
+                depth,
+                &gpu.from_depth_pipeline,
+                &gpu.downsample_pipeline,
+            );
+            gpu.rebind(device);
+        }
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Depth Pyramid Pass"),
+            timestamp_writes: None,
+        });
+        for (level, bind_group) in gpu.pyramid.bind_groups.iter().enumerate() {
+            let pipeline = if level == 0 {
+                &gpu.from_depth_pipeline
+            } else {
+                &gpu.downsample_pipeline
+            };
+            let width = (gpu.pyramid.size.0 >> level).max(1);
+            let height = (gpu.pyramid.size.1 >> level).max(1);
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(
+                width.div_ceil(HIZ_WORKGROUP),
+                height.div_ceil(HIZ_WORKGROUP),
+                1,
+            );
+        }
+
+        gpu.pyramid.view_proj = view_proj;
+        gpu.pyramid.valid = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::MeshData;
+
+    #[test]
+    fn test_supported() {
+        let downlevel =
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        assert!(supported(wgpu::Features::MULTI_DRAW_INDIRECT, downlevel));
+        assert!(!supported(wgpu::Features::empty(), downlevel));
+        assert!(!supported(
+            wgpu::Features::MULTI_DRAW_INDIRECT,
+            wgpu::DownlevelFlags::COMPUTE_SHADERS
+        ));
+    }
+
+    #[test]
+    fn test_range_allocator() {
+        let mut ranges = RangeAllocator::new(100);
+        let a = ranges.alloc(40).unwrap();
+        let b = ranges.alloc(40).unwrap();
+        assert_eq!((a.clone(), b.clone()), (0..40, 40..80));
+        assert_eq!(ranges.alloc(30), None);
+        assert_eq!(ranges.alloc(0), Some(0..0));
+
+        // A gap that's too small is skipped over
+        ranges.free(a);
+        assert_eq!(ranges.alloc(50), None);
+        assert_eq!(ranges.alloc(10), Some(0..10));
+
+        // Freed ranges merge back together
+        ranges.free(b);
+        ranges.free(0..10);
+        assert_eq!(ranges.free.len(), 1);
+        assert_eq!(ranges.free[0], 0..100);
+
+        ranges.alloc(100).unwrap();
+        ranges.grow(10);
+        assert_eq!(ranges.capacity, 200);
+        assert_eq!(ranges.alloc(100), Some(100..200));
+        ranges.grow(500);
+        assert_eq!(ranges.capacity, 700);
+    }
+
+    fn vertex(position: [f32; 3]) -> BlockVertex {
+        BlockVertex {
+            position,
+            tex_coords: [0.0; 2],
+            normal: [0.0; 3],
+            tex_idx: 0,
+            light: [0.0; 2],
+            ao: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_combine_solid() {
+        let data = ChunkMeshData {
+            opaque: MeshData {
+                vertices: vec![vertex([0.0, 1.0, 2.0]), vertex([4.0, 1.0, 0.0])],
+                indices: vec![0, 1, 0],
+            },
+            cutout: MeshData {
+                vertices: vec![vertex([-1.0, 5.0, 1.0])],
+                indices: vec![0, 0],
+            },
+            translucent: MeshData {
+                vertices: vec![vertex([100.0; 3])],
+                indices: vec![0],
+            },
+        };
+
+        let mesh = combine_solid(&data);
+        assert_eq!(mesh.vertices.len(), 3);
+        // Cutout indices point past the opaque vertices
+        assert_eq!(mesh.indices, vec![0, 1, 0, 2, 2]);
+        assert_eq!(mesh.opaque_indices, 3);
+        // Translucent blocks aren't part of it
+        assert_eq!(mesh.min, [-1.0, 1.0, 0.0]);
+        assert_eq!(mesh.max, [4.0, 5.0, 2.0]);
+    }
+}
@@ -0,0 +1,60 @@
+// Builds the depth pyramid used for occlusion culling. Every texel keeps
+// the furthest depth of the texels under it in the level below.
+
+@group(0) @binding(0)
+var depth: texture_depth_2d;
+@group(0) @binding(1)
+var src: texture_2d<f32>;
+@group(0) @binding(2)
+var dst: texture_storage_2d<r32float, write>;
+
+// Odd sizes leave a texel over at the end of a row or column, the last
+// texel of the level takes it in as well
+fn footprint(id: u32, src_size: u32, dst_size: u32) -> u32 {
+    if id == dst_size - 1u && (src_size & 1u) == 1u && src_size > 1u {
+        return 3u;
+    }
+    return 2u;
+}
+
+// The first level comes from the depth buffer
+@compute @workgroup_size(8, 8)
+fn cs_from_depth(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dst_size = textureDimensions(dst);
+    if id.x >= dst_size.x || id.y >= dst_size.y {
+        return;
+    }
+
+    let src_size = textureDimensions(depth);
+    let nx = footprint(id.x, src_size.x, dst_size.x);
+    let ny = footprint(id.y, src_size.y, dst_size.y);
+    var furthest = 0.0;
+    for (var y = 0u; y < ny; y++) {
+        for (var x = 0u; x < nx; x++) {
+            let texel = min(id.xy * 2u + vec2<u32>(x, y), src_size - 1u);
+            furthest = max(furthest, textureLoad(depth, texel, 0));
+        }
+    }
+    textureStore(dst, id.xy, vec4<f32>(furthest, 0.0, 0.0, 0.0));
+}
+
+// Every level after that comes from the one before it
+@compute @workgroup_size(8, 8)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dst_size = textureDimensions(dst);
+    if id.x >= dst_size.x || id.y >= dst_size.y {
+        return;
+    }
+
+    let src_size = textureDimensions(src);
+    let nx = footprint(id.x, src_size.x, dst_size.x);
+    let ny = footprint(id.y, src_size.y, dst_size.y);
+    var furthest = 0.0;
+    for (var y = 0u; y < ny; y++) {
+        for (var x = 0u; x < nx; x++) {
+            let texel = min(id.xy * 2u + vec2<u32>(x, y), src_size - 1u);
+            furthest = max(furthest, textureLoad(src, texel, 0).r);
+        }
+    }
+    textureStore(dst, id.xy, vec4<f32>(furthest, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,805 @@
+use crate::{
+    inventory::{Inventory, InventoryScreen, ItemStack, HOTBAR_SLOTS, INVENTORY_SLOTS},
+    model::Vertex,
+    player::Vitals,
+    texture,
+};
+use glyphon::{
+    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache,
+    TextArea, TextAtlas, TextBounds, TextRenderer, Viewport,
+};
+
+// All layout values are in logical pixels, and get scaled by the
+// window scale factor when the quads are built.
+const CROSSHAIR_LENGTH: f32 = 20.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+const SLOT_SIZE: f32 = 40.0;
+const SLOT_GAP: f32 = 4.0;
+const SLOT_BORDER: f32 = 3.0;
+const ICON_INSET: f32 = 7.0;
+const HOTBAR_MARGIN: f32 = 10.0;
+const BAR_HEIGHT: f32 = 8.0;
+const BAR_GAP: f32 = 6.0;
+const INVENTORY_ROW_GAP: f32 = 12.0;
+const COUNT_FONT_SIZE: f32 = 14.0;
+const PAUSED_TEXT: &str = "Paused";
+
+const CROSSHAIR_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+const SLOT_COLOUR: [f32; 4] = [0.1, 0.1, 0.1, 0.6];
+const SELECTED_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+const BAR_BACKGROUND_COLOUR: [f32; 4] = [0.1, 0.1, 0.1, 0.6];
+const HEALTH_COLOUR: [f32; 4] = [0.8, 0.1, 0.1, 1.0];
+const HUNGER_COLOUR: [f32; 4] = [0.75, 0.5, 0.15, 1.0];
+const SCREEN_SHADE_COLOUR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+// How full a status bar is
+fn fraction(value: u32, max: u32) -> f32 {
+    if max == 0 {
+        return 0.0;
+    }
+
+    value.min(max) as f32 / max as f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+
+    fn inset(&self, amount: f32) -> Self {
+        Self::new(
+            self.x + amount,
+            self.y + amount,
+            (self.w - 2.0 * amount).max(0.0),
+            (self.h - 2.0 * amount).max(0.0),
+        )
+    }
+}
+
+// Works out where everything on the HUD goes. The window size is
+// given in physical pixels, and converted to logical pixels so
+// that the HUD is the same size on high DPI displays.
+pub struct HudLayout {
+    width: f32,
+    height: f32,
+    scale_factor: f32,
+}
+
+impl HudLayout {
+    pub fn new(physical_width: u32, physical_height: u32, scale_factor: f64) -> Self {
+        let scale_factor = scale_factor as f32;
+        Self {
+            width: physical_width as f32 / scale_factor,
+            height: physical_height as f32 / scale_factor,
+            scale_factor,
+        }
+    }
+
+    pub fn crosshair(&self) -> [Rect; 2] {
+        let cx = self.width / 2.0;
+        let cy = self.height / 2.0;
+
+        [
+            Rect::new(
+                cx - CROSSHAIR_LENGTH / 2.0,
+                cy - CROSSHAIR_THICKNESS / 2.0,
+                CROSSHAIR_LENGTH,
+                CROSSHAIR_THICKNESS,
+            ),
+            Rect::new(
+                cx - CROSSHAIR_THICKNESS / 2.0,
+                cy - CROSSHAIR_LENGTH / 2.0,
+                CROSSHAIR_THICKNESS,
+                CROSSHAIR_LENGTH,
+            ),
+        ]
+    }
+
+    // Centred on the screen, for text `text_width` wide
+    pub fn paused_label(&self, text_width: f32) -> Rect {
+        Rect::new(
+            (self.width - text_width) / 2.0,
+            (self.height - COUNT_FONT_SIZE) / 2.0,
+            text_width,
+            COUNT_FONT_SIZE + 2.0,
+        )
+    }
+
+    fn hotbar_width() -> f32 {
+        HOTBAR_SLOTS as f32 * SLOT_SIZE + (HOTBAR_SLOTS - 1) as f32 * SLOT_GAP
+    }
+
+    pub fn hotbar_slot(&self, slot: usize) -> Rect {
+        let left = (self.width - Self::hotbar_width()) / 2.0;
+        let top = self.height - HOTBAR_MARGIN - SLOT_SIZE;
+
+        Rect::new(
+            left + slot as f32 * (SLOT_SIZE + SLOT_GAP),
+            top,
+            SLOT_SIZE,
+            SLOT_SIZE,
+        )
+    }
+
+    // Health sits above the left half of the hotbar, hunger
+    // above the right half
+    pub fn health_bar(&self) -> Rect {
+        let first = self.hotbar_slot(0);
+        let width = (Self::hotbar_width() - BAR_GAP) / 2.0;
+
+        Rect::new(first.x, first.y - BAR_GAP - BAR_HEIGHT, width, BAR_HEIGHT)
+    }
+
+    pub fn hunger_bar(&self) -> Rect {
+        let health = self.health_bar();
+
+        Rect::new(health.x + health.w + BAR_GAP, health.y, health.w, health.h)
+    }
+
+    // The inventory screen is a grid of HOTBAR_SLOTS wide rows centred
+    // on the screen. The hotbar is the bottom row, split off from the
+    // rest of the inventory by a small gap.
+    pub fn inventory_slot(&self, slot: usize) -> Rect {
+        let rows = INVENTORY_SLOTS / HOTBAR_SLOTS;
+        let grid_height =
+            rows as f32 * SLOT_SIZE + (rows - 1) as f32 * SLOT_GAP + INVENTORY_ROW_GAP;
+        let left = (self.width - Self::hotbar_width()) / 2.0;
+        let top = (self.height - grid_height) / 2.0;
+
+        let col = slot % HOTBAR_SLOTS;
+        let (row, gap) = if slot < HOTBAR_SLOTS {
+            (rows - 1, INVENTORY_ROW_GAP)
+        } else {
+            (slot / HOTBAR_SLOTS - 1, 0.0)
+        };
+
+        Rect::new(
+            left + col as f32 * (SLOT_SIZE + SLOT_GAP),
+            top + row as f32 * (SLOT_SIZE + SLOT_GAP) + gap,
+            SLOT_SIZE,
+            SLOT_SIZE,
+        )
+    }
+
+    // Finds the inventory slot under a point given in physical pixels
+    pub fn inventory_slot_at(&self, physical_x: f32, physical_y: f32) -> Option<usize> {
+        let x = physical_x / self.scale_factor;
+        let y = physical_y / self.scale_factor;
+
+        (0..INVENTORY_SLOTS).find(|&slot| self.inventory_slot(slot).contains(x, y))
+    }
+
+    pub fn to_ndc(&self, x: f32, y: f32) -> [f32; 2] {
+        let px = x * self.scale_factor;
+        let py = y * self.scale_factor;
+        let width = self.width * self.scale_factor;
+        let height = self.height * self.scale_factor;
+
+        [px / width * 2.0 - 1.0, 1.0 - py / height * 2.0]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HudVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    colour: [f32; 4],
+    tex_idx: u32,
+    textured: u32,
+}
+
+impl Vertex for HudVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<HudVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // position
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                // tex_coords
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                // colour
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                // tex_idx
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                // textured
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+            ],
+        }
+    }
+}
+
+struct HudQuad {
+    rect: Rect,
+    colour: [f32; 4],
+    tex_idx: Option<u32>,
+}
+
+impl HudQuad {
+    fn solid(rect: Rect, colour: [f32; 4]) -> Self {
+        Self {
+            rect,
+            colour,
+            tex_idx: None,
+        }
+    }
+
+    fn vertices(&self, layout: &HudLayout) -> [HudVertex; 6] {
+        let r = self.rect;
+        let (tex_idx, textured) = match self.tex_idx {
+            Some(idx) => (idx, 1),
+            None => (0, 0),
+        };
+        let vertex = |x: f32, y: f32, u: f32, v: f32| HudVertex {
+            position: layout.to_ndc(x, y),
+            tex_coords: [u, v],
+            colour: self.colour,
+            tex_idx,
+            textured,
+        };
+
+        let top_left = vertex(r.x, r.y, 0.0, 0.0);
+        let top_right = vertex(r.x + r.w, r.y, 1.0, 0.0);
+        let bottom_left = vertex(r.x, r.y + r.h, 0.0, 1.0);
+        let bottom_right = vertex(r.x + r.w, r.y + r.h, 1.0, 1.0);
+
+        [
+            top_left,
+            bottom_left,
+            top_right,
+            top_right,
+            bottom_left,
+            bottom_right,
+        ]
+    }
+}
+
+// Item counts are drawn as text over the slot icons
+struct CountText {
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    renderer: TextRenderer,
+    labels: Vec<(Buffer, Rect)>,
+}
+
+impl CountText {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        let cache = Cache::new(device);
+        let viewport = Viewport::new(device, &cache);
+        let mut atlas = TextAtlas::new(device, queue, &cache, format);
+        let renderer =
+            TextRenderer::new(&mut atlas, device, wgpu::MultisampleState::default(), None);
+
+        Self {
+            font_system,
+            swash_cache,
+            viewport,
+            atlas,
+            renderer,
+            labels: Vec::new(),
+        }
+    }
+
+    // How wide `text` comes out on one line, in logical pixels
+    fn measure(&mut self, text: &str) -> f32 {
+        let mut buffer = Buffer::new(
+            &mut self.font_system,
+            Metrics::new(COUNT_FONT_SIZE, COUNT_FONT_SIZE),
+        );
+        buffer.set_size(&mut self.font_system, None, None);
+        buffer.set_text(
+            &mut self.font_system,
+            text,
+            &Attrs::new().family(Family::SansSerif),
+            Shaping::Basic,
+        );
+        buffer.shape_until_scroll(&mut self.font_system, false);
+
+        // Rounded up so the label's bounds don't wrap the last glyph
+        buffer
+            .layout_runs()
+            .map(|run| run.line_w)
+            .fold(0.0, f32::max)
+            .ceil()
+    }
+
+    fn set_labels(&mut self, labels: Vec<(String, Rect)>, scale_factor: f32) {
+        self.labels = labels
+            .into_iter()
+            .map(|(txt, rect)| {
+                let mut buffer = Buffer::new(
+                    &mut self.font_system,
+                    Metrics::new(COUNT_FONT_SIZE, COUNT_FONT_SIZE),
+                );
+                buffer.set_size(
+                    &mut self.font_system,
+                    Some(rect.w * scale_factor),
+                    Some(rect.h * scale_factor),
+                );
+                buffer.set_text(
+                    &mut self.font_system,
+                    &txt,
+                    &Attrs::new().family(Family::SansSerif),
+                    Shaping::Basic,
+                );
+                buffer.shape_until_scroll(&mut self.font_system, false);
+                (buffer, rect)
+            })
+            .collect();
+    }
+
+    fn render(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        queue: &wgpu::Queue,
+        pass: &mut wgpu::RenderPass,
+        scale_factor: f32,
+    ) {
+        self.viewport.update(
+            queue,
+            Resolution {
+                width: config.width,
+                height: config.height,
+            },
+        );
+
+        let areas = self.labels.iter().map(|(buffer, rect)| TextArea {
+            buffer,
+            left: rect.x * scale_factor,
+            top: rect.y * scale_factor,
+            scale: scale_factor,
+            bounds: TextBounds {
+                left: (rect.x * scale_factor) as i32,
+                top: (rect.y * scale_factor) as i32,
+                right: ((rect.x + rect.w) * scale_factor) as i32,
+                bottom: ((rect.y + rect.h) * scale_factor) as i32,
+            },
+            default_color: Color::rgb(255, 255, 255),
+            custom_glyphs: &[],
+        });
+
+        let _ = self.renderer.prepare(
+            device,
+            queue,
+            &mut self.font_system,
+            &mut self.atlas,
+            &self.viewport,
+            areas,
+            &mut self.swash_cache,
+        );
+
+        let _ = self.renderer.render(&self.atlas, &self.viewport, pass);
+
+        self.atlas.trim();
+    }
+}
+
+// What the HUD shows this frame
+pub struct HudFrame<'a> {
+    pub inventory: &'a Inventory,
+    pub screen: &'a InventoryScreen,
+    pub vitals: Vitals,
+    pub paused: bool,
+    // In physical pixels
+    pub cursor: Option<(f32, f32)>,
+}
+
+// Controls the screen-space overlay drawn on top of the world:
+// crosshair, hotbar, status bars and the inventory screen
+pub struct Hud {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    // How many vertices the buffer has room for
+    vertex_capacity: usize,
+    n_vertices: u32,
+    counts: CountText,
+    scale_factor: f32,
+    // "Paused" as laid out in the HUD font, in logical pixels
+    paused_width: f32,
+}
+
+impl Hud {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        queue: &wgpu::Queue,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("HUD Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("hud.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("HUD Pipeline Layout"),
+            bind_group_layouts: &[texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("HUD Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[HudVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        // Enough for the hotbar and bars, it grows when the inventory opens
+        let vertex_capacity = 256;
+
+        let mut counts = CountText::new(device, queue, config.format);
+        let paused_width = counts.measure(PAUSED_TEXT);
+
+        Self {
+            pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, vertex_capacity),
+            vertex_capacity,
+            n_vertices: 0,
+            counts,
+            scale_factor: 1.0,
+            paused_width,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<HudVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Pushes the slot background, icon and count label for a stack
+    fn push_slot(
+        quads: &mut Vec<HudQuad>,
+        labels: &mut Vec<(String, Rect)>,
+        rect: Rect,
+        stack: Option<&ItemStack>,
+        selected: bool,
+        texture_manager: &texture::TextureManager,
+    ) {
+        if selected {
+            quads.push(HudQuad::solid(rect, SELECTED_COLOUR));
+            quads.push(HudQuad::solid(rect.inset(SLOT_BORDER), SLOT_COLOUR));
+        } else {
+            quads.push(HudQuad::solid(rect, SLOT_COLOUR));
+        }
+
+        if let Some(stack) = stack {
+            Self::push_stack(quads, labels, rect, stack, texture_manager);
+        }
+    }
+
+    fn push_stack(
+        quads: &mut Vec<HudQuad>,
+        labels: &mut Vec<(String, Rect)>,
+        rect: Rect,
+        stack: &ItemStack,
+        texture_manager: &texture::TextureManager,
+    ) {
+        if let Some(idx) = texture_manager.lookup_idx(stack.item.tex_label()) {
+            quads.push(HudQuad {
+                rect: rect.inset(ICON_INSET),
+                colour: WHITE,
+                tex_idx: Some(idx as u32),
+            });
+        }
+
+        if stack.count > 1 {
+            // bottom right corner of the slot
+            let label = Rect::new(
+                rect.x + rect.w / 2.0,
+                rect.y + rect.h - COUNT_FONT_SIZE - 2.0,
+                rect.w / 2.0,
+                COUNT_FONT_SIZE + 2.0,
+            );
+            labels.push((stack.count.to_string(), label));
+        }
+    }
+
+    fn build_quads(
+        &self,
+        layout: &HudLayout,
+        frame: &HudFrame,
+        texture_manager: &texture::TextureManager,
+    ) -> (Vec<HudQuad>, Vec<(String, Rect)>) {
+        let HudFrame {
+            inventory,
+            screen,
+            vitals,
+            paused,
+            cursor,
+        } = *frame;
+        let mut quads = Vec::new();
+        let mut labels = Vec::new();
+
+        if !screen.open && !paused {
+            for rect in layout.crosshair() {
+                quads.push(HudQuad::solid(rect, CROSSHAIR_COLOUR));
+            }
+        }
+
+        for i in 0..HOTBAR_SLOTS {
+            Self::push_slot(
+                &mut quads,
+                &mut labels,
+                layout.hotbar_slot(i),
+                inventory.get(i),
+                i == inventory.selected(),
+                texture_manager,
+            );
+        }
+
+        let bars = [
+            (
+                layout.health_bar(),
+                fraction(vitals.health, vitals.max_health),
+                HEALTH_COLOUR,
+            ),
+            (
+                layout.hunger_bar(),
+                fraction(vitals.hunger, vitals.max_hunger),
+                HUNGER_COLOUR,
+            ),
+        ];
+
+        for (rect, fraction, colour) in bars {
+            quads.push(HudQuad::solid(rect, BAR_BACKGROUND_COLOUR));
+            quads.push(HudQuad::solid(
+                Rect::new(rect.x, rect.y, rect.w * fraction, rect.h),
+                colour,
+            ));
+        }
+
+        if screen.open {
+            quads.push(HudQuad::solid(
+                Rect::new(0.0, 0.0, layout.width, layout.height),
+                SCREEN_SHADE_COLOUR,
+            ));
+
+            for i in 0..INVENTORY_SLOTS {
+                Self::push_slot(
+                    &mut quads,
+                    &mut labels,
+                    layout.inventory_slot(i),
+                    inventory.get(i),
+                    false,
+                    texture_manager,
+                );
+            }
+
+            // The held stack follows the cursor
+            if let (Some(held), Some((x, y))) = (screen.held.as_ref(), cursor) {
+                let rect = Rect::new(
+                    x / layout.scale_factor - SLOT_SIZE / 2.0,
+                    y / layout.scale_factor - SLOT_SIZE / 2.0,
+                    SLOT_SIZE,
+                    SLOT_SIZE,
+                );
+                Self::push_stack(&mut quads, &mut labels, rect, held, texture_manager);
+            }
+        }
+
+        // Over everything else, inventory included
+        if paused {
+            quads.push(HudQuad::solid(
+                Rect::new(0.0, 0.0, layout.width, layout.height),
+                SCREEN_SHADE_COLOUR,
+            ));
+            labels.push((
+                PAUSED_TEXT.to_string(),
+                layout.paused_label(self.paused_width),
+            ));
+        }
+
+        (quads, labels)
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        scale_factor: f64,
+        frame: &HudFrame,
+        texture_manager: &texture::TextureManager,
+    ) {
+        let layout = HudLayout::new(config.width, config.height, scale_factor);
+        let (quads, labels) = self.build_quads(&layout, frame, texture_manager);
+        let vertices = quads
+            .iter()
+            .flat_map(|q| q.vertices(&layout))
+            .collect::<Vec<_>>();
+
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.vertex_capacity);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        self.n_vertices = vertices.len() as u32;
+        self.scale_factor = layout.scale_factor;
+        self.counts.set_labels(labels, layout.scale_factor);
+    }
+
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        texture_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.n_vertices == 0 {
+            return;
+        }
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("hud_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, texture_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..self.n_vertices, 0..1);
+
+        self.counts
+            .render(device, config, queue, &mut pass, self.scale_factor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_respects_scale_factor() {
+        // The same logical window at different scale factors should
+        // place everything at the same NDC coordinates
+        let low_dpi = HudLayout::new(800, 600, 1.0);
+        let high_dpi = HudLayout::new(1600, 1200, 2.0);
+
+        assert_eq!(low_dpi.hotbar_slot(3), high_dpi.hotbar_slot(3));
+        assert_eq!(low_dpi.crosshair(), high_dpi.crosshair());
+
+        let slot = low_dpi.hotbar_slot(0);
+        assert_eq!(
+            low_dpi.to_ndc(slot.x, slot.y),
+            high_dpi.to_ndc(slot.x, slot.y)
+        );
+    }
+
+    #[test]
+    fn test_layout_positions() {
+        let layout = HudLayout::new(800, 600, 1.0);
+
+        // crosshair is centred
+        let [horizontal, vertical] = layout.crosshair();
+        assert_eq!(horizontal.x + horizontal.w / 2.0, 400.0);
+        assert_eq!(vertical.y + vertical.h / 2.0, 300.0);
+
+        // so is the pause label
+        let paused = layout.paused_label(50.0);
+        assert_eq!(paused.x + paused.w / 2.0, 400.0);
+        assert!(paused.contains(400.0, 300.0));
+
+        // hotbar is centred horizontally and sits on the bottom edge
+        let first = layout.hotbar_slot(0);
+        let last = layout.hotbar_slot(HOTBAR_SLOTS - 1);
+        assert_eq!(first.x, 800.0 - (last.x + last.w));
+        assert_eq!(first.y + first.h, 600.0 - HOTBAR_MARGIN);
+
+        // status bars sit above the hotbar without overlapping
+        let health = layout.health_bar();
+        let hunger = layout.hunger_bar();
+        assert!(health.y + health.h < first.y);
+        assert!(health.x + health.w < hunger.x);
+
+        assert_eq!(layout.to_ndc(0.0, 0.0), [-1.0, 1.0]);
+        assert_eq!(layout.to_ndc(800.0, 600.0), [1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_inventory_slot_hit_testing() {
+        let layout = HudLayout::new(1600, 1200, 2.0);
+
+        for slot in 0..INVENTORY_SLOTS {
+            let rect = layout.inventory_slot(slot);
+            let centre = ((rect.x + rect.w / 2.0) * 2.0, (rect.y + rect.h / 2.0) * 2.0);
+            assert_eq!(layout.inventory_slot_at(centre.0, centre.1), Some(slot));
+        }
+
+        // the hotbar row sits below the rest of the inventory
+        assert!(layout.inventory_slot(0).y > layout.inventory_slot(INVENTORY_SLOTS - 1).y);
+
+        // gaps between slots don't hit anything
+        let first = layout.inventory_slot(0);
+        assert_eq!(
+            layout.inventory_slot_at((first.x + first.w + SLOT_GAP / 2.0) * 2.0, first.y * 2.0),
+            None
+        );
+        assert_eq!(layout.inventory_slot_at(0.0, 0.0), None);
+    }
+}
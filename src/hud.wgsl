@@ -0,0 +1,44 @@
+// Vertex shader
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) colour: vec4<f32>,
+    @location(3) tex_idx: u32,
+    @location(4) textured: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+    @location(2) @interpolate(flat) tex_idx: u32,
+    @location(3) @interpolate(flat) textured: u32,
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.colour = model.colour;
+    out.tex_idx = model.tex_idx;
+    out.textured = model.textured;
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var texture_array: texture_2d_array<f32>;
+@group(0) @binding(1)
+var tex_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sample unconditionally so the sample stays in uniform control flow
+    let sampled = textureSample(texture_array, tex_sampler, in.tex_coords, in.tex_idx);
+    if in.textured == 1u {
+        return sampled * in.colour;
+    }
+    return in.colour;
+}
@@ -0,0 +1,570 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use winit::{
+    event::{ElementState, MouseButton},
+    keyboard::KeyCode,
+};
+
+// Rebinds controls, one `action binding` per line, e.g.
+//
+// move_forward KeyW
+// move_forward ArrowUp
+// break MouseLeft
+// sensitivity 0.004
+// invert_y true
+//
+// An action listed here loses all its default bindings, anything not
+// listed keeps them. `sensitivity` and `invert_y` set how the mouse
+// turns the camera.
+pub const CONTROLS_FILE: &str = "controls.txt";
+
+// Something the player can do, whatever it's bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sneak,
+    Break,
+    Place,
+    ToggleInventory,
+    ToggleDebug,
+    OpenCommandLine,
+    // Backs out of the inventory, or pauses the game
+    Pause,
+    // Only while paused
+    Quit,
+    Hotbar1,
+    Hotbar2,
+    Hotbar3,
+    Hotbar4,
+    Hotbar5,
+    Hotbar6,
+    Hotbar7,
+    Hotbar8,
+    Hotbar9,
+}
+
+impl Action {
+    pub const HOTBAR: [Action; 9] = [
+        Action::Hotbar1,
+        Action::Hotbar2,
+        Action::Hotbar3,
+        Action::Hotbar4,
+        Action::Hotbar5,
+        Action::Hotbar6,
+        Action::Hotbar7,
+        Action::Hotbar8,
+        Action::Hotbar9,
+    ];
+
+    // As written in the controls file
+    fn from_name(name: &str) -> Option<Self> {
+        let action = match name {
+            "move_forward" => Action::MoveForward,
+            "move_back" => Action::MoveBack,
+            "move_left" => Action::MoveLeft,
+            "move_right" => Action::MoveRight,
+            "jump" => Action::Jump,
+            "sneak" => Action::Sneak,
+            "break" => Action::Break,
+            "place" => Action::Place,
+            "inventory" => Action::ToggleInventory,
+            "debug" => Action::ToggleDebug,
+            "command" => Action::OpenCommandLine,
+            "pause" => Action::Pause,
+            "quit" => Action::Quit,
+            _ => {
+                let slot = name.strip_prefix("hotbar_")?.parse::<usize>().ok()?;
+                *Self::HOTBAR.get(slot.checked_sub(1)?)?
+            }
+        };
+        Some(action)
+    }
+}
+
+// A key or mouse button that actions can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+// Keys the controls file can name, by their winit names
+const NAMED_KEYS: [KeyCode; 76] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Escape,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::CapsLock,
+    KeyCode::Slash,
+    KeyCode::Backslash,
+    KeyCode::Period,
+    KeyCode::Comma,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Backquote,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::Delete,
+];
+
+impl Binding {
+    // Keys go by their winit names, e.g. `KeyW` or `ShiftLeft`, and
+    // mouse buttons are `MouseLeft`, `MouseRight` and `MouseMiddle`
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            _ => NAMED_KEYS
+                .into_iter()
+                .find(|key| format!("{key:?}") == name)
+                .map(Binding::Key),
+        }
+    }
+}
+
+// How the mouse turns the camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseLook {
+    // Radians per pixel the mouse moves
+    pub sensitivity: f32,
+    // Moving the mouse up looks down
+    pub invert_y: bool,
+}
+
+impl Default for MouseLook {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.004,
+            invert_y: false,
+        }
+    }
+}
+
+// Which actions each key and button sets off
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    actions: HashMap<Binding, Vec<Action>>,
+    pub look: MouseLook,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self {
+            actions: HashMap::new(),
+            look: MouseLook::default(),
+        };
+
+        let keys = [
+            (Action::MoveForward, KeyCode::KeyW),
+            (Action::MoveForward, KeyCode::ArrowUp),
+            (Action::MoveBack, KeyCode::KeyS),
+            (Action::MoveBack, KeyCode::ArrowDown),
+            (Action::MoveLeft, KeyCode::KeyA),
+            (Action::MoveLeft, KeyCode::ArrowLeft),
+            (Action::MoveRight, KeyCode::KeyD),
+            (Action::MoveRight, KeyCode::ArrowRight),
+            (Action::Jump, KeyCode::Space),
+            (Action::Sneak, KeyCode::ShiftLeft),
+            (Action::ToggleInventory, KeyCode::KeyE),
+            (Action::ToggleDebug, KeyCode::F1),
+            (Action::OpenCommandLine, KeyCode::Slash),
+            (Action::Pause, KeyCode::Escape),
+            (Action::Quit, KeyCode::KeyQ),
+        ];
+        for (action, key) in keys {
+            bindings.bind(action, Binding::Key(key));
+        }
+
+        let digits = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+        ];
+        for (action, key) in Action::HOTBAR.into_iter().zip(digits) {
+            bindings.bind(action, Binding::Key(key));
+        }
+
+        bindings.bind(Action::Break, Binding::Mouse(MouseButton::Left));
+        bindings.bind(Action::Place, Binding::Mouse(MouseButton::Right));
+
+        bindings
+    }
+}
+
+impl Bindings {
+    fn bind(&mut self, action: Action, binding: Binding) {
+        let actions = self.actions.entry(binding).or_default();
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    }
+
+    fn unbind_all(&mut self, action: Action) {
+        for actions in self.actions.values_mut() {
+            actions.retain(|a| *a != action);
+        }
+        self.actions.retain(|_, actions| !actions.is_empty());
+    }
+
+    // The defaults, with anything in the file replacing them. Lines it
+    // can't make sense of are skipped with a warning.
+    fn parse(contents: &str) -> Self {
+        let mut bindings = Self::default();
+        let mut replaced = HashSet::new();
+
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let Some((name, binding)) = line.trim().split_once(' ') else {
+                log::warn!("Missing a key for {} in controls", line.trim());
+                continue;
+            };
+            match name {
+                "sensitivity" => {
+                    match binding.trim().parse::<f32>() {
+                        Ok(sensitivity) if sensitivity > 0.0 => {
+                            bindings.look.sensitivity = sensitivity
+                        }
+                        _ => log::warn!("Bad mouse sensitivity {binding} in controls"),
+                    }
+                    continue;
+                }
+                "invert_y" => {
+                    match binding.trim().parse::<bool>() {
+                        Ok(invert_y) => bindings.look.invert_y = invert_y,
+                        Err(_) => log::warn!("Bad invert_y {binding} in controls"),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some(action) = Action::from_name(name) else {
+                log::warn!("Unknown action {name} in controls");
+                continue;
+            };
+            let Some(binding) = Binding::from_name(binding.trim()) else {
+                log::warn!("Unknown key {binding} for {name} in controls");
+                continue;
+            };
+
+            if replaced.insert(action) {
+                bindings.unbind_all(action);
+            }
+            bindings.bind(action, binding);
+        }
+
+        bindings
+    }
+
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn actions(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.actions.get(&binding).into_iter().flatten().copied()
+    }
+}
+
+// What the actions did over one tick. An action pressed and let go
+// again within the tick is pressed and released, but not held.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionState {
+    held: HashSet<Action>,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+
+    // Went down during the tick
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    // Came up during the tick
+    pub fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+
+    // 1 when only `positive` is held, -1 when only `negative` is
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+    }
+
+    pub fn press(&mut self, action: Action) {
+        if self.held.insert(action) {
+            self.pressed.insert(action);
+        }
+    }
+
+    pub fn release(&mut self, action: Action) {
+        if self.held.remove(&action) {
+            self.released.insert(action);
+        }
+    }
+}
+
+// Turns key and mouse button events into actions, ready to be taken
+// once a tick
+pub struct Controls {
+    bindings: Bindings,
+    // Keys and buttons that are down right now
+    down: HashSet<Binding>,
+    state: ActionState,
+}
+
+impl Controls {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            down: HashSet::new(),
+            state: ActionState::default(),
+        }
+    }
+
+    // Returns the actions that went down because of this event, for
+    // anything that needs to react before the next tick
+    pub fn process(&mut self, binding: Binding, state: ElementState) -> Vec<Action> {
+        match state {
+            ElementState::Pressed => {
+                // Held keys repeat, but that's not a new press
+                if !self.down.insert(binding) {
+                    return Vec::new();
+                }
+
+                let pressed = self
+                    .bindings
+                    .actions(binding)
+                    .filter(|action| !self.state.held(*action))
+                    .collect::<Vec<_>>();
+                for action in &pressed {
+                    self.state.press(*action);
+                }
+                pressed
+            }
+            ElementState::Released => {
+                if !self.down.remove(&binding) {
+                    return Vec::new();
+                }
+
+                // Still held through anything else bound to it
+                for action in self.bindings.actions(binding) {
+                    let still_down = self
+                        .down
+                        .iter()
+                        .any(|other| self.bindings.actions(*other).any(|a| a == action));
+                    if !still_down {
+                        self.state.release(action);
+                    }
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn process_key(&mut self, key: KeyCode, state: ElementState) -> Vec<Action> {
+        self.process(Binding::Key(key), state)
+    }
+
+    pub fn process_mouse_button(
+        &mut self,
+        button: MouseButton,
+        state: ElementState,
+    ) -> Vec<Action> {
+        self.process(Binding::Mouse(button), state)
+    }
+
+    // Everything the actions did since the last tick. Held actions carry
+    // on into the next one.
+    pub fn take_tick(&mut self) -> ActionState {
+        let tick = self.state.clone();
+        self.state.pressed.clear();
+        self.state.released.clear();
+        tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESS: ElementState = ElementState::Pressed;
+    const RELEASE: ElementState = ElementState::Released;
+
+    #[test]
+    fn test_pressed_held_released_per_tick() {
+        let mut controls = Controls::new(Bindings::default());
+
+        assert_eq!(
+            controls.process_key(KeyCode::KeyW, PRESS),
+            [Action::MoveForward]
+        );
+        let tick = controls.take_tick();
+        assert!(tick.pressed(Action::MoveForward));
+        assert!(tick.held(Action::MoveForward));
+        assert_eq!(tick.axis(Action::MoveForward, Action::MoveBack), 1.0);
+
+        // Key repeat doesn't press it again
+        assert!(controls.process_key(KeyCode::KeyW, PRESS).is_empty());
+        let tick = controls.take_tick();
+        assert!(!tick.pressed(Action::MoveForward));
+        assert!(tick.held(Action::MoveForward));
+
+        controls.process_key(KeyCode::KeyW, RELEASE);
+        let tick = controls.take_tick();
+        assert!(tick.released(Action::MoveForward));
+        assert!(!tick.held(Action::MoveForward));
+        assert_eq!(controls.take_tick(), ActionState::default());
+    }
+
+    #[test]
+    fn test_quick_click_isnt_lost() {
+        let mut controls = Controls::new(Bindings::default());
+
+        // Down and up again between two ticks
+        controls.process_mouse_button(MouseButton::Left, PRESS);
+        controls.process_mouse_button(MouseButton::Left, RELEASE);
+        let tick = controls.take_tick();
+        assert!(tick.pressed(Action::Break));
+        assert!(tick.released(Action::Break));
+        assert!(!tick.held(Action::Break));
+    }
+
+    #[test]
+    fn test_action_held_through_either_binding() {
+        let mut controls = Controls::new(Bindings::default());
+
+        controls.process_key(KeyCode::KeyW, PRESS);
+        assert!(controls.process_key(KeyCode::ArrowUp, PRESS).is_empty());
+        controls.process_key(KeyCode::KeyW, RELEASE);
+        assert!(controls.take_tick().held(Action::MoveForward));
+
+        controls.process_key(KeyCode::ArrowUp, RELEASE);
+        assert!(!controls.take_tick().held(Action::MoveForward));
+    }
+
+    #[test]
+    fn test_controls_file() {
+        let bindings = Bindings::parse(
+            "move_forward KeyZ\n\
+             move_forward MouseMiddle\n\
+             hotbar_1 KeyR\n\
+             fly_away Space\n\
+             jump NotAKey\n\
+             move_back\n",
+        );
+        let actions = |binding| bindings.actions(binding).collect::<Vec<_>>();
+
+        // Rebinding an action drops its defaults
+        assert_eq!(actions(Binding::Key(KeyCode::KeyZ)), [Action::MoveForward]);
+        assert_eq!(
+            actions(Binding::Mouse(MouseButton::Middle)),
+            [Action::MoveForward]
+        );
+        assert!(actions(Binding::Key(KeyCode::KeyW)).is_empty());
+        assert!(actions(Binding::Key(KeyCode::ArrowUp)).is_empty());
+        assert_eq!(actions(Binding::Key(KeyCode::KeyR)), [Action::Hotbar1]);
+        assert!(actions(Binding::Key(KeyCode::Digit1)).is_empty());
+
+        // Anything it doesn't understand is skipped, keeping the defaults
+        assert_eq!(actions(Binding::Key(KeyCode::Space)), [Action::Jump]);
+        assert_eq!(actions(Binding::Key(KeyCode::KeyS)), [Action::MoveBack]);
+    }
+
+    #[test]
+    fn test_mouse_look_settings() {
+        let bindings = Bindings::parse("sensitivity 0.01\ninvert_y true\n");
+        assert_eq!(
+            bindings.look,
+            MouseLook {
+                sensitivity: 0.01,
+                invert_y: true
+            }
+        );
+
+        // Nonsense keeps the defaults
+        let bindings = Bindings::parse("sensitivity -1\ninvert_y sometimes\n");
+        assert_eq!(bindings.look, MouseLook::default());
+    }
+}
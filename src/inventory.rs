@@ -0,0 +1,477 @@
+use crate::chunk::BlockType;
+
+pub const HOTBAR_SLOTS: usize = 9;
+pub const INVENTORY_SLOTS: usize = 36;
+pub const DEFAULT_MAX_STACK: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Item {
+    Block(BlockType),
+}
+
+impl Item {
+    pub fn max_stack_size(&self) -> u32 {
+        match self {
+            Self::Block(_) => DEFAULT_MAX_STACK,
+        }
+    }
+
+    pub fn tex_label(&self) -> &'static str {
+        match self {
+            Self::Block(block_type) => block_type.tex_label(),
+        }
+    }
+
+    pub fn as_block(&self) -> Option<BlockType> {
+        match self {
+            Self::Block(block_type) => Some(*block_type),
+        }
+    }
+
+    // What ends up in the inventory when a block of this
+    // type is broken
+    pub fn dropped_by(block_type: BlockType) -> Option<ItemStack> {
+        match block_type {
+            BlockType::Dirt
+            | BlockType::Stone
+            | BlockType::Lamp
+            | BlockType::Glass
+            | BlockType::Leaves => Some(ItemStack::new(Self::Block(block_type), 1)),
+            // Doors always come back closed
+            BlockType::Door { .. } => Some(ItemStack::new(
+                Self::Block(BlockType::Door { open: false }),
+                1,
+            )),
+            // Breaking water just clears it, there is nothing to pick up
+            BlockType::Water => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ItemStack {
+    pub item: Item,
+    pub count: u32,
+}
+
+impl ItemStack {
+    pub fn new(item: Item, count: u32) -> Self {
+        Self { item, count }
+    }
+
+    pub fn max_size(&self) -> u32 {
+        self.item.max_stack_size()
+    }
+
+    pub fn space(&self) -> u32 {
+        self.max_size().saturating_sub(self.count)
+    }
+
+    // Moves as much of `other` into this stack as will fit, and
+    // returns whatever is left over
+    pub fn merge(&mut self, other: ItemStack) -> Option<ItemStack> {
+        if other.item != self.item {
+            return Some(other);
+        }
+
+        let moved = other.count.min(self.space());
+        self.count += moved;
+
+        let left = other.count - moved;
+        if left > 0 {
+            Some(ItemStack::new(other.item, left))
+        } else {
+            None
+        }
+    }
+
+    // Takes up to `n` items off this stack. Returns None if
+    // there was nothing to take
+    pub fn take(&mut self, n: u32) -> Option<ItemStack> {
+        let taken = n.min(self.count);
+        if taken == 0 {
+            return None;
+        }
+
+        self.count -= taken;
+        Some(ItemStack::new(self.item, taken))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameMode {
+    #[default]
+    Survival,
+    Creative,
+}
+
+// The player's inventory. The first HOTBAR_SLOTS slots
+// make up the hotbar.
+#[derive(Debug, Clone)]
+pub struct Inventory {
+    slots: [Option<ItemStack>; INVENTORY_SLOTS],
+    selected: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            slots: [None; INVENTORY_SLOTS],
+            selected: 0,
+        }
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot).and_then(|s| s.as_ref())
+    }
+
+    #[cfg(test)]
+    pub fn set(&mut self, slot: usize, stack: Option<ItemStack>) {
+        if slot < INVENTORY_SLOTS {
+            self.slots[slot] = stack.filter(|s| s.count > 0);
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        if slot < HOTBAR_SLOTS {
+            self.selected = slot;
+        }
+    }
+
+    pub fn selected_stack(&self) -> Option<&ItemStack> {
+        self.get(self.selected)
+    }
+
+    pub fn scroll(&mut self, amount: f32) {
+        // Scrolling up moves the selection left, and the selection
+        // wraps around at either end of the hotbar
+        if amount > 0.0 {
+            self.selected = (self.selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+        } else if amount < 0.0 {
+            self.selected = (self.selected + 1) % HOTBAR_SLOTS;
+        }
+    }
+
+    // Adds a stack to the inventory, topping up existing stacks of the
+    // same item before filling empty slots. Returns anything that
+    // didn't fit.
+    pub fn add(&mut self, stack: ItemStack) -> Option<ItemStack> {
+        let mut remaining = Some(stack);
+
+        for slot in self.slots.iter_mut().flatten() {
+            match remaining {
+                Some(r) => remaining = slot.merge(r),
+                None => return None,
+            }
+        }
+
+        for slot in self.slots.iter_mut().filter(|s| s.is_none()) {
+            match remaining {
+                Some(mut r) => {
+                    let max = r.max_size();
+                    *slot = r.take(max);
+                    remaining = if r.count > 0 { Some(r) } else { None };
+                }
+                None => return None,
+            }
+        }
+
+        remaining
+    }
+
+    // Removes up to `n` items from the given slot
+    pub fn take(&mut self, slot: usize, n: u32) -> Option<ItemStack> {
+        let stack = self.slots.get_mut(slot)?.as_mut()?;
+        let taken = stack.take(n);
+
+        if stack.count == 0 {
+            self.slots[slot] = None;
+        }
+
+        taken
+    }
+
+    pub fn take_selected(&mut self, n: u32) -> Option<ItemStack> {
+        self.take(self.selected, n)
+    }
+
+    #[cfg(test)]
+    pub fn count(&self, item: Item) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|s| s.item == item)
+            .map(|s| s.count)
+            .sum()
+    }
+
+    // Left click on a slot in the inventory screen. Picks up the slot
+    // if nothing is held, otherwise puts the held stack down, merging
+    // with or swapping for whatever is already there.
+    pub fn click_slot(&mut self, slot: usize, held: &mut Option<ItemStack>) {
+        if slot >= INVENTORY_SLOTS {
+            return;
+        }
+
+        match (&mut self.slots[slot], held.take()) {
+            (Some(existing), Some(h)) if existing.item == h.item => {
+                *held = existing.merge(h);
+            }
+            (slot_stack, h) => {
+                *held = slot_stack.take();
+                *slot_stack = h;
+            }
+        }
+    }
+
+    // Right click on a slot in the inventory screen. Picks up half the
+    // slot if nothing is held, otherwise puts down a single item.
+    pub fn right_click_slot(&mut self, slot: usize, held: &mut Option<ItemStack>) {
+        if slot >= INVENTORY_SLOTS {
+            return;
+        }
+
+        match held.as_mut() {
+            None => {
+                let half = self.slots[slot].map_or(0, |s| s.count.div_ceil(2));
+                *held = self.take(slot, half);
+            }
+            Some(h) => match self.slots[slot].as_mut() {
+                None => {
+                    self.slots[slot] = h.take(1);
+                }
+                Some(existing) if existing.item == h.item && existing.space() > 0 => {
+                    existing.count += 1;
+                    h.count -= 1;
+                }
+                Some(_) => {}
+            },
+        }
+
+        if held.is_some_and(|h| h.count == 0) {
+            *held = None;
+        }
+    }
+}
+
+// State for the inventory screen, where stacks can be moved
+// between slots with the mouse
+#[derive(Debug, Default)]
+pub struct InventoryScreen {
+    pub open: bool,
+    pub held: Option<ItemStack>,
+}
+
+impl InventoryScreen {
+    // Returns anything that has to be dropped in the world
+    // because of the screen closing
+    pub fn toggle(&mut self, inventory: &mut Inventory) -> Option<ItemStack> {
+        if self.open {
+            self.close(inventory)
+        } else {
+            self.open = true;
+            None
+        }
+    }
+
+    // Closing the screen puts whatever was being held back into
+    // the inventory. Anything that doesn't fit is returned.
+    pub fn close(&mut self, inventory: &mut Inventory) -> Option<ItemStack> {
+        self.open = false;
+        inventory.add(self.held.take()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRT: Item = Item::Block(BlockType::Dirt);
+    const STONE: Item = Item::Block(BlockType::Stone);
+
+    #[test]
+    fn test_stack_merge() {
+        let mut stack = ItemStack::new(DIRT, 60);
+
+        assert_eq!(
+            stack.merge(ItemStack::new(DIRT, 10)),
+            Some(ItemStack::new(DIRT, 6))
+        );
+        assert_eq!(stack.count, DEFAULT_MAX_STACK);
+
+        // different items never merge
+        let mut stack = ItemStack::new(DIRT, 1);
+        assert_eq!(
+            stack.merge(ItemStack::new(STONE, 1)),
+            Some(ItemStack::new(STONE, 1))
+        );
+        assert_eq!(stack.count, 1);
+    }
+
+    #[test]
+    fn test_stack_take() {
+        let mut stack = ItemStack::new(DIRT, 3);
+
+        assert_eq!(stack.take(2), Some(ItemStack::new(DIRT, 2)));
+        assert_eq!(stack.take(5), Some(ItemStack::new(DIRT, 1)));
+        assert_eq!(stack.take(1), None);
+    }
+
+    #[test]
+    fn test_add_merges_then_fills() {
+        let mut inventory = Inventory::new();
+        inventory.set(4, Some(ItemStack::new(DIRT, 60)));
+
+        assert_eq!(inventory.add(ItemStack::new(DIRT, 10)), None);
+        assert_eq!(inventory.get(4), Some(&ItemStack::new(DIRT, 64)));
+
+        // the overflow goes into the first empty slot
+        assert_eq!(inventory.get(0), Some(&ItemStack::new(DIRT, 6)));
+        assert_eq!(inventory.count(DIRT), 70);
+    }
+
+    #[test]
+    fn test_add_splits_oversized_stacks() {
+        let mut inventory = Inventory::new();
+
+        assert_eq!(inventory.add(ItemStack::new(STONE, 100)), None);
+        assert_eq!(inventory.get(0), Some(&ItemStack::new(STONE, 64)));
+        assert_eq!(inventory.get(1), Some(&ItemStack::new(STONE, 36)));
+    }
+
+    #[test]
+    fn test_add_when_full() {
+        let mut inventory = Inventory::new();
+        for slot in 0..INVENTORY_SLOTS {
+            inventory.set(slot, Some(ItemStack::new(STONE, 64)));
+        }
+
+        assert_eq!(
+            inventory.add(ItemStack::new(DIRT, 5)),
+            Some(ItemStack::new(DIRT, 5))
+        );
+    }
+
+    #[test]
+    fn test_take_selected_empties_slot() {
+        let mut inventory = Inventory::new();
+        inventory.set(2, Some(ItemStack::new(DIRT, 1)));
+        inventory.select(2);
+
+        assert_eq!(inventory.take_selected(1), Some(ItemStack::new(DIRT, 1)));
+        assert_eq!(inventory.selected_stack(), None);
+        assert_eq!(inventory.take_selected(1), None);
+    }
+
+    #[test]
+    fn test_select_and_scroll() {
+        let mut inventory = Inventory::new();
+
+        inventory.select(4);
+        assert_eq!(inventory.selected(), 4);
+
+        // only hotbar slots can be selected
+        inventory.select(HOTBAR_SLOTS);
+        assert_eq!(inventory.selected(), 4);
+
+        inventory.select(0);
+        inventory.scroll(1.0);
+        assert_eq!(inventory.selected(), HOTBAR_SLOTS - 1);
+
+        inventory.scroll(-1.0);
+        assert_eq!(inventory.selected(), 0);
+    }
+
+    #[test]
+    fn test_click_slot() {
+        let mut inventory = Inventory::new();
+        let mut held = None;
+        inventory.set(0, Some(ItemStack::new(DIRT, 10)));
+        inventory.set(1, Some(ItemStack::new(STONE, 5)));
+        inventory.set(2, Some(ItemStack::new(DIRT, 60)));
+
+        // pick up
+        inventory.click_slot(0, &mut held);
+        assert_eq!(held, Some(ItemStack::new(DIRT, 10)));
+        assert_eq!(inventory.get(0), None);
+
+        // swap with a different item
+        inventory.click_slot(1, &mut held);
+        assert_eq!(held, Some(ItemStack::new(STONE, 5)));
+        assert_eq!(inventory.get(1), Some(&ItemStack::new(DIRT, 10)));
+
+        // put down in an empty slot
+        inventory.click_slot(0, &mut held);
+        assert_eq!(held, None);
+        assert_eq!(inventory.get(0), Some(&ItemStack::new(STONE, 5)));
+
+        // merge into a matching stack, keeping the overflow
+        inventory.click_slot(1, &mut held);
+        inventory.click_slot(2, &mut held);
+        assert_eq!(inventory.get(2), Some(&ItemStack::new(DIRT, 64)));
+        assert_eq!(held, Some(ItemStack::new(DIRT, 6)));
+    }
+
+    #[test]
+    fn test_right_click_slot() {
+        let mut inventory = Inventory::new();
+        let mut held = None;
+        inventory.set(0, Some(ItemStack::new(DIRT, 5)));
+
+        // pick up half, rounding up
+        inventory.right_click_slot(0, &mut held);
+        assert_eq!(held, Some(ItemStack::new(DIRT, 3)));
+        assert_eq!(inventory.get(0), Some(&ItemStack::new(DIRT, 2)));
+
+        // place one at a time
+        inventory.right_click_slot(1, &mut held);
+        inventory.right_click_slot(0, &mut held);
+        assert_eq!(inventory.get(1), Some(&ItemStack::new(DIRT, 1)));
+        assert_eq!(inventory.get(0), Some(&ItemStack::new(DIRT, 3)));
+        assert_eq!(held, Some(ItemStack::new(DIRT, 1)));
+
+        inventory.right_click_slot(1, &mut held);
+        assert_eq!(held, None);
+        assert_eq!(inventory.get(1), Some(&ItemStack::new(DIRT, 2)));
+    }
+
+    #[test]
+    fn test_screen_close_returns_held() {
+        let mut inventory = Inventory::new();
+        let mut screen = InventoryScreen::default();
+
+        screen.toggle(&mut inventory);
+        assert!(screen.open);
+
+        screen.held = Some(ItemStack::new(STONE, 7));
+        assert_eq!(screen.toggle(&mut inventory), None);
+        assert!(!screen.open);
+        assert_eq!(screen.held, None);
+        assert_eq!(inventory.count(STONE), 7);
+    }
+
+    #[test]
+    fn test_screen_close_when_full() {
+        let mut inventory = Inventory::new();
+        for slot in 0..INVENTORY_SLOTS {
+            inventory.set(slot, Some(ItemStack::new(STONE, 64)));
+        }
+        let mut screen = InventoryScreen {
+            open: true,
+            held: Some(ItemStack::new(DIRT, 3)),
+        };
+
+        assert_eq!(screen.close(&mut inventory), Some(ItemStack::new(DIRT, 3)));
+        assert_eq!(screen.held, None);
+    }
+}
@@ -0,0 +1,408 @@
+use std::time::Duration;
+
+use cgmath::{prelude::*, Point3, Rad, Vector3};
+
+use crate::{
+    entity::{Collider, Entities, EntityId, EntityManager, RenderModel, TickContext, Transform},
+    inventory::ItemStack,
+    player,
+};
+
+pub const ITEM_SIZE: f32 = 0.25;
+// Items that are this close together and hold the same thing
+// get combined into a single stack
+pub const MERGE_RANGE: f32 = 0.5;
+pub const DESPAWN_TIME: Duration = Duration::from_secs(300);
+// Stops freshly dropped items being picked up again straight away
+pub const PICKUP_DELAY: Duration = Duration::from_millis(500);
+
+const SPIN_SPEED: f32 = 1.5; // rad / s
+const BOB_HEIGHT: f32 = 0.05;
+const POP_SPEED: f32 = 2.0;
+
+// Component for a stack of items lying in the world,
+// waiting to be picked up
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemData {
+    pub stack: ItemStack,
+    pub age: Duration,
+}
+
+impl ItemData {
+    pub fn expired(&self) -> bool {
+        self.age >= DESPAWN_TIME
+    }
+
+    pub fn can_pickup(&self) -> bool {
+        self.age >= PICKUP_DELAY
+    }
+}
+
+pub fn spawn_item(
+    entities: &mut Entities,
+    stack: ItemStack,
+    pos: Point3<f32>,
+    vel: Vector3<f32>,
+) -> EntityId {
+    let id = entities.spawn(Transform::new(pos));
+
+    entities.velocities.insert(id, vel);
+    entities
+        .colliders
+        .insert(id, Collider::new(ITEM_SIZE, ITEM_SIZE));
+    entities.models.insert(
+        id,
+        RenderModel {
+            label: stack.item.tex_label().to_string(),
+            // The cube model spans -1 to 1
+            scale: ITEM_SIZE / 2.0,
+            offset: Vector3::new(0.0, 0.0, ITEM_SIZE / 2.0),
+        },
+    );
+    entities.items.insert(
+        id,
+        ItemData {
+            stack,
+            age: Duration::ZERO,
+        },
+    );
+
+    id
+}
+
+// Spawns the drop from a broken block in the middle of where the
+// block was, with a little random pop so drops don't all stack up
+pub fn spawn_block_drop(
+    entities: &mut Entities,
+    stack: ItemStack,
+    block_loc: Point3<i32>,
+) -> EntityId {
+    let pos = block_loc.cast::<f32>().unwrap() + Vector3::new(0.5, 0.5, 0.5 - ITEM_SIZE / 2.0);
+    let vel = Vector3::new(
+        (rand::random::<f32>() - 0.5) * POP_SPEED,
+        (rand::random::<f32>() - 0.5) * POP_SPEED,
+        POP_SPEED,
+    );
+
+    spawn_item(entities, stack, pos, vel)
+}
+
+pub fn register_systems(manager: &mut EntityManager) {
+    manager.add_system("item_age", item_age_system);
+    manager.add_system("item_merge", item_merge_system);
+    manager.add_system("item_pickup", item_pickup_system);
+}
+
+// Ages items, despawning old ones, and animates the spin and bob
+pub fn item_age_system(entities: &mut Entities, _ctx: &mut TickContext, dt: Duration) {
+    let mut expired = Vec::new();
+
+    for (id, item) in entities.items.iter_mut() {
+        item.age += dt;
+        if item.expired() {
+            expired.push(*id);
+            continue;
+        }
+
+        let t = item.age.as_secs_f32();
+        if let Some(transform) = entities.transforms.get_mut(id) {
+            transform.yaw = Rad(t * SPIN_SPEED);
+        }
+
+        let on_ground = entities.colliders.get(id).is_some_and(|c| c.on_ground);
+        if let Some(model) = entities.models.get_mut(id) {
+            let bob = if on_ground {
+                BOB_HEIGHT * (1.0 + (t * 2.0).sin())
+            } else {
+                0.0
+            };
+            model.offset.z = ITEM_SIZE / 2.0 + bob;
+        }
+    }
+
+    for id in expired {
+        entities.despawn(id);
+    }
+}
+
+// Combines nearby items holding the same thing. The older item
+// absorbs the younger one, up to the max stack size.
+pub fn item_merge_system(entities: &mut Entities, _ctx: &mut TickContext, _dt: Duration) {
+    let ids = entities.items.keys().copied().collect::<Vec<_>>();
+    let mut emptied = Vec::new();
+
+    for id in ids {
+        if emptied.contains(&id) {
+            continue;
+        }
+
+        let pos = entities.transforms[&id].position;
+        for other in entities.near(pos, MERGE_RANGE) {
+            if other == id || emptied.contains(&other) {
+                continue;
+            }
+
+            let (Some(a), Some(b)) = (entities.items.get(&id), entities.items.get(&other)) else {
+                continue;
+            };
+            if a.stack.item != b.stack.item {
+                continue;
+            }
+
+            // Ties go to whichever was spawned first
+            let (keep, absorb) = if (a.age, other) >= (b.age, id) {
+                (id, other)
+            } else {
+                (other, id)
+            };
+
+            let space = entities.items[&keep].stack.space();
+            let absorbed = entities.items.get_mut(&absorb).unwrap();
+            let moved = absorbed.stack.count.min(space);
+            absorbed.stack.count -= moved;
+            let now_empty = absorbed.stack.count == 0;
+            entities.items.get_mut(&keep).unwrap().stack.count += moved;
+
+            if now_empty {
+                emptied.push(absorb);
+                if absorb == id {
+                    break;
+                }
+            }
+        }
+    }
+
+    for id in emptied {
+        entities.despawn(id);
+    }
+}
+
+// Moves any items within reach of the player into their inventory.
+// Items that only partly fit stay in the world with what's left.
+pub fn item_pickup_system(entities: &mut Entities, ctx: &mut TickContext, _dt: Duration) {
+    let reach = player::player_aabb(ctx.player_eye).expand(1.0, 1.0, 0.5);
+    let search = ctx.player_eye.distance(reach.min) + ITEM_SIZE;
+    let mut collected = Vec::new();
+
+    for id in entities.near(ctx.player_eye, search) {
+        let Some(aabb) = entities.aabb(id) else {
+            continue;
+        };
+        let Some(item) = entities.items.get_mut(&id) else {
+            continue;
+        };
+
+        if !item.can_pickup() || !reach.intersects(&aabb) {
+            continue;
+        }
+
+        match ctx.inventory.add(item.stack) {
+            Some(left) => item.stack = left,
+            None => collected.push(id),
+        }
+    }
+
+    for id in collected {
+        entities.despawn(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::{flat_world, BlockType, ChunkManager},
+        inventory::{Inventory, Item, INVENTORY_SLOTS},
+    };
+
+    use super::*;
+
+    const DIRT: Item = Item::Block(BlockType::Dirt);
+    const STONE: Item = Item::Block(BlockType::Stone);
+
+    fn item_at(entities: &mut Entities, item: Item, count: u32, pos: Point3<f32>) -> EntityId {
+        spawn_item(entities, ItemStack::new(item, count), pos, Vector3::zero())
+    }
+
+    fn run(
+        system: crate::entity::System,
+        entities: &mut Entities,
+        world: &ChunkManager,
+        eye: Point3<f32>,
+        inventory: &mut Inventory,
+        dt: Duration,
+    ) {
+        let mut ctx = TickContext {
+            chunk_manager: world,
+            player_eye: eye,
+            inventory,
+            spawn_mobs: false,
+        };
+        system(entities, &mut ctx, dt);
+        entities.reindex();
+    }
+
+    #[test]
+    fn test_item_falls_and_lands() {
+        let world = flat_world(&[(0, 0)]);
+        let mut inventory = Inventory::new();
+        let mut manager = EntityManager::default();
+        let id = item_at(&mut manager.entities, DIRT, 1, Point3::new(4.5, 4.5, 5.0));
+        let mut ctx = TickContext {
+            chunk_manager: &world,
+            player_eye: Point3::new(100.0, 100.0, 100.0),
+            inventory: &mut inventory,
+            spawn_mobs: false,
+        };
+
+        for _ in 0..200 {
+            manager.tick(&mut ctx, Duration::from_millis(16));
+        }
+
+        let entities = &manager.entities;
+        assert!((entities.transforms[&id].position.z - 1.0).abs() < 1e-4);
+        assert_eq!(entities.velocities[&id].z, 0.0);
+        assert!(entities.colliders[&id].on_ground);
+    }
+
+    #[test]
+    fn test_merge_nearby_stacks() {
+        let world = flat_world(&[(0, 0)]);
+        let mut inventory = Inventory::new();
+        let mut entities = Entities::default();
+        let a = item_at(&mut entities, DIRT, 10, Point3::new(1.0, 1.0, 1.0));
+        let b = item_at(&mut entities, DIRT, 5, Point3::new(1.2, 1.0, 1.0));
+        // different item
+        let c = item_at(&mut entities, STONE, 5, Point3::new(1.0, 1.2, 1.0));
+        // too far away
+        let d = item_at(&mut entities, DIRT, 5, Point3::new(5.0, 5.0, 1.0));
+
+        run(
+            item_merge_system,
+            &mut entities,
+            &world,
+            Point3::new(100.0, 100.0, 100.0),
+            &mut inventory,
+            Duration::ZERO,
+        );
+
+        assert_eq!(entities.len(), 3);
+        assert!(!entities.contains(b));
+        assert_eq!(entities.items[&a].stack, ItemStack::new(DIRT, 15));
+        assert_eq!(entities.items[&c].stack, ItemStack::new(STONE, 5));
+        assert_eq!(entities.items[&d].stack, ItemStack::new(DIRT, 5));
+    }
+
+    #[test]
+    fn test_merge_respects_max_stack() {
+        let world = flat_world(&[(0, 0)]);
+        let mut inventory = Inventory::new();
+        let mut entities = Entities::default();
+        let a = item_at(&mut entities, DIRT, 60, Point3::new(1.0, 1.0, 1.0));
+        let b = item_at(&mut entities, DIRT, 10, Point3::new(1.0, 1.0, 1.0));
+
+        run(
+            item_merge_system,
+            &mut entities,
+            &world,
+            Point3::new(100.0, 100.0, 100.0),
+            &mut inventory,
+            Duration::ZERO,
+        );
+
+        assert_eq!(entities.items[&a].stack.count, 64);
+        assert_eq!(entities.items[&b].stack.count, 6);
+    }
+
+    #[test]
+    fn test_despawn() {
+        let world = flat_world(&[(0, 0)]);
+        let mut inventory = Inventory::new();
+        let mut entities = Entities::default();
+        let id = item_at(&mut entities, DIRT, 1, Point3::new(3.5, 3.5, 1.0));
+        let far_away = Point3::new(100.0, 100.0, 100.0);
+
+        run(
+            item_age_system,
+            &mut entities,
+            &world,
+            far_away,
+            &mut inventory,
+            DESPAWN_TIME - Duration::from_secs(1),
+        );
+        assert!(entities.contains(id));
+
+        run(
+            item_age_system,
+            &mut entities,
+            &world,
+            far_away,
+            &mut inventory,
+            Duration::from_secs(1),
+        );
+        assert!(!entities.contains(id));
+        assert_eq!(entities.in_chunk(Point3::new(0, 0, 0)).count(), 0);
+    }
+
+    #[test]
+    fn test_pickup() {
+        let world = flat_world(&[(0, 0)]);
+        let mut inventory = Inventory::new();
+        let mut entities = Entities::default();
+        let near = item_at(&mut entities, DIRT, 3, Point3::new(1.0, 1.0, 1.0));
+        let far = item_at(&mut entities, STONE, 3, Point3::new(10.0, 10.0, 1.0));
+        let eye = Point3::new(1.5, 1.5, 1.0 + player::EYE_HEIGHT);
+
+        // too fresh to pick up
+        run(
+            item_pickup_system,
+            &mut entities,
+            &world,
+            eye,
+            &mut inventory,
+            Duration::ZERO,
+        );
+        assert_eq!(entities.len(), 2);
+
+        for item in entities.items.values_mut() {
+            item.age = PICKUP_DELAY;
+        }
+
+        run(
+            item_pickup_system,
+            &mut entities,
+            &world,
+            eye,
+            &mut inventory,
+            Duration::ZERO,
+        );
+        assert!(!entities.contains(near));
+        assert!(entities.contains(far));
+        assert_eq!(inventory.count(DIRT), 3);
+    }
+
+    #[test]
+    fn test_partial_pickup() {
+        let world = flat_world(&[(0, 0)]);
+        let mut inventory = Inventory::new();
+        for slot in 1..INVENTORY_SLOTS {
+            inventory.set(slot, Some(ItemStack::new(STONE, 64)));
+        }
+        inventory.set(0, Some(ItemStack::new(DIRT, 62)));
+
+        let mut entities = Entities::default();
+        let id = item_at(&mut entities, DIRT, 5, Point3::new(1.0, 1.0, 1.0));
+        entities.items.get_mut(&id).unwrap().age = PICKUP_DELAY;
+
+        run(
+            item_pickup_system,
+            &mut entities,
+            &world,
+            Point3::new(1.0, 1.0, 2.5),
+            &mut inventory,
+            Duration::ZERO,
+        );
+
+        assert_eq!(entities.items[&id].stack.count, 3);
+        assert_eq!(inventory.count(DIRT), 64);
+    }
+}
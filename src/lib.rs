@@ -1,36 +1,70 @@
-use std::fs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use image::imageops;
+use cgmath::{EuclideanSpace, InnerSpace};
+
 use log::{debug, info};
-use player::{player_left_click, player_right_click};
 use pollster::FutureExt;
 use texture::TextureManager;
 use wgpu::util::DeviceExt;
 use wgpu::{Adapter, Device, Instance, PresentMode, Queue, Surface, SurfaceCapabilities};
-use winit::dpi::PhysicalSize;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::ElementState;
 use winit::event::KeyEvent;
 use winit::event::MouseButton;
 use winit::event::MouseScrollDelta;
 use winit::event::WindowEvent;
 use winit::event_loop::EventLoop;
-use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::Window;
+use winit::keyboard::PhysicalKey;
+use winit::window::{CursorGrabMode, Window};
 
+mod animation;
 pub mod app;
 pub mod camera;
 mod chunk;
+mod clock;
+mod command;
 mod debug_view;
+mod entity;
+mod fog;
+mod frustum;
 pub mod game;
+mod gpu_cull;
+mod hud;
+mod input;
+mod inventory;
+mod item_entity;
+mod lighting;
+mod lod;
+mod mesh;
+mod mob;
 mod model;
+mod occlusion;
+mod pathfinding;
+mod physics;
 mod player;
 mod raycasting;
+mod resource_pack;
 mod resources;
+mod shadow;
+mod sky;
+mod sun;
 mod texture;
+mod timestep;
+mod world;
 
 use model::Vertex;
 
+// Where the world gets saved to and loaded from
+const SAVE_DIR: &str = "saves/world";
+// Everything that gets a layer in the texture array
+const TEXTURE_NAMES: [&str; 8] = [
+    "stone", "dirt", "door", "animal", "lamp", "water", "glass", "leaves",
+];
+// Anisotropic filtering for block textures, 1 turns it off
+const DEFAULT_ANISOTROPY: u16 = 4;
+
 pub fn run() {
     info!("Starting MCRS");
     let event_loop = EventLoop::new().unwrap();
@@ -45,20 +79,42 @@ pub struct State {
     queue: Queue,
     config: wgpu::SurfaceConfiguration,
     render_pipeline: wgpu::RenderPipeline,
-    camera: camera::Camera,
+    // Opaque, cutout and translucent, in drawing order
+    terrain_pipelines: [wgpu::RenderPipeline; 3],
+    chunk_meshes: HashMap<cgmath::Point3<i32>, mesh::ChunkMesh>,
+    chunk_geometry: gpu_cull::ChunkGeometry,
     camera_uniform: camera::CameraUniform,
     camera_controller: camera::CameraController,
+    controls: input::Controls,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    sun_binding: sun::SunBinding,
+    shadows: shadow::ShadowMaps,
+    fog_binding: fog::FogBinding,
+    sky: sky::Sky,
+    command_line: command::CommandLine,
+    save_dir: PathBuf,
     projection: camera::Projection,
     instance_buffer: wgpu::Buffer,
     depth_texture: texture::DepthTexture,
-    mouse_pressed: bool,
-    chunk_manager: chunk::ChunkManager,
+    // Nothing ticks while paused
+    paused: bool,
+    // The cursor is hidden and held in place while playing, so the
+    // mouse only turns the camera
+    cursor_grabbed: bool,
     pub debug_view: debug_view::DebugView,
+    hud: hud::Hud,
+    world: world::World,
+    // The camera as drawn this frame, between the world's last two ticks
+    view: camera::Camera,
+    // Built up from window events until the next tick
+    inputs: world::Inputs,
+    cursor_pos: Option<PhysicalPosition<f64>>,
     window: Arc<Window>,
     obj_model: model::Model,
     texture_manager: texture::TextureManager,
+    // Drives animated textures, keeps going when the world clock is frozen
+    texture_time: f32,
     texture_bind_group: wgpu::BindGroup,
     pub running: bool,
     n_instances: usize,
@@ -75,100 +131,108 @@ impl State {
         let surface_caps = surface.get_capabilities(&adapter);
         let config = Self::create_surface_config(size, surface_caps);
 
-        let mut texture_manager_builder = texture::TextureManagerBuilder::new(None, None);
-        texture_manager_builder.add_texture(
-            "stone",
-            texture::Texture::from_image(
-                "stone",
-                &image::load_from_memory(&fs::read("res/cobble.png").unwrap())
-                    .unwrap()
-                    .resize(256, 256, imageops::FilterType::Nearest),
-            ),
-        );
-
-        texture_manager_builder.add_texture(
-            "dirt",
-            texture::Texture::from_image(
-                "dirt",
-                &image::load_from_memory(&fs::read("res/dirt.png").unwrap())
-                    .unwrap()
-                    .resize(256, 256, imageops::FilterType::Nearest),
-            ),
+        let resource_packs = resource_pack::ResourcePackStack::load(
+            Path::new(resource_pack::USER_PACK_DIR),
+            Path::new(resource_pack::DEFAULT_PACK_DIR),
         );
+        let texture_manager_builder = resource_packs.build(&TEXTURE_NAMES);
 
-        let texture_manager = TextureManager::from(texture_manager_builder);
+        let mut texture_manager = TextureManager::from(texture_manager_builder);
         let (texture_bind_group, texture_bind_group_layout) =
-            texture_manager.create_and_submit_texture_array(&device, &queue);
-
-        let chunk_manager = chunk::ChunkManager::default();
+            texture_manager.create_and_submit_texture_array(&device, &queue, DEFAULT_ANISOTROPY);
 
         surface.configure(&device, &config);
 
         let depth_texture = texture::DepthTexture::new(&device, &config, "depth_texture");
+        let gpu_culling = gpu_cull::supported(
+            device.features(),
+            adapter.get_downlevel_capabilities().flags,
+        );
+        info!(
+            "Culling chunks on the {}",
+            if gpu_culling { "GPU" } else { "CPU" }
+        );
+        let chunk_geometry = gpu_cull::ChunkGeometry::new(&device, &depth_texture, gpu_culling);
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("world_lighting.wgsl"),
+                    include_str!("shader.wgsl")
+                )
+                .into(),
+            ),
         });
 
+        let chunk_config = chunk::ChunkManagerConfig::default();
         let (
-            camera,
             projection,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
             camera_bind_group_layout,
-        ) = Self::setup_camera(&device, &config);
+        ) = Self::setup_camera(&device, &config, chunk_config.render_dist());
+
+        let save_dir = PathBuf::from(SAVE_DIR);
+        let clock = clock::WorldClock::load(&save_dir).unwrap_or_else(|_| {
+            info!("No saved clock found in {save_dir:?}, starting a new day");
+            clock::WorldClock::default()
+        });
+        let shadows = shadow::ShadowMaps::new(&device, shadow::ShadowSettings::default());
+        let sun_binding = sun::SunBinding::new(&device, &clock.sun(), &shadows);
+        let fog =
+            fog::Fog::for_render_distance(chunk_config.render_dist(), clock.sky_colours().horizon);
+        let fog_binding = fog::FogBinding::new(&device, &fog);
+        let sky = sky::Sky::new(&device, &config, &clock, &fog);
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &camera_bind_group_layout,
+                    &sun_binding.bind_group_layout,
+                    &fog_binding.bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[model::ModelVertex::desc(), model::RenderInstanceRaw::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Cw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::DepthTexture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
+        let render_pipeline = Self::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader,
+            &[model::ModelVertex::desc(), model::RenderInstanceRaw::desc()],
+            config.format,
+            chunk::Material::Opaque,
+            "Render Pipeline",
+        );
+
+        let terrain_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("world_lighting.wgsl"),
+                    include_str!("terrain.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let terrain_pipelines = [
+            (chunk::Material::Opaque, "Terrain Pipeline"),
+            (chunk::Material::Cutout, "Cutout Terrain Pipeline"),
+            (chunk::Material::Translucent, "Translucent Terrain Pipeline"),
+        ]
+        .map(|(material, label)| {
+            Self::create_render_pipeline(
+                &device,
+                &render_pipeline_layout,
+                &terrain_shader,
+                &[mesh::BlockVertex::desc()],
+                config.format,
+                material,
+                label,
+            )
         });
 
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -183,49 +247,132 @@ impl State {
         let debug_view =
             debug_view::DebugView::new(&device, &config, &queue, window_arc.scale_factor());
 
+        let hud = hud::Hud::new(&device, &config, &queue, &texture_bind_group_layout);
+
+        let world = world::World::new(chunk_config, clock);
+        let bindings = input::Bindings::load(Path::new(input::CONTROLS_FILE));
+
         Self {
-            chunk_manager,
             surface,
             device,
             queue,
             config,
             window: window_arc,
             render_pipeline,
-            camera,
+            terrain_pipelines,
+            chunk_meshes: HashMap::new(),
+            chunk_geometry,
             camera_uniform,
             depth_texture,
             camera_buffer,
             camera_bind_group,
-            camera_controller: camera::CameraController::new(1.0, 0.4),
+            sun_binding,
+            shadows,
+            fog_binding,
+            sky,
+            command_line: command::CommandLine::default(),
+            save_dir,
+            camera_controller: camera::CameraController::new(bindings.look),
+            controls: input::Controls::new(bindings),
             instance_buffer,
             projection,
-            mouse_pressed: false,
+            paused: false,
+            cursor_grabbed: false,
             debug_view,
+            hud,
+            view: world.camera,
+            world,
+            inputs: world::Inputs::default(),
+            cursor_pos: None,
             obj_model,
             texture_manager,
+            texture_time: 0.0,
             texture_bind_group,
             running: true,
             n_instances: 0,
         }
     }
 
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        buffers: &[wgpu::VertexBufferLayout],
+        format: wgpu::TextureFormat,
+        material: chunk::Material,
+        label: &str,
+    ) -> wgpu::RenderPipeline {
+        // Cutouts discard their see-through pixels, and translucent
+        // geometry blends over what's already drawn without hiding it
+        let (fs_entry, blend, depth_write_enabled) = match material {
+            chunk::Material::Opaque => ("fs_main", wgpu::BlendState::REPLACE, true),
+            chunk::Material::Cutout => ("fs_cutout", wgpu::BlendState::REPLACE, true),
+            chunk::Material::Translucent => ("fs_main", wgpu::BlendState::ALPHA_BLENDING, false),
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                buffers,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some(fs_entry),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
     fn setup_camera(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        render_dist: u32,
     ) -> (
-        camera::Camera,
         camera::Projection,
         camera::CameraUniform,
         wgpu::Buffer,
         wgpu::BindGroup,
         wgpu::BindGroupLayout,
     ) {
-        let camera = camera::Camera::new((0.0, 0.0, 0.0), cgmath::Deg(0.0), cgmath::Deg(0.0));
+        // Far enough to take in the corners of the furthest chunks, which
+        // are hidden by fog by then
+        let zfar = (render_dist + 1) as f32 * chunk::CHUNK_WIDTH as f32;
         let projection =
-            camera::Projection::new(config.width, config.height, cgmath::Deg(45.0), 0.1, 100.0);
+            camera::Projection::new(config.width, config.height, cgmath::Deg(45.0), 0.1, zfar);
 
-        let mut camera_uniform = camera::CameraUniform::new();
-        camera_uniform.update_view_proj(&camera, &projection);
+        let camera_uniform = camera::CameraUniform::new();
 
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
@@ -258,7 +405,6 @@ impl State {
         });
 
         (
-            camera,
             projection,
             camera_uniform,
             camera_buffer,
@@ -293,7 +439,8 @@ impl State {
     fn create_device(adapter: &Adapter) -> (Device, Queue) {
         adapter
             .request_device(&wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
+                // Only used if it's there, see gpu_cull::supported
+                required_features: adapter.features() & wgpu::Features::MULTI_DRAW_INDIRECT,
                 required_limits: wgpu::Limits::default(),
                 label: None,
                 memory_hints: wgpu::MemoryHints::Performance,
@@ -344,6 +491,28 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
+        // Anything close enough to throw a shadow into view casts one
+        let eye = self.view.position;
+        let caster_dist = self.shadows.settings.distance + shadow::CASTER_MARGIN;
+        self.shadows.render(&mut encoder, |render_pass| {
+            self.chunk_geometry.bind(render_pass);
+            for key in self.chunk_meshes.keys() {
+                if chunk_distance2(*key, eye) <= caster_dist * caster_dist {
+                    // Translucent blocks let the sun through
+                    for material in [chunk::Material::Opaque, chunk::Material::Cutout] {
+                        self.chunk_geometry.draw_chunk(render_pass, *key, material);
+                    }
+                }
+            }
+        });
+
+        self.chunk_geometry.cull(
+            &mut encoder,
+            &self.queue,
+            &frustum::Frustum::from_camera(&self.view, &self.projection),
+            self.world.chunk_manager.render_keys(),
+        );
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -351,12 +520,7 @@ impl State {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.sky.clear_colour()),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -372,6 +536,24 @@ impl State {
                 timestamp_writes: None,
             });
 
+            self.sky.render(&mut render_pass);
+
+            render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.sun_binding.bind_group, &[]);
+            render_pass.set_bind_group(3, &self.fog_binding.bind_group, &[]);
+            for (material, pipeline) in [chunk::Material::Opaque, chunk::Material::Cutout]
+                .into_iter()
+                .zip(&self.terrain_pipelines)
+            {
+                render_pass.set_pipeline(pipeline);
+                self.chunk_geometry.draw(
+                    &mut render_pass,
+                    material,
+                    self.world.chunk_manager.render_keys(),
+                );
+            }
+
             if self.n_instances > 0 {
                 render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
                 render_pass.set_pipeline(&self.render_pipeline);
@@ -382,11 +564,65 @@ impl State {
                         .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
                     render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
                     render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                    render_pass.set_bind_group(2, &self.sun_binding.bind_group, &[]);
+                    render_pass.set_bind_group(3, &self.fog_binding.bind_group, &[]);
                     render_pass.draw_indexed(0..mesh.n_elements, 0, 0..self.n_instances as u32);
                 }
             }
+
+            // Translucent blocks go last so everything behind them is
+            // already there to blend with, furthest chunks first
+            let mut translucent = self
+                .world
+                .chunk_manager
+                .render_keys()
+                .filter_map(|key| Some((key, self.chunk_meshes.get(key)?)))
+                .filter(|(_, mesh)| mesh.has_translucent())
+                .collect::<Vec<_>>();
+            translucent.sort_by(|(a, _), (b, _)| {
+                chunk_distance2(**b, eye).total_cmp(&chunk_distance2(**a, eye))
+            });
+
+            render_pass.set_pipeline(&self.terrain_pipelines[2]);
+            render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.sun_binding.bind_group, &[]);
+            render_pass.set_bind_group(3, &self.fog_binding.bind_group, &[]);
+            for (_, mesh) in translucent {
+                mesh.draw_translucent(&mut render_pass);
+            }
         }
 
+        self.chunk_geometry.build_depth_pyramid(
+            &self.device,
+            &mut encoder,
+            &self.depth_texture,
+            camera::view_proj(&self.view, &self.projection),
+        );
+
+        self.hud.update(
+            &self.device,
+            &self.queue,
+            &self.config,
+            self.window.scale_factor(),
+            &hud::HudFrame {
+                inventory: &self.world.inventory,
+                screen: &self.world.inventory_screen,
+                vitals: self.world.vitals,
+                paused: self.paused,
+                cursor: self.cursor_pos.map(|p| (p.x as f32, p.y as f32)),
+            },
+            &self.texture_manager,
+        );
+        self.hud.render(
+            &self.device,
+            &self.config,
+            &self.queue,
+            &mut encoder,
+            &view,
+            &self.texture_bind_group,
+        );
+
         self.debug_view
             .render(&self.device, &self.config, &self.queue, &mut encoder, &view);
 
@@ -397,8 +633,41 @@ impl State {
         Ok(())
     }
 
-    fn update_instances(&mut self) -> usize {
-        let instances = self.chunk_manager.gen_instances();
+    // Rebuilds the meshes of any chunks that have changed
+    fn update_chunk_meshes(&mut self) {
+        for key in self.world.chunk_manager.take_dirty() {
+            let lod = self.world.chunk_manager.lod(key);
+            let data =
+                lod::build_chunk_mesh(&self.world.chunk_manager, key, lod, &self.texture_manager);
+            self.chunk_geometry
+                .upload(&self.device, &self.queue, key, &data);
+            if data.is_empty() {
+                self.chunk_meshes.remove(&key);
+            } else {
+                self.chunk_meshes
+                    .insert(key, mesh::ChunkMesh::new(&self.device, &data));
+            }
+        }
+
+        for key in self.world.chunk_manager.render_keys() {
+            if let Some(mesh) = self.chunk_meshes.get_mut(key) {
+                mesh.sort_translucent(&self.queue, self.view.position);
+            }
+        }
+    }
+
+    fn update_instances(&mut self, alpha: f32) -> usize {
+        // Anything lost in the fog doesn't need drawing
+        let fog = self.current_fog();
+        let eye = self.view.position.to_vec();
+        let instances = self
+            .world
+            .entities
+            .entities
+            .render_instances(&self.world.chunk_manager, alpha)
+            .into_iter()
+            .filter(|x| fog.amount((x.position - eye).magnitude()) < 1.0)
+            .collect::<Vec<_>>();
         let instance_data = instances
             .iter()
             .map(|x| x.to_raw(&self.texture_manager))
@@ -420,24 +689,23 @@ impl State {
     }
 
     fn input(&mut self, event: &WindowEvent) {
+        // The command line takes all typing while it's open, but releases
+        // still go through so movement keys don't get stuck down
+        if self.command_line.open {
+            if let WindowEvent::KeyboardInput { event, .. } = event {
+                if let Some(line) = self.command_line.handle_key(event) {
+                    self.run_command(&line);
+                }
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    if event.state == ElementState::Released {
+                        self.controls.process_key(key, event.state);
+                    }
+                }
+                return;
+            }
+        }
+
         match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(KeyCode::Escape),
-                        ..
-                    },
-                ..
-            } => self.running = false,
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(KeyCode::F1),
-                        state: ElementState::Pressed,
-                        ..
-                    },
-                ..
-            } => self.debug_view.view_active = !self.debug_view.view_active,
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -446,43 +714,275 @@ impl State {
                         ..
                     },
                 ..
-            } => self.camera_controller.process_keyboard(*key, *state),
+            } => {
+                let pressed = self.controls.process_key(*key, *state);
+                self.handle_actions(&pressed);
+            }
+            WindowEvent::CursorMoved { position, .. } => self.cursor_pos = Some(*position),
             WindowEvent::MouseWheel { delta, .. } => {
                 self.handle_mouse_scroll(delta);
             }
             WindowEvent::MouseInput { button, state, .. } => {
                 self.handle_mouse_button(*button, *state);
             }
+            WindowEvent::Focused(false) => self.paused = true,
             _ => (),
         }
+        self.update_cursor_grab();
+    }
+
+    // Whether the mouse is turning the camera, rather than being used
+    // for a menu or the command line
+    fn playing(&self) -> bool {
+        !self.paused && !self.command_line.open && !self.world.inventory_screen.open
+    }
+
+    fn update_cursor_grab(&mut self) {
+        let grab = self.playing();
+        if grab == self.cursor_grabbed {
+            return;
+        }
+
+        if grab {
+            // Not every platform can lock the cursor in place, but
+            // keeping it in the window is near enough
+            let grabbed = self
+                .window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined));
+            if let Err(e) = grabbed {
+                log::warn!("Couldn't grab the cursor: {e}");
+            }
+        } else if let Err(e) = self.window.set_cursor_grab(CursorGrabMode::None) {
+            log::warn!("Couldn't release the cursor: {e}");
+        }
+        self.window.set_cursor_visible(!grab);
+        self.cursor_grabbed = grab;
     }
 
-    pub fn update(&mut self, dt: instant::Duration) {
-        self.chunk_manager.update(&self.camera, &self.projection);
-        self.n_instances = self.update_instances();
-        self.camera_controller.update_camera(&mut self.camera, dt);
+    // Raw mouse movement, which only looks around while the cursor is
+    // grabbed
+    pub fn mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.cursor_grabbed {
+            self.camera_controller.process_mouse(dx, dy);
+        }
+    }
+
+    // Anything that only changes what's on screen happens straight away,
+    // everything else waits for the world's next tick
+    fn handle_actions(&mut self, pressed: &[input::Action]) {
+        for action in pressed {
+            match action {
+                // The world closes the inventory itself
+                input::Action::Pause if !self.world.inventory_screen.open => {
+                    self.paused = !self.paused
+                }
+                input::Action::Quit if self.paused => self.running = false,
+                input::Action::OpenCommandLine => {
+                    self.command_line.open();
+                    self.debug_view.view_active = true;
+                }
+                input::Action::ToggleDebug => {
+                    self.debug_view.view_active = !self.debug_view.view_active
+                }
+                input::Action::Break if self.world.inventory_screen.open => {
+                    self.handle_inventory_click(false)
+                }
+                input::Action::Place if self.world.inventory_screen.open => {
+                    self.handle_inventory_click(true)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Moves the world on by one tick with everything the player has
+    // done since the last one
+    pub fn tick(&mut self) {
+        // Clicks and key presses only happen once, on the first tick
+        // after them
+        let mut inputs = std::mem::take(&mut self.inputs);
+        inputs.actions = self.controls.take_tick();
+        inputs.look = self.camera_controller.take_look();
+        // Anything done while paused is thrown away
+        if self.paused {
+            return;
+        }
+
+        self.world.tick(&inputs);
+        self.update_cursor_grab();
+    }
+
+    // Gets ready to draw a frame `alpha` of the way between the last two
+    // ticks. `dt` is the time since the last frame.
+    pub fn update(&mut self, alpha: f32, dt: instant::Duration) {
+        // Stay on the last tick rather than moving towards one that
+        // isn't coming
+        let alpha = if self.paused { 1.0 } else { alpha };
+        self.view = self.world.camera_at(alpha);
+        self.world
+            .chunk_manager
+            .update_view(&self.view, &self.projection);
+        self.update_chunk_meshes();
+        self.n_instances = self.update_instances(alpha);
         self.camera_uniform
-            .update_view_proj(&self.camera, &self.projection);
+            .update_view_proj(&self.view, &self.projection);
 
         self.queue.write_buffer(
             &self.camera_buffer,
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
+        self.texture_time += dt.as_secs_f32();
+        self.texture_manager.animate(&self.queue, self.texture_time);
+
+        let sun = self.world.clock.sun();
+        self.sun_binding.update(&self.queue, &sun);
+        self.shadows
+            .update(&self.queue, &self.view, &self.projection, sun.direction);
+        let fog = self.current_fog();
+        self.fog_binding.update(&self.queue, &fog);
+        self.sky.update(
+            &self.queue,
+            &self.world.clock,
+            &fog,
+            self.camera_uniform.view_proj.into(),
+        );
+    }
+
+    // Thick fog when the camera is inside a fluid, otherwise fog that hides
+    // the edge of the render distance
+    fn current_fog(&self) -> fog::Fog {
+        let eye = chunk::point_to_world(self.view.position);
+        let in_fluid = self
+            .world
+            .chunk_manager
+            .get_block(eye)
+            .is_some_and(|block| block.block_type().is_fluid());
+
+        if in_fluid {
+            fog::Fog::underwater()
+        } else {
+            fog::Fog::for_render_distance(
+                self.world.chunk_manager.config.render_dist(),
+                self.world.clock.sky_colours().horizon,
+            )
+        }
+    }
+
+    fn run_command(&mut self, line: &str) {
+        self.command_line.output = match command::parse(line) {
+            Ok(command::Command::SetTime(time)) => {
+                self.world.clock.set_time(time);
+                let (hours, minutes) = self.world.clock.hours_minutes();
+                format!("Set time to {hours:02}:{minutes:02}")
+            }
+            Ok(command::Command::FreezeTime(frozen)) => {
+                self.world.clock.frozen = frozen;
+                if frozen {
+                    "Time frozen"
+                } else {
+                    "Time unfrozen"
+                }
+                .to_string()
+            }
+            Ok(command::Command::DayLength(seconds)) => {
+                self.world.clock.day_length = seconds;
+                format!("Day length set to {seconds}s")
+            }
+            Ok(command::Command::GpuCulling(enabled)) => {
+                match self.chunk_geometry.set_gpu_culling(
+                    &self.device,
+                    &self.depth_texture,
+                    enabled,
+                ) {
+                    Ok(()) if enabled => "Culling chunks on the GPU".to_string(),
+                    Ok(()) => "Culling chunks on the CPU".to_string(),
+                    Err(()) => "GPU culling isn't supported here".to_string(),
+                }
+            }
+            Ok(command::Command::Shadows(enabled)) => {
+                self.set_shadow_settings(shadow::ShadowSettings {
+                    enabled,
+                    ..self.shadows.settings
+                });
+                if enabled { "Shadows on" } else { "Shadows off" }.to_string()
+            }
+            Ok(command::Command::ShadowDistance(distance)) => {
+                self.set_shadow_settings(shadow::ShadowSettings {
+                    distance,
+                    ..self.shadows.settings
+                });
+                format!("Shadow distance set to {distance}")
+            }
+            Ok(command::Command::ShadowResolution(resolution)) => {
+                self.set_shadow_settings(shadow::ShadowSettings {
+                    resolution,
+                    ..self.shadows.settings
+                });
+                format!("Shadow resolution set to {resolution}")
+            }
+            Ok(command::Command::Anisotropy(anisotropy)) => {
+                self.texture_bind_group = self
+                    .texture_manager
+                    .create_bind_group(&self.device, anisotropy);
+                format!("Anisotropy set to {anisotropy}")
+            }
+            Ok(command::Command::GameMode(game_mode)) => {
+                self.world.game_mode = game_mode;
+                format!("Game mode set to {game_mode:?}")
+            }
+            Err(()) => format!("Unknown command: {line}"),
+        };
+        info!("{}", self.command_line.output);
+    }
+
+    fn set_shadow_settings(&mut self, settings: shadow::ShadowSettings) {
+        if self.shadows.set_settings(&self.device, settings) {
+            self.sun_binding.rebind_shadows(&self.device, &self.shadows);
+        }
+    }
+
+    pub fn save(&self) {
+        match self.world.save(&self.save_dir) {
+            Ok(()) => info!("Saved world to {:?}", self.save_dir),
+            Err(()) => log::error!("Failed to save world to {:?}", self.save_dir),
+        }
     }
 
     fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState) {
-        if button == MouseButton::Left {
-            debug!("Mouse left button press");
-            self.mouse_pressed = state == ElementState::Pressed;
-            player_left_click(&self.camera, &mut self.chunk_manager);
-        } else if button == MouseButton::Right {
-            debug!("Mouse right button press");
-            player_right_click(&self.camera, &mut self.chunk_manager);
+        let pressed = self.controls.process_mouse_button(button, state);
+        self.handle_actions(&pressed);
+    }
+
+    fn handle_inventory_click(&mut self, right: bool) {
+        let Some(cursor) = self.cursor_pos else {
+            return;
+        };
+
+        let layout = hud::HudLayout::new(
+            self.config.width,
+            self.config.height,
+            self.window.scale_factor(),
+        );
+        if let Some(slot) = layout.inventory_slot_at(cursor.x as f32, cursor.y as f32) {
+            self.inputs.slot_clicks.push((slot, right));
         }
     }
 
     fn handle_mouse_scroll(&mut self, delta: &MouseScrollDelta) {
-        self.camera_controller.process_scroll(delta);
+        self.inputs.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
+        };
     }
 }
+
+// Squared distance from the eye to the middle of a chunk
+fn chunk_distance2(origin: cgmath::Point3<i32>, eye: cgmath::Point3<f32>) -> f32 {
+    let half = chunk::CHUNK_WIDTH as f32 / 2.0;
+    let dx = origin.x as f32 + half - eye.x;
+    let dy = origin.y as f32 + half - eye.y;
+    let dz = origin.z as f32 + half - eye.z;
+    dx * dx + dy * dy + dz * dz
+}
@@ -0,0 +1,458 @@
+use std::collections::VecDeque;
+
+use cgmath::{Point3, Vector3};
+
+use crate::chunk::{ChunkManager, CHUNK_WIDTH};
+
+pub const MAX_LIGHT: u8 = 15;
+// Each level of light is this much dimmer than the one above it
+const FALLOFF: f32 = 0.8;
+
+const NEIGHBOURS: [Vector3<i32>; 6] = [
+    Vector3::new(1, 0, 0),
+    Vector3::new(-1, 0, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(0, -1, 0),
+    Vector3::new(0, 0, 1),
+    Vector3::new(0, 0, -1),
+];
+const DOWN: Vector3<i32> = Vector3::new(0, 0, -1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    // Light from the sky, which travels straight down without fading
+    Sky,
+    // Light given off by blocks such as lamps
+    Block,
+}
+
+// Sky light in the top four bits, block light in the bottom four
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Light(u8);
+
+impl Light {
+    pub fn new(sky: u8, block: u8) -> Self {
+        Self((sky.min(MAX_LIGHT) << 4) | block.min(MAX_LIGHT))
+    }
+
+    pub fn sky(self) -> u8 {
+        self.0 >> 4
+    }
+
+    pub fn block(self) -> u8 {
+        self.0 & 0x0f
+    }
+
+    pub fn get(self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => self.sky(),
+            Channel::Block => self.block(),
+        }
+    }
+
+    pub fn with(self, channel: Channel, level: u8) -> Self {
+        match channel {
+            Channel::Sky => Self::new(level, self.block()),
+            Channel::Block => Self::new(self.sky(), level),
+        }
+    }
+
+    // How bright the sky and block light look, from 0 to 1. Kept apart
+    // so the sky can be dimmed at night without touching lamps.
+    pub fn brightness(self) -> [f32; 2] {
+        [level_brightness(self.sky()), level_brightness(self.block())]
+    }
+}
+
+pub fn level_brightness(level: u8) -> f32 {
+    FALLOFF.powi((MAX_LIGHT - level.min(MAX_LIGHT)) as i32)
+}
+
+// The light at a location. Unloaded chunks are dark, unless nothing
+// loaded above them blocks the sky.
+pub fn light_at(world: &ChunkManager, loc: Point3<i32>) -> Light {
+    world.get_light(loc).unwrap_or_else(|| {
+        if world.open_to_sky(loc) {
+            Light::new(MAX_LIGHT, 0)
+        } else {
+            Light::default()
+        }
+    })
+}
+
+fn level(world: &ChunkManager, loc: Point3<i32>, channel: Channel) -> u8 {
+    world.get_light(loc).map_or(0, |light| light.get(channel))
+}
+
+fn set_level(world: &mut ChunkManager, loc: Point3<i32>, channel: Channel, level: u8) {
+    if let Some(light) = world.get_light(loc) {
+        world.set_light(loc, light.with(channel, level));
+    }
+}
+
+// Whether light can pass into a location
+fn transparent(world: &ChunkManager, loc: Point3<i32>) -> bool {
+    world.get_light(loc).is_some()
+        && !world
+            .get_block(loc)
+            .is_some_and(|block| block.block_type().is_opaque())
+}
+
+fn emission(world: &ChunkManager, loc: Point3<i32>) -> u8 {
+    world
+        .get_block(loc)
+        .map_or(0, |block| block.block_type().light_emission())
+}
+
+// Flood fills light outwards from every location in the queue
+fn propagate(world: &mut ChunkManager, channel: Channel, mut queue: VecDeque<Point3<i32>>) {
+    while let Some(pos) = queue.pop_front() {
+        let current = level(world, pos, channel);
+        if current == 0 {
+            continue;
+        }
+
+        for dir in NEIGHBOURS {
+            let next = pos + dir;
+            if !transparent(world, next) {
+                continue;
+            }
+
+            let new = if channel == Channel::Sky && dir == DOWN && current == MAX_LIGHT {
+                MAX_LIGHT
+            } else {
+                current - 1
+            };
+
+            if new > level(world, next, channel) {
+                set_level(world, next, channel, new);
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+// Clears out light that came from the locations in `removal`. Anything
+// lit by another source is queued up in `refill` to spread back in.
+fn remove(
+    world: &mut ChunkManager,
+    channel: Channel,
+    mut removal: VecDeque<(Point3<i32>, u8)>,
+    refill: &mut VecDeque<Point3<i32>>,
+) {
+    while let Some((pos, old)) = removal.pop_front() {
+        for dir in NEIGHBOURS {
+            let next = pos + dir;
+            let next_level = level(world, next, channel);
+            if next_level == 0 {
+                continue;
+            }
+
+            // Full sky light below full sky light came straight down
+            // from it, so goes too
+            let sky_column = channel == Channel::Sky && dir == DOWN && old == MAX_LIGHT;
+
+            if next_level < old || sky_column {
+                set_level(world, next, channel, 0);
+                removal.push_back((next, next_level));
+
+                let glow = emission(world, next);
+                if channel == Channel::Block && glow > 0 {
+                    set_level(world, next, channel, glow);
+                    refill.push_back(next);
+                }
+            } else {
+                refill.push_back(next);
+            }
+        }
+    }
+}
+
+// Relights the world around a block that has just been placed, removed
+// or changed
+pub fn update_block(world: &mut ChunkManager, loc: Point3<i32>) {
+    if world.get_light(loc).is_none() {
+        return;
+    }
+
+    for channel in [Channel::Sky, Channel::Block] {
+        let mut removal = VecDeque::new();
+        let mut refill = VecDeque::new();
+
+        let old = level(world, loc, channel);
+        set_level(world, loc, channel, 0);
+        if old > 0 {
+            removal.push_back((loc, old));
+        }
+        remove(world, channel, removal, &mut refill);
+
+        if channel == Channel::Block {
+            let glow = emission(world, loc);
+            if glow > 0 {
+                set_level(world, loc, channel, glow);
+                refill.push_back(loc);
+            }
+        }
+
+        // Let light flow back in from around the change
+        if transparent(world, loc) {
+            let above = loc + Vector3::unit_z();
+            if channel == Channel::Sky && light_at(world, above).sky() == MAX_LIGHT {
+                set_level(world, loc, channel, MAX_LIGHT);
+                refill.push_back(loc);
+            }
+
+            for dir in NEIGHBOURS {
+                if level(world, loc + dir, channel) > 1 {
+                    refill.push_back(loc + dir);
+                }
+            }
+        }
+
+        propagate(world, channel, refill);
+    }
+}
+
+// Lets light flow out of a chunk that has just been added to the world,
+// which has had its own light set up, and between it and the chunks
+// already around it
+pub fn light_chunk(world: &mut ChunkManager, origin: Point3<i32>) {
+    let Some(chunk) = world.chunks.get(&origin) else {
+        return;
+    };
+
+    // Above the highest opaque block, every neighbour of a sky lit block
+    // in the chunk is fully lit too, so there's nothing to spread
+    let top = world.highest_opaque(origin);
+
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+
+    // Solid chunks hold no light unless something in them glows
+    if chunk.is_lit() {
+        for (loc, light) in chunk.iter_light() {
+            if light.sky() > 1 && top.is_some_and(|top| loc.z <= top + 1) {
+                sky.push_back(loc);
+            }
+            if light.block() > 1 {
+                block.push_back(loc);
+            }
+        }
+    }
+
+    // Light spreads across the border whichever side it's brighter on,
+    // in to the chunk from its neighbours or out of it into them
+    let width = CHUNK_WIDTH as i32;
+    for i in 0..width {
+        for j in 0..width {
+            for (inside, dir) in [
+                (Vector3::new(0, i, j), -Vector3::unit_x()),
+                (Vector3::new(width - 1, i, j), Vector3::unit_x()),
+                (Vector3::new(i, 0, j), -Vector3::unit_y()),
+                (Vector3::new(i, width - 1, j), Vector3::unit_y()),
+                (Vector3::new(i, j, 0), DOWN),
+                (Vector3::new(i, j, width - 1), Vector3::unit_z()),
+            ] {
+                let inside = origin + inside;
+                let outside = inside + dir;
+                let Some(outside_light) = world.get_light(outside) else {
+                    continue;
+                };
+                let inside_light = world.get_light(inside).unwrap();
+
+                for (channel, queue) in [(Channel::Sky, &mut sky), (Channel::Block, &mut block)] {
+                    let (a, b) = (inside_light.get(channel), outside_light.get(channel));
+                    // Full sky light carries straight down without fading
+                    let falls = |above: u8, below: u8| {
+                        channel == Channel::Sky && above == MAX_LIGHT && below < MAX_LIGHT
+                    };
+                    if a > b + 1 || (dir == DOWN && falls(a, b)) {
+                        queue.push_back(inside);
+                    } else if b > a + 1 || (dir == Vector3::unit_z() && falls(b, a)) {
+                        queue.push_back(outside);
+                    }
+                }
+            }
+        }
+    }
+
+    propagate(world, Channel::Sky, sky);
+    propagate(world, Channel::Block, block);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{block_to_chunk, flat_world, Block, BlockType, Chunk};
+
+    use super::*;
+
+    fn place(world: &mut ChunkManager, x: i32, y: i32, z: i32, block_type: BlockType) {
+        world
+            .set_block(Point3::new(x, y, z), Block::new(block_type))
+            .unwrap();
+    }
+
+    fn light(world: &ChunkManager, x: i32, y: i32, z: i32) -> Light {
+        world.get_light(Point3::new(x, y, z)).unwrap()
+    }
+
+    #[test]
+    fn test_light_packing() {
+        let light = Light::new(12, 3);
+        assert_eq!(light.sky(), 12);
+        assert_eq!(light.block(), 3);
+        assert_eq!(light.with(Channel::Block, 9), Light::new(12, 9));
+        assert_eq!(light.with(Channel::Sky, 0), Light::new(0, 3));
+        assert_eq!(level_brightness(MAX_LIGHT), 1.0);
+    }
+
+    #[test]
+    fn test_sky_light() {
+        let world = flat_world(&[(0, 0)]);
+
+        assert_eq!(light(&world, 5, 5, 1).sky(), MAX_LIGHT);
+        assert_eq!(light(&world, 5, 5, 30).sky(), MAX_LIGHT);
+        // Underground is dark
+        assert_eq!(light(&world, 5, 5, 0).sky(), 0);
+        assert_eq!(light(&world, 5, 5, -10).sky(), 0);
+    }
+
+    #[test]
+    fn test_roof_shades_and_light_spreads_under_it() {
+        let mut world = flat_world(&[(0, 0)]);
+        // A roof over x in 0..8 at z = 3
+        for x in 0..8 {
+            for y in 0..16 {
+                place(&mut world, x, y, 3, BlockType::Stone);
+            }
+        }
+
+        assert_eq!(light(&world, 8, 5, 1).sky(), MAX_LIGHT);
+        // Light creeps in sideways from the open edge
+        assert_eq!(light(&world, 7, 5, 1).sky(), MAX_LIGHT - 1);
+        assert_eq!(light(&world, 4, 5, 1).sky(), MAX_LIGHT - 4);
+        assert_eq!(light(&world, 0, 5, 2).sky(), MAX_LIGHT - 8);
+        assert_eq!(light(&world, 3, 5, 3).sky(), 0);
+
+        // Taking a hole out of the roof lets the sky straight back in
+        world.remove_block(Point3::new(2, 5, 3)).unwrap();
+        assert_eq!(light(&world, 2, 5, 3).sky(), MAX_LIGHT);
+        assert_eq!(light(&world, 2, 5, 1).sky(), MAX_LIGHT);
+        assert_eq!(light(&world, 1, 5, 1).sky(), MAX_LIGHT - 1);
+    }
+
+    #[test]
+    fn test_chunk_above_shades_column() {
+        let mut world = flat_world(&[(0, 0)]);
+        // A roof over x in 0..8, in a chunk loaded later further up
+        let mut chunk = Chunk::gen_empty_chunk(Point3::new(0, 0, 32));
+        for x in 0..8 {
+            for y in 0..16 {
+                chunk
+                    .set_block(Point3::new(x, y, 40), Block::new(BlockType::Stone))
+                    .unwrap();
+            }
+        }
+        world.insert_chunk(chunk);
+
+        assert_eq!(world.column_height(Point3::new(4, 5, 0)), Some(40));
+        assert_eq!(light(&world, 8, 5, 1).sky(), MAX_LIGHT);
+        assert_eq!(light(&world, 4, 5, 1).sky(), MAX_LIGHT - 4);
+        assert_eq!(light(&world, 4, 5, 39).sky(), MAX_LIGHT - 4);
+
+        // Without its top block, the column falls back to the floor
+        world.remove_block(Point3::new(2, 5, 40)).unwrap();
+        assert_eq!(world.column_height(Point3::new(2, 5, 0)), Some(0));
+        assert_eq!(light(&world, 2, 5, 1).sky(), MAX_LIGHT);
+    }
+
+    #[test]
+    fn test_placing_casts_shadow() {
+        let mut world = flat_world(&[(0, 0)]);
+        place(&mut world, 5, 5, 10, BlockType::Stone);
+
+        // Directly under the block is lit from the sides
+        assert_eq!(light(&world, 5, 5, 9).sky(), MAX_LIGHT - 1);
+        assert_eq!(light(&world, 5, 5, 1).sky(), MAX_LIGHT - 1);
+
+        world.remove_block(Point3::new(5, 5, 10)).unwrap();
+        assert_eq!(light(&world, 5, 5, 9).sky(), MAX_LIGHT);
+        assert_eq!(light(&world, 5, 5, 1).sky(), MAX_LIGHT);
+    }
+
+    #[test]
+    fn test_lamp_light_and_removal() {
+        let mut world = flat_world(&[(0, 0)]);
+        place(&mut world, 5, 5, 1, BlockType::Lamp);
+
+        assert_eq!(light(&world, 5, 5, 1).block(), MAX_LIGHT);
+        assert_eq!(light(&world, 6, 5, 1).block(), MAX_LIGHT - 1);
+        assert_eq!(light(&world, 7, 6, 1).block(), MAX_LIGHT - 3);
+        assert_eq!(light(&world, 5, 5, 4).block(), MAX_LIGHT - 3);
+        // Doesn't go through the floor
+        assert_eq!(light(&world, 5, 5, 0).block(), 0);
+
+        // A wall blocks the light, so it has to go round
+        for y in 0..16 {
+            place(&mut world, 8, y, 1, BlockType::Stone);
+        }
+        assert_eq!(light(&world, 9, 5, 1).block(), MAX_LIGHT - 6);
+
+        world.remove_block(Point3::new(5, 5, 1)).unwrap();
+        for x in 0..16 {
+            for z in 1..16 {
+                assert_eq!(light(&world, x, 5, z).block(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_two_lamps() {
+        let mut world = flat_world(&[(0, 0)]);
+        place(&mut world, 2, 5, 1, BlockType::Lamp);
+        place(&mut world, 10, 5, 1, BlockType::Lamp);
+        assert_eq!(light(&world, 6, 5, 1).block(), MAX_LIGHT - 4);
+
+        // The other lamp still lights the area after one goes
+        world.remove_block(Point3::new(2, 5, 1)).unwrap();
+        assert_eq!(light(&world, 6, 5, 1).block(), MAX_LIGHT - 4);
+        assert_eq!(light(&world, 2, 5, 1).block(), MAX_LIGHT - 8);
+    }
+
+    #[test]
+    fn test_light_crosses_chunk_borders() {
+        let mut world = flat_world(&[(0, 0), (16, 0)]);
+        place(&mut world, 14, 5, 1, BlockType::Lamp);
+
+        assert_eq!(light(&world, 16, 5, 1).block(), MAX_LIGHT - 2);
+        assert_eq!(light(&world, 20, 5, 1).block(), MAX_LIGHT - 6);
+
+        // Both chunks need a new mesh
+        let dirty = world.take_dirty();
+        assert!(dirty.contains(&block_to_chunk(Point3::new(0, 0, 1))));
+        assert!(dirty.contains(&block_to_chunk(Point3::new(16, 0, 1))));
+
+        world.remove_block(Point3::new(14, 5, 1)).unwrap();
+        assert_eq!(light(&world, 16, 5, 1).block(), 0);
+        assert_eq!(light(&world, 20, 5, 1).block(), 0);
+    }
+
+    #[test]
+    fn test_new_chunk_picks_up_neighbour_light() {
+        let mut world = flat_world(&[(0, 0)]);
+        place(&mut world, 14, 5, 1, BlockType::Lamp);
+
+        let mut chunk = Chunk::gen_empty_chunk(Point3::new(16, 0, 0));
+        for x in 16..32 {
+            for y in 0..16 {
+                chunk
+                    .set_block(Point3::new(x, y, 0), Block::new(BlockType::Stone))
+                    .unwrap();
+            }
+        }
+        world.insert_chunk(chunk);
+
+        assert_eq!(light(&world, 17, 5, 1).block(), MAX_LIGHT - 3);
+        assert_eq!(light(&world, 17, 5, 1).sky(), MAX_LIGHT);
+    }
+}
@@ -0,0 +1,434 @@
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    chunk::{BlockType, ChunkManager, CHUNK_WIDTH},
+    lighting::{self, Light, MAX_LIGHT},
+    mesh::{self, BlockVertex, ChunkMeshData, FACES, FACE_TEX_COORDS},
+    texture,
+};
+
+// How much detail a chunk's mesh is built with. Far away chunks are
+// meshed from a coarser grid of blocks, which is far fewer faces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lod {
+    Full,
+    Half,
+    Quarter,
+}
+
+impl Lod {
+    const ALL: [Lod; 3] = [Lod::Full, Lod::Half, Lod::Quarter];
+
+    // How many blocks along each side of a cell
+    pub fn scale(self) -> usize {
+        1 << self as usize
+    }
+}
+
+// How far away each coarser level starts, in chunks
+const LOD_DISTANCES: [f32; 2] = [3.0, 5.0];
+// How far past a boundary a chunk has to be before it switches, so one
+// sat right on the boundary doesn't keep getting remeshed
+const HYSTERESIS: f32 = 0.5;
+
+// The level for a chunk `distance` chunks away, sticking with `current`
+// until it's clearly past a boundary
+pub fn select_lod(current: Option<Lod>, distance: f32) -> Lod {
+    let level = |margin: f32| {
+        LOD_DISTANCES
+            .iter()
+            .filter(|start| distance >= *start + margin)
+            .count()
+    };
+    let index = match current {
+        Some(current) => (current as usize).clamp(level(HYSTERESIS), level(-HYSTERESIS)),
+        None => level(0.0),
+    };
+    Lod::ALL[index]
+}
+
+// A chunk shrunk down to cells of `scale` blocks a side, with a layer of
+// cells from the chunks above and below it to mesh against
+#[derive(Debug)]
+pub struct VoxelGrid {
+    scale: usize,
+    width: usize,
+    // Whether the chunks below and above are there, so their layers of
+    // cells are worth meshing against
+    loaded: [bool; 2],
+    // Starting from the layer below the chunk
+    cells: Vec<Option<BlockType>>,
+    light: Vec<Light>,
+}
+
+impl VoxelGrid {
+    // Each cell takes whichever block fills most of it. Air has to beat
+    // every block to win, so thin layers like the surface don't vanish.
+    pub fn downsample(world: &ChunkManager, origin: Point3<i32>, scale: usize) -> Self {
+        let width = CHUNK_WIDTH / scale;
+        let layers = width + 2;
+        let mut cells = Vec::with_capacity(width * width * layers);
+        let mut light = Vec::with_capacity(width * width * layers);
+        let chunk_width = CHUNK_WIDTH as i32;
+        let loaded = [
+            world
+                .chunks
+                .contains_key(&(origin - Vector3::new(0, 0, chunk_width))),
+            world
+                .chunks
+                .contains_key(&(origin + Vector3::new(0, 0, chunk_width))),
+        ];
+
+        for z in -1..=width as i32 {
+            for y in 0..width {
+                for x in 0..width {
+                    let mut counts: Vec<(BlockType, usize)> = Vec::new();
+                    let mut air = 0;
+                    let (mut sky, mut block_light) = (0, 0);
+
+                    for dz in 0..scale {
+                        for dy in 0..scale {
+                            for dx in 0..scale {
+                                let loc = origin
+                                    + Vector3::new(
+                                        (x * scale + dx) as i32,
+                                        (y * scale + dy) as i32,
+                                        z * scale as i32 + dz as i32,
+                                    );
+                                let cell_light = lighting::light_at(world, loc);
+                                sky = sky.max(cell_light.sky());
+                                block_light = block_light.max(cell_light.block());
+
+                                match world.get_block(loc) {
+                                    Some(block) => {
+                                        let block_type = block.block_type();
+                                        match counts.iter_mut().find(|(t, _)| *t == block_type) {
+                                            Some((_, count)) => *count += 1,
+                                            None => counts.push((block_type, 1)),
+                                        }
+                                    }
+                                    None => air += 1,
+                                }
+                            }
+                        }
+                    }
+
+                    // The first block to reach the top count wins a tie
+                    let dominant =
+                        counts
+                            .iter()
+                            .fold(
+                                None,
+                                |best: Option<(BlockType, usize)>, (t, count)| match best {
+                                    Some((_, best_count)) if best_count >= *count => best,
+                                    _ => Some((*t, *count)),
+                                },
+                            );
+                    cells.push(dominant.filter(|(_, count)| *count >= air).map(|(t, _)| t));
+                    light.push(Light::new(sky, block_light));
+                }
+            }
+        }
+
+        Self {
+            scale,
+            width,
+            loaded,
+            cells,
+            light,
+        }
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.width + y) * self.width + x
+    }
+
+    fn contains(&self, loc: Point3<i32>) -> bool {
+        let width = self.width as i32;
+        (0..width).contains(&loc.x) && (0..width).contains(&loc.y) && (-1..=width).contains(&loc.z)
+    }
+
+    // Whether a cell is in one of the layers borrowed from a chunk that
+    // isn't loaded
+    fn unloaded(&self, loc: Point3<i32>) -> bool {
+        (loc.z < 0 && !self.loaded[0]) || (loc.z >= self.width as i32 && !self.loaded[1])
+    }
+
+    pub fn get(&self, loc: Point3<i32>) -> Option<BlockType> {
+        if !self.contains(loc) {
+            return None;
+        }
+        self.cells[self.index(loc.x as usize, loc.y as usize, (loc.z + 1) as usize)]
+    }
+
+    // Outside the grid is taken to be open sky
+    fn light(&self, loc: Point3<i32>) -> Light {
+        if !self.contains(loc) {
+            return Light::new(MAX_LIGHT, 0);
+        }
+        self.light[self.index(loc.x as usize, loc.y as usize, (loc.z + 1) as usize)]
+    }
+}
+
+// Builds a mesh from a downsampled chunk. Without the chunks alongside
+// to hand, faces along the chunk's sides are left out, apart from the
+// ones at the surface. Those hang a skirt a cell further down, which
+// covers the gap against a neighbour meshed at another level.
+pub fn build_lod_mesh(
+    grid: &VoxelGrid,
+    origin: Point3<i32>,
+    texture_manager: &texture::TextureManager,
+) -> ChunkMeshData {
+    let mut mesh = ChunkMeshData::default();
+    let scale = grid.scale as f32;
+
+    for z in 0..grid.width as i32 {
+        for y in 0..grid.width as i32 {
+            for x in 0..grid.width as i32 {
+                let cell = Point3::new(x, y, z);
+                let Some(block_type) = grid.get(cell) else {
+                    continue;
+                };
+                let tex_idx = texture_manager.lookup_idx(block_type.tex_label()).unwrap() as u32;
+
+                for face in &FACES {
+                    let facing = cell + face.normal;
+                    let skirt = if !grid.contains(facing) {
+                        let above = grid.get(cell + Vector3::unit_z());
+                        if above.is_some_and(|b| b.is_opaque()) {
+                            continue;
+                        }
+                        true
+                    } else if grid.unloaded(facing) {
+                        // Closed up until the chunk arrives, like full
+                        // detail meshes
+                        continue;
+                    } else {
+                        let neighbour = grid.get(facing);
+                        if neighbour.is_some_and(|b| b.is_opaque() || b == block_type) {
+                            continue;
+                        }
+                        false
+                    };
+
+                    let normal = face.normal.cast::<f32>().unwrap().into();
+                    let light = grid.light(facing).brightness();
+                    let base = [
+                        origin.x as f32 + x as f32 * scale,
+                        origin.y as f32 + y as f32 * scale,
+                        origin.z as f32 + z as f32 * scale,
+                    ];
+
+                    mesh.part_mut(block_type.material())
+                        .push_quad(std::array::from_fn(|i| {
+                            let corner = face.corners[i];
+                            let mut position = [
+                                base[0] + corner[0] * scale,
+                                base[1] + corner[1] * scale,
+                                base[2] + corner[2] * scale,
+                            ];
+                            if skirt && corner[2] == 0.0 {
+                                position[2] -= scale;
+                            }
+                            BlockVertex {
+                                position,
+                                tex_coords: FACE_TEX_COORDS[i],
+                                normal,
+                                tex_idx,
+                                light,
+                                ao: 1.0,
+                            }
+                        }));
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+// Meshes a chunk at the given level of detail
+pub fn build_chunk_mesh(
+    world: &ChunkManager,
+    origin: Point3<i32>,
+    lod: Lod,
+    texture_manager: &texture::TextureManager,
+) -> ChunkMeshData {
+    match (lod, world.chunks.get(&origin)) {
+        (Lod::Full, _) | (_, None) => mesh::build_chunk_mesh(world, origin, texture_manager),
+        (_, Some(chunk)) if chunk.is_empty() => ChunkMeshData::default(),
+        (lod, Some(_)) => build_lod_mesh(
+            &VoxelGrid::downsample(world, origin, lod.scale()),
+            origin,
+            texture_manager,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{Block, Chunk};
+
+    use super::*;
+
+    fn texture_manager() -> texture::TextureManager {
+        let mut builder = texture::TextureManagerBuilder::new(None, None);
+        for label in ["dirt", "stone", "water"] {
+            builder.add_texture(
+                label,
+                texture::Texture::from_image(label, &image::DynamicImage::new_rgba8(1, 1)),
+            );
+        }
+        texture::TextureManager::from(builder)
+    }
+
+    fn place(world: &mut ChunkManager, x: i32, y: i32, z: i32, block_type: BlockType) {
+        world
+            .set_block(Point3::new(x, y, z), Block::new(block_type))
+            .unwrap();
+    }
+
+    // A column of three chunks, stone from the bottom up to `height`
+    fn flat_world(height: i32) -> ChunkManager {
+        let mut world = ChunkManager::default();
+        for oz in [-16, 0, 16] {
+            let mut chunk = Chunk::gen_empty_chunk(Point3::new(0, 0, oz));
+            for z in oz..height.min(oz + CHUNK_WIDTH as i32) {
+                for y in 0..CHUNK_WIDTH as i32 {
+                    for x in 0..CHUNK_WIDTH as i32 {
+                        chunk
+                            .set_block(Point3::new(x, y, z), Block::new(BlockType::Stone))
+                            .unwrap();
+                    }
+                }
+            }
+            world.insert_chunk(chunk);
+        }
+        world
+    }
+
+    #[test]
+    fn test_lod_hysteresis() {
+        assert_eq!(select_lod(None, 1.0), Lod::Full);
+        assert_eq!(select_lod(None, 3.2), Lod::Half);
+        assert_eq!(select_lod(None, 6.0), Lod::Quarter);
+
+        // Just over a boundary isn't enough to switch either way
+        assert_eq!(select_lod(Some(Lod::Full), 3.2), Lod::Full);
+        assert_eq!(select_lod(Some(Lod::Half), 2.8), Lod::Half);
+        assert_eq!(select_lod(Some(Lod::Full), 3.6), Lod::Half);
+        assert_eq!(select_lod(Some(Lod::Half), 2.4), Lod::Full);
+
+        // Jumping straight past several boundaries still gets there
+        assert_eq!(select_lod(Some(Lod::Full), 8.0), Lod::Quarter);
+        assert_eq!(select_lod(Some(Lod::Quarter), 0.0), Lod::Full);
+    }
+
+    #[test]
+    fn test_downsample_dominant_block() {
+        let mut world = ChunkManager::default();
+        world.insert_chunk(Chunk::gen_empty_chunk(Point3::new(0, 0, 0)));
+        // Half stone, half air keeps the stone
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            place(&mut world, x, y, 0, BlockType::Stone);
+        }
+        // Three dirt against two stone, with the rest air
+        for x in 2..4 {
+            place(&mut world, x, 0, 0, BlockType::Stone);
+        }
+        place(&mut world, 2, 1, 0, BlockType::Dirt);
+        place(&mut world, 3, 1, 0, BlockType::Dirt);
+        place(&mut world, 2, 0, 1, BlockType::Dirt);
+        // Mostly air
+        place(&mut world, 4, 0, 0, BlockType::Stone);
+        place(&mut world, 5, 0, 0, BlockType::Dirt);
+
+        let grid = VoxelGrid::downsample(&world, Point3::new(0, 0, 0), 2);
+        assert_eq!(grid.get(Point3::new(0, 0, 0)), Some(BlockType::Stone));
+        assert_eq!(grid.get(Point3::new(1, 0, 0)), Some(BlockType::Dirt));
+        assert_eq!(grid.get(Point3::new(2, 0, 0)), None);
+        assert_eq!(grid.get(Point3::new(0, 0, 1)), None);
+        assert_eq!(grid.get(Point3::new(8, 0, 0)), None);
+        // Nothing loaded underneath to mesh against
+        assert!(grid.unloaded(Point3::new(0, 0, -1)));
+        assert!(!grid.unloaded(Point3::new(0, 0, 7)));
+    }
+
+    #[test]
+    fn test_coarse_mesh_has_fewer_faces() {
+        let world = flat_world(2);
+
+        let mesh_at = |lod: Lod, oz: i32| {
+            build_chunk_mesh(&world, Point3::new(0, 0, oz), lod, &texture_manager())
+        };
+        let full = mesh_at(Lod::Full, 0);
+        let half = mesh_at(Lod::Half, 0);
+        let quarter = mesh_at(Lod::Quarter, 0);
+
+        let quads = |mesh: &ChunkMeshData| mesh.opaque.vertices.len() / 4;
+        assert_eq!(quads(&full), 16 * 16);
+        // Tops, plus a skirt along each side
+        assert_eq!(quads(&half), 8 * 8 + 4 * 8);
+        assert_eq!(quads(&quarter), 4 * 4 + 4 * 4);
+
+        // The surface gets rounded up to a whole cell
+        let tops_at = |mesh: &ChunkMeshData, height: f32| {
+            mesh.opaque
+                .vertices
+                .iter()
+                .filter(|v| v.normal == [0.0, 0.0, 1.0])
+                .all(|v| v.position[2] == height)
+        };
+        assert!(tops_at(&half, 2.0));
+        assert!(tops_at(&quarter, 4.0));
+
+        // Under the surface, the cells of the chunk above hide the top
+        for lod in [Lod::Full, Lod::Half, Lod::Quarter] {
+            assert!(mesh_at(lod, -16).opaque.vertices.is_empty());
+        }
+        // And there's nothing at all above it
+        assert!(mesh_at(Lod::Quarter, 16).opaque.vertices.is_empty());
+    }
+
+    #[test]
+    fn test_skirts_hang_below_the_surface() {
+        let world = flat_world(0);
+        let grid = VoxelGrid::downsample(&world, Point3::new(0, 0, -16), 4);
+        let mesh = build_lod_mesh(&grid, Point3::new(32, 16, -16), &texture_manager());
+
+        let skirt = mesh
+            .opaque
+            .vertices
+            .chunks(4)
+            .find(|quad| quad[0].normal == [1.0, 0.0, 0.0])
+            .unwrap();
+        for v in skirt {
+            assert_eq!(v.position[0], 48.0);
+            assert!((16.0..=32.0).contains(&v.position[1]));
+            assert!(v.position[2] == 0.0 || v.position[2] == -8.0);
+            // Lit from the open air outside
+            assert_eq!(v.light[0], 1.0);
+        }
+    }
+
+    #[test]
+    fn test_lod_mesh_keeps_materials_apart() {
+        let mut world = flat_world(4);
+        for y in 0..4 {
+            for x in 0..4 {
+                place(&mut world, x, y, 4, BlockType::Water);
+                place(&mut world, x, y, 5, BlockType::Water);
+            }
+        }
+
+        let mesh = build_lod_mesh(
+            &VoxelGrid::downsample(&world, Point3::new(0, 0, 0), 4),
+            Point3::new(0, 0, 0),
+            &texture_manager(),
+        );
+        // The water cell's top, its two sides against air, and skirts on
+        // the two sides along the edge of the chunk
+        assert_eq!(mesh.translucent.vertices.len() / 4, 5);
+        assert!(!mesh.opaque.vertices.is_empty());
+    }
+}
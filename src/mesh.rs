@@ -0,0 +1,720 @@
+use cgmath::{MetricSpace, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    chunk::{point_to_world, BlockType, ChunkManager, Material},
+    lighting, model, texture,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BlockVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub tex_idx: u32,
+    // Sky and block light brightness
+    pub light: [f32; 2],
+    // How much of the ambient light reaches this corner, from 0 to 1
+    pub ao: f32,
+}
+
+impl model::Vertex for BlockVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<BlockVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // position
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                // tex_coords
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                // normal
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                // tex_idx
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                // light
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                // ao
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+pub struct Face {
+    pub normal: Vector3<i32>,
+    // Counter clockwise seen from outside the block, starting from the
+    // bottom left of the texture
+    pub corners: [[f32; 3]; 4],
+}
+
+pub const FACES: [Face; 6] = [
+    Face {
+        normal: Vector3::new(1, 0, 0),
+        corners: [
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+        ],
+    },
+    Face {
+        normal: Vector3::new(-1, 0, 0),
+        corners: [
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ],
+    },
+    Face {
+        normal: Vector3::new(0, 1, 0),
+        corners: [
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+        ],
+    },
+    Face {
+        normal: Vector3::new(0, -1, 0),
+        corners: [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+        ],
+    },
+    Face {
+        normal: Vector3::new(0, 0, 1),
+        corners: [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ],
+    },
+    Face {
+        normal: Vector3::new(0, 0, -1),
+        corners: [
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ],
+    },
+];
+
+pub const FACE_TEX_COORDS: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+
+// Brightness for each ambient occlusion level, from a fully enclosed
+// corner up to an open one
+const AO_CURVE: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+#[derive(Debug, Default, Clone)]
+pub struct MeshData {
+    pub vertices: Vec<BlockVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn push_quad(&mut self, vertices: [BlockVertex; 4]) {
+        let start = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&vertices);
+
+        let order = if flip_quad(&vertices) {
+            [1, 2, 3, 1, 3, 0]
+        } else {
+            [0, 1, 2, 0, 2, 3]
+        };
+        self.indices.extend(order.iter().map(|i| start + i));
+    }
+
+    fn quad_centre(&self, quad: &[u32]) -> Point3<f32> {
+        // Each quad's six indices only ever point at its own four vertices
+        let first = (quad.iter().min().unwrap() / 4 * 4) as usize;
+        let sum = self.vertices[first..first + 4]
+            .iter()
+            .fold([0.0; 3], |acc, v| {
+                [
+                    acc[0] + v.position[0],
+                    acc[1] + v.position[1],
+                    acc[2] + v.position[2],
+                ]
+            });
+        Point3::new(sum[0] / 4.0, sum[1] / 4.0, sum[2] / 4.0)
+    }
+
+    // Reorders the quads so the furthest from `eye` get drawn first, which
+    // blending needs to come out right
+    pub fn sort_back_to_front(&mut self, eye: Point3<f32>) {
+        let mut quads = self
+            .indices
+            .chunks(6)
+            .map(|quad| (self.quad_centre(quad).distance2(eye), quad.to_vec()))
+            .collect::<Vec<_>>();
+        quads.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        self.indices = quads.into_iter().flat_map(|(_, quad)| quad).collect();
+    }
+}
+
+// Blocks are meshed into a separate part for each material, as each is
+// drawn with its own pipeline
+#[derive(Debug, Default)]
+pub struct ChunkMeshData {
+    pub opaque: MeshData,
+    pub cutout: MeshData,
+    pub translucent: MeshData,
+}
+
+impl ChunkMeshData {
+    pub fn is_empty(&self) -> bool {
+        self.opaque.indices.is_empty()
+            && self.cutout.indices.is_empty()
+            && self.translucent.indices.is_empty()
+    }
+
+    pub fn part_mut(&mut self, material: Material) -> &mut MeshData {
+        match material {
+            Material::Opaque => &mut self.opaque,
+            Material::Cutout => &mut self.cutout,
+            Material::Translucent => &mut self.translucent,
+        }
+    }
+}
+
+fn vertex_shade(vertex: &BlockVertex) -> f32 {
+    vertex.ao * vertex.light[0].max(vertex.light[1])
+}
+
+// Quads are split into two triangles along one of their diagonals, and
+// the shading is interpolated across each triangle. Splitting along the
+// diagonal between the two brighter corners keeps a single dark corner
+// from smearing across the whole face.
+pub fn flip_quad(vertices: &[BlockVertex; 4]) -> bool {
+    let shade = vertices.each_ref().map(vertex_shade);
+    shade[0] + shade[2] < shade[1] + shade[3]
+}
+
+// The classic voxel ambient occlusion value for a corner, from 0 (fully
+// enclosed) to 3 (open), given which of the two blocks along the edges
+// and the block on the diagonal are solid
+pub fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as u8 + side2 as u8 + corner as u8)
+    }
+}
+
+fn occludes(world: &ChunkManager, loc: Point3<i32>) -> bool {
+    world
+        .get_block(loc)
+        .is_some_and(|block| block.block_type().is_opaque())
+}
+
+// The ambient occlusion and smoothed light for one corner of a face.
+// `facing` is the space the face looks into, and the neighbours are found
+// by stepping from it towards the corner along the face's two axes.
+fn corner_lighting(
+    world: &ChunkManager,
+    face: &Face,
+    facing: Point3<i32>,
+    corner: [f32; 3],
+) -> (f32, [f32; 2]) {
+    let mut steps = [Vector3::new(0, 0, 0); 2];
+    let mut n = 0;
+    for axis in 0..3 {
+        if face.normal[axis] == 0 {
+            steps[n][axis] = if corner[axis] > 0.5 { 1 } else { -1 };
+            n += 1;
+        }
+    }
+
+    let side1 = facing + steps[0];
+    let side2 = facing + steps[1];
+    let diagonal = facing + steps[0] + steps[1];
+
+    let (s1, s2, c) = (
+        occludes(world, side1),
+        occludes(world, side2),
+        occludes(world, diagonal),
+    );
+    let ao = AO_CURVE[vertex_ao(s1, s2, c) as usize];
+
+    // Average the light of every open space touching the corner. The
+    // diagonal can't be seen past two solid sides, so it's left out then.
+    let mut samples = vec![facing];
+    if !s1 {
+        samples.push(side1);
+    }
+    if !s2 {
+        samples.push(side2);
+    }
+    let diagonal_visible = !(s1 && s2);
+    if !c && diagonal_visible {
+        samples.push(diagonal);
+    }
+
+    let mut light = [0.0; 2];
+    for loc in &samples {
+        let brightness = lighting::light_at(world, *loc).brightness();
+        light[0] += brightness[0];
+        light[1] += brightness[1];
+    }
+    let count = samples.len() as f32;
+
+    (ao, [light[0] / count, light[1] / count])
+}
+
+// Whether the face of a block that looks into `loc` can be seen.
+// Transparent blocks of the same type merge together, like a body of
+// water, so the faces between them are hidden.
+fn face_visible(world: &ChunkManager, block_type: BlockType, loc: Point3<i32>) -> bool {
+    // Leave the borders of unloaded chunks closed up, they get
+    // remeshed once their neighbour arrives
+    world.get_light(loc).is_some()
+        && !world
+            .get_block(loc)
+            .is_some_and(|block| block.block_type().is_opaque() || block.block_type() == block_type)
+}
+
+// Builds the visible faces of every block in a chunk. Each corner of
+// a face is lit by the spaces around it, and darkened by the blocks
+// crowding it.
+pub fn build_chunk_mesh(
+    world: &ChunkManager,
+    origin: Point3<i32>,
+    texture_manager: &texture::TextureManager,
+) -> ChunkMeshData {
+    let mut mesh = ChunkMeshData::default();
+    let Some(chunk) = world.chunks.get(&origin) else {
+        return mesh;
+    };
+    if world.chunk_hidden(origin) {
+        return mesh;
+    }
+
+    for (loc, block) in chunk.iter_blocks() {
+        let block_type = block.block_type();
+        let tex_idx = texture_manager.lookup_idx(block_type.tex_label()).unwrap() as u32;
+        let part = mesh.part_mut(block_type.material());
+
+        for face in &FACES {
+            let facing = loc + face.normal;
+            if !face_visible(world, block_type, facing) {
+                continue;
+            }
+
+            let normal = face.normal.cast::<f32>().unwrap().into();
+
+            part.push_quad(std::array::from_fn(|i| {
+                let corner = face.corners[i];
+                let (ao, light) = corner_lighting(world, face, facing, corner);
+                BlockVertex {
+                    position: [
+                        loc.x as f32 + corner[0],
+                        loc.y as f32 + corner[1],
+                        loc.z as f32 + corner[2],
+                    ],
+                    tex_coords: FACE_TEX_COORDS[i],
+                    normal,
+                    tex_idx,
+                    light,
+                    ao,
+                }
+            }));
+        }
+    }
+
+    mesh
+}
+
+// One material's part of a chunk mesh, uploaded to the GPU
+struct MeshBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    n_indices: u32,
+}
+
+impl MeshBuffers {
+    fn new(device: &wgpu::Device, data: &MeshData) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Vertex Buffer"),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        // Translucent indices get rewritten as the camera moves
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Index Buffer"),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            n_indices: data.indices.len() as u32,
+        }
+    }
+}
+
+// Translucent blocks get their own buffers, as they're sorted per chunk.
+// Opaque and cutout blocks live in the shared `gpu_cull::ChunkGeometry`.
+pub struct ChunkMesh {
+    translucent: MeshBuffers,
+    // Kept around to re-sort when the camera moves
+    translucent_data: MeshData,
+    // The block the camera was in at the last sort
+    sorted_from: Option<Point3<i32>>,
+}
+
+impl ChunkMesh {
+    pub fn new(device: &wgpu::Device, data: &ChunkMeshData) -> Self {
+        Self {
+            translucent: MeshBuffers::new(device, &data.translucent),
+            translucent_data: data.translucent.clone(),
+            sorted_from: None,
+        }
+    }
+
+    pub fn has_translucent(&self) -> bool {
+        self.translucent.n_indices > 0
+    }
+
+    // Only needs doing once the camera moves into another block
+    pub fn sort_translucent(&mut self, queue: &wgpu::Queue, eye: Point3<f32>) {
+        let eye_block = point_to_world(eye);
+        if !self.has_translucent() || self.sorted_from == Some(eye_block) {
+            return;
+        }
+
+        self.translucent_data.sort_back_to_front(eye);
+        queue.write_buffer(
+            &self.translucent.index_buffer,
+            0,
+            bytemuck::cast_slice(&self.translucent_data.indices),
+        );
+        self.sorted_from = Some(eye_block);
+    }
+
+    pub fn draw_translucent(&self, render_pass: &mut wgpu::RenderPass) {
+        if !self.has_translucent() {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.translucent.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(
+            self.translucent.index_buffer.slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+        render_pass.draw_indexed(0..self.translucent.n_indices, 0, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::{Block, BlockType, Chunk},
+        lighting::{level_brightness, MAX_LIGHT},
+    };
+
+    use super::*;
+
+    fn texture_manager() -> texture::TextureManager {
+        let mut builder = texture::TextureManagerBuilder::new(None, None);
+        for label in ["dirt", "stone", "door", "lamp", "water", "glass", "leaves"] {
+            builder.add_texture(
+                label,
+                texture::Texture::from_image(label, &image::DynamicImage::new_rgba8(1, 1)),
+            );
+        }
+        texture::TextureManager::from(builder)
+    }
+
+    fn world() -> ChunkManager {
+        let mut world = ChunkManager::default();
+        world.insert_chunk(Chunk::gen_empty_chunk(Point3::new(0, 0, 0)));
+        world
+    }
+
+    fn place(world: &mut ChunkManager, x: i32, y: i32, z: i32, block_type: BlockType) {
+        world
+            .set_block(Point3::new(x, y, z), Block::new(block_type))
+            .unwrap();
+    }
+
+    // Everything in the tests is placed in the same chunk
+    fn build(world: &ChunkManager) -> ChunkMeshData {
+        build_chunk_mesh(world, Point3::new(0, 0, 0), &texture_manager())
+    }
+
+    fn face_normals(mesh: &MeshData) -> Vec<[f32; 3]> {
+        mesh.vertices.chunks(4).map(|quad| quad[0].normal).collect()
+    }
+
+    #[test]
+    fn test_single_block() {
+        let mut world = world();
+        place(&mut world, 4, 4, 4, BlockType::Stone);
+
+        let mesh = build(&world);
+        assert_eq!(mesh.opaque.vertices.len(), 24);
+        assert_eq!(mesh.opaque.indices.len(), 36);
+
+        // Every face lies on the block's surface, facing out
+        for quad in mesh.opaque.vertices.chunks(4) {
+            for v in quad {
+                for axis in 0..3 {
+                    assert!((4.0..=5.0).contains(&v.position[axis]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hidden_faces_culled() {
+        let mut world = world();
+        place(&mut world, 4, 4, 4, BlockType::Stone);
+        place(&mut world, 5, 4, 4, BlockType::Dirt);
+
+        let mesh = build(&world);
+        assert_eq!(mesh.opaque.vertices.len(), 10 * 4);
+
+        // Doors don't hide what's behind them, but are hidden by the
+        // stone next to them
+        place(&mut world, 4, 5, 4, BlockType::Door { open: false });
+        let mesh = build(&world);
+        assert_eq!(mesh.opaque.vertices.len(), 10 * 4);
+        assert_eq!(mesh.cutout.vertices.len(), 5 * 4);
+    }
+
+    #[test]
+    fn test_unloaded_borders_closed() {
+        let mut world = world();
+        place(&mut world, 15, 4, 4, BlockType::Stone);
+
+        let mesh = build(&world);
+        assert!(!face_normals(&mesh.opaque).contains(&[1.0, 0.0, 0.0]));
+        assert_eq!(mesh.opaque.vertices.len(), 5 * 4);
+    }
+
+    #[test]
+    fn test_faces_take_light_from_outside() {
+        let mut world = world();
+        // A roofed block with a lamp next to it
+        place(&mut world, 4, 4, 4, BlockType::Stone);
+        for x in 0..10 {
+            for y in 0..10 {
+                place(&mut world, x, y, 8, BlockType::Stone);
+            }
+        }
+        place(&mut world, 6, 4, 4, BlockType::Lamp);
+
+        let mesh = build(&world);
+        // Average light over the corners of one of the stone's faces
+        let face_light = |normal: [f32; 3]| {
+            let quad = mesh
+                .opaque
+                .vertices
+                .chunks(4)
+                .find(|quad| {
+                    quad[0].normal == normal
+                        && quad[0].position[0] >= 4.0
+                        && quad[0].position[0] <= 5.0
+                        && quad[0].position[2] <= 5.0
+                })
+                .unwrap();
+            let sum = quad.iter().fold([0.0, 0.0], |acc, v| {
+                [acc[0] + v.light[0], acc[1] + v.light[1]]
+            });
+            [sum[0] / 4.0, sum[1] / 4.0]
+        };
+
+        // The side facing the lamp is lit by it, the far side only by
+        // light that makes it round the block
+        assert!(face_light([1.0, 0.0, 0.0])[1] > level_brightness(MAX_LIGHT - 2));
+        assert!(face_light([-1.0, 0.0, 0.0])[1] < level_brightness(MAX_LIGHT - 4));
+        // The roof keeps direct sky light off
+        assert!(face_light([0.0, 0.0, 1.0])[0] < 1.0);
+    }
+
+    #[test]
+    fn test_vertex_ao() {
+        // (side1, side2, corner) -> ao
+        let cases = [
+            ((false, false, false), 3),
+            ((true, false, false), 2),
+            ((false, true, false), 2),
+            ((false, false, true), 2),
+            ((true, false, true), 1),
+            ((false, true, true), 1),
+            // Two sides hide the corner completely
+            ((true, true, false), 0),
+            ((true, true, true), 0),
+        ];
+
+        for ((side1, side2, corner), ao) in cases {
+            assert_eq!(vertex_ao(side1, side2, corner), ao);
+        }
+    }
+
+    #[test]
+    fn test_open_block_is_evenly_lit() {
+        let mut world = world();
+        place(&mut world, 4, 4, 4, BlockType::Stone);
+
+        let mesh = build(&world);
+        for v in mesh
+            .opaque
+            .vertices
+            .iter()
+            .filter(|v| v.normal == [0.0, 0.0, 1.0])
+        {
+            assert_eq!(v.ao, 1.0);
+            assert_eq!(v.light, [1.0, level_brightness(0)]);
+        }
+    }
+
+    #[test]
+    fn test_ao_in_a_corner() {
+        let mut world = world();
+        place(&mut world, 4, 4, 4, BlockType::Stone);
+        // Two walls meeting over the block's (4, 4) corner
+        place(&mut world, 3, 4, 5, BlockType::Stone);
+        place(&mut world, 4, 3, 5, BlockType::Stone);
+
+        let mesh = build(&world);
+        let top = mesh
+            .opaque
+            .vertices
+            .chunks(4)
+            .find(|quad| quad[0].normal == [0.0, 0.0, 1.0] && quad[0].position[2] == 5.0)
+            .unwrap();
+        let ao_at = |x: f32, y: f32| {
+            top.iter()
+                .find(|v| v.position[0] == x && v.position[1] == y)
+                .unwrap()
+                .ao
+        };
+
+        assert_eq!(ao_at(4.0, 4.0), AO_CURVE[0]);
+        assert_eq!(ao_at(5.0, 4.0), AO_CURVE[2]);
+        assert_eq!(ao_at(4.0, 5.0), AO_CURVE[2]);
+        assert_eq!(ao_at(5.0, 5.0), AO_CURVE[3]);
+    }
+
+    #[test]
+    fn test_quad_flip() {
+        let vertex = |ao: f32| BlockVertex {
+            position: [0.0; 3],
+            tex_coords: [0.0; 2],
+            normal: [0.0, 0.0, 1.0],
+            tex_idx: 0,
+            light: [1.0, 0.0],
+            ao,
+        };
+
+        let even = [vertex(1.0), vertex(1.0), vertex(1.0), vertex(1.0)];
+        assert!(!flip_quad(&even));
+
+        // A single dark corner gets a triangle to itself
+        let dark_corner = [vertex(0.45), vertex(1.0), vertex(1.0), vertex(1.0)];
+        assert!(flip_quad(&dark_corner));
+
+        let mut mesh = MeshData::default();
+        mesh.push_quad(even);
+        mesh.push_quad(dark_corner);
+        assert_eq!(mesh.indices[..6], [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.indices[6..], [5, 6, 7, 5, 7, 4]);
+    }
+
+    #[test]
+    fn test_materials_meshed_apart() {
+        let mut world = world();
+        place(&mut world, 2, 2, 2, BlockType::Stone);
+        place(&mut world, 6, 2, 2, BlockType::Leaves);
+        place(&mut world, 10, 2, 2, BlockType::Water);
+
+        let mesh = build(&world);
+        assert_eq!(mesh.opaque.vertices.len(), 6 * 4);
+        assert_eq!(mesh.cutout.vertices.len(), 6 * 4);
+        assert_eq!(mesh.translucent.vertices.len(), 6 * 4);
+        assert_eq!(mesh.translucent.indices.len(), 6 * 6);
+    }
+
+    #[test]
+    fn test_faces_between_same_transparent_blocks_culled() {
+        let mut world = world();
+        // A 2x1 pool of water, with glass on one side
+        place(&mut world, 4, 4, 4, BlockType::Water);
+        place(&mut world, 5, 4, 4, BlockType::Water);
+        place(&mut world, 3, 4, 4, BlockType::Glass);
+
+        let mesh = build(&world);
+        // Water shows against the glass, and the glass against the water,
+        // but the water doesn't show inside itself
+        assert_eq!(mesh.translucent.vertices.len(), (10 + 6) * 4);
+
+        // Stone next to the glass still hides its face
+        place(&mut world, 2, 4, 4, BlockType::Stone);
+        let mesh = build(&world);
+        assert_eq!(mesh.translucent.vertices.len(), (10 + 5) * 4);
+        assert_eq!(mesh.opaque.vertices.len(), 6 * 4);
+    }
+
+    #[test]
+    fn test_sort_back_to_front() {
+        let mut world = world();
+        for x in [2, 6, 10] {
+            place(&mut world, x, 4, 4, BlockType::Glass);
+        }
+
+        let mut mesh = build(&world).translucent;
+        let eye = Point3::new(0.0, 4.5, 4.5);
+        mesh.sort_back_to_front(eye);
+
+        let distances = mesh
+            .indices
+            .chunks(6)
+            .map(|quad| mesh.quad_centre(quad).distance(eye))
+            .collect::<Vec<_>>();
+        assert!(distances.windows(2).all(|pair| pair[0] >= pair[1]));
+        // The far side of the furthest block comes first
+        assert_eq!(mesh.quad_centre(&mesh.indices[..6]).x, 11.0);
+
+        // Still the same triangles
+        assert_eq!(mesh.indices.len(), 3 * 6 * 6);
+        let mut indices = mesh.indices.clone();
+        indices.sort();
+        indices.dedup();
+        assert_eq!(indices.len(), mesh.vertices.len());
+    }
+}
@@ -0,0 +1,365 @@
+use std::time::Duration;
+
+use cgmath::{prelude::*, Point3, Rad, Vector2, Vector3};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::{
+    chunk::{point_to_world, ChunkManager},
+    entity::{Collider, Entities, EntityId, EntityManager, RenderModel, TickContext, Transform},
+    pathfinding::{self, Movement, Path, PathConfig},
+};
+
+const ANIMAL_SIZE: f32 = 0.9;
+const WANDER_SPEED: f32 = 1.5; // blocks / s
+const FLEE_SPEED: f32 = 4.0;
+const JUMP_SPEED: f32 = 5.5;
+const WANDER_RADIUS: i32 = 8;
+const FLEE_DISTANCE: f32 = 12.0;
+const FLEE_TIME: f32 = 5.0; // s
+const MIN_IDLE_TIME: f32 = 2.0;
+const MAX_IDLE_TIME: f32 = 6.0;
+// Close enough to a path node to move on to the next one
+const NODE_REACHED: f32 = 0.2;
+// Give up on a path if it stops making progress for this long
+const STUCK_TIME: f32 = 2.0;
+// How far up or down to look for ground when picking a wander target
+const SURFACE_SEARCH: i32 = 4;
+
+// Passive animals can't open doors, and are kept off long paths
+const ANIMAL_PATH_CONFIG: PathConfig = PathConfig {
+    width: ANIMAL_SIZE,
+    height: 1,
+    step_height: 0,
+    jump_height: 1,
+    max_drop: 3,
+    open_doors: false,
+    max_nodes: 400,
+};
+
+const MAX_ANIMALS: usize = 6;
+const SPAWN_CHANCE: f32 = 0.5; // per second, while under the cap
+const MIN_SPAWN_DIST: f32 = 8.0;
+const MAX_SPAWN_DIST: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MobState {
+    Idle { remaining: f32 },
+    Wander,
+    Flee { from: Point3<f32>, remaining: f32 },
+}
+
+pub struct MobData {
+    pub state: MobState,
+    path: Option<Path>,
+    next_node: usize,
+    stuck: f32,
+    rng: SmallRng,
+}
+
+impl MobData {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        Self {
+            state: MobState::Idle {
+                remaining: rng.random_range(MIN_IDLE_TIME..MAX_IDLE_TIME),
+            },
+            path: None,
+            next_node: 0,
+            stuck: 0.0,
+            rng,
+        }
+    }
+
+    fn set_path(&mut self, path: Option<Path>) {
+        self.path = path;
+        self.next_node = 1;
+        self.stuck = 0.0;
+    }
+
+    fn idle(&mut self) {
+        self.state = MobState::Idle {
+            remaining: self.rng.random_range(MIN_IDLE_TIME..MAX_IDLE_TIME),
+        };
+        self.set_path(None);
+    }
+}
+
+// `seed` drives everything the animal decides to do, so the same seed
+// always wanders the same way
+pub fn spawn_animal(entities: &mut Entities, pos: Point3<f32>, seed: u64) -> EntityId {
+    let id = entities.spawn(Transform::new(pos));
+
+    entities.velocities.insert(id, Vector3::zero());
+    entities
+        .colliders
+        .insert(id, Collider::new(ANIMAL_SIZE, ANIMAL_SIZE));
+    entities.models.insert(
+        id,
+        RenderModel {
+            label: "animal".to_string(),
+            scale: ANIMAL_SIZE / 2.0,
+            offset: Vector3::new(0.0, 0.0, ANIMAL_SIZE / 2.0),
+        },
+    );
+    entities.mobs.insert(id, MobData::new(seed));
+
+    id
+}
+
+// Makes a mob run away from whatever hit it
+pub fn scare(entities: &mut Entities, id: EntityId, from: Point3<f32>) {
+    if let Some(mob) = entities.mobs.get_mut(&id) {
+        mob.state = MobState::Flee {
+            from,
+            remaining: FLEE_TIME,
+        };
+        mob.set_path(None);
+    }
+}
+
+// The AI has to run before physics so its velocity gets applied this tick
+pub fn register_systems(manager: &mut EntityManager) {
+    manager.add_system("mob_ai", mob_ai_system);
+}
+
+// Finds the ground in a column near `z`, as the block an entity's feet
+// would be in
+fn find_surface(world: &ChunkManager, x: i32, y: i32, z: i32) -> Option<Point3<i32>> {
+    (z - SURFACE_SEARCH..=z + SURFACE_SEARCH)
+        .rev()
+        .map(|z| Point3::new(x, y, z))
+        .find(|&loc| ANIMAL_PATH_CONFIG.walkable(world, loc))
+}
+
+fn plan(world: &ChunkManager, from: Point3<i32>, to: Point3<i32>) -> Option<Path> {
+    let path = pathfinding::find_path(world, from, to, &ANIMAL_PATH_CONFIG)?;
+    (path.nodes.len() > 1).then(|| pathfinding::smooth_path(world, &path, &ANIMAL_PATH_CONFIG))
+}
+
+pub fn mob_ai_system(entities: &mut Entities, ctx: &mut TickContext, dt: Duration) {
+    let dt = dt.as_secs_f32();
+
+    for (id, mob) in entities.mobs.iter_mut() {
+        let (Some(transform), Some(vel), Some(collider)) = (
+            entities.transforms.get_mut(id),
+            entities.velocities.get_mut(id),
+            entities.colliders.get(id),
+        ) else {
+            continue;
+        };
+
+        // Only plan from solid ground, the path starts from the feet
+        let feet = point_to_world(transform.position + Vector3::new(0.0, 0.0, 0.01));
+        let world = ctx.chunk_manager;
+        // What physics actually let the mob do last tick
+        let last_speed = Vector2::new(vel.x, vel.y).magnitude();
+
+        match mob.state {
+            MobState::Idle { remaining } => {
+                let remaining = remaining - dt;
+                mob.state = MobState::Idle { remaining };
+
+                if remaining <= 0.0 && collider.on_ground {
+                    let x = feet.x + mob.rng.random_range(-WANDER_RADIUS..=WANDER_RADIUS);
+                    let y = feet.y + mob.rng.random_range(-WANDER_RADIUS..=WANDER_RADIUS);
+
+                    match find_surface(world, x, y, feet.z).and_then(|t| plan(world, feet, t)) {
+                        Some(path) => {
+                            mob.state = MobState::Wander;
+                            mob.set_path(Some(path));
+                        }
+                        None => mob.idle(),
+                    }
+                }
+            }
+            MobState::Wander => {
+                if mob.path.is_none() {
+                    mob.idle();
+                }
+            }
+            MobState::Flee { from, remaining } => {
+                let remaining = remaining - dt;
+                mob.state = MobState::Flee { from, remaining };
+
+                if remaining <= 0.0 {
+                    mob.idle();
+                } else if mob.path.is_none() && collider.on_ground {
+                    let away =
+                        Vector2::new(transform.position.x - from.x, transform.position.y - from.y);
+                    let away = if away.magnitude2() > 0.0 {
+                        away.normalize()
+                    } else {
+                        Vector2::new(1.0, 0.0)
+                    };
+                    let target = transform.position.to_vec().truncate() + away * FLEE_DISTANCE;
+
+                    // The target doesn't need to be reachable, or even
+                    // loaded, a partial path still leads away
+                    let goal =
+                        Point3::new(target.x.floor() as i32, target.y.floor() as i32, feet.z);
+                    mob.set_path(plan(world, feet, goal));
+                }
+            }
+        }
+
+        let speed = match mob.state {
+            MobState::Flee { .. } => FLEE_SPEED,
+            _ => WANDER_SPEED,
+        };
+
+        // Follow the current path
+        let Some(path) = &mob.path else {
+            vel.x = 0.0;
+            vel.y = 0.0;
+            continue;
+        };
+
+        let node = path.nodes[mob.next_node];
+        let target = Vector2::new(node.pos.x as f32 + 0.5, node.pos.y as f32 + 0.5);
+        let to_target = target - transform.position.to_vec().truncate();
+
+        if to_target.magnitude() < NODE_REACHED && (node.pos.z - feet.z).abs() <= 1 {
+            mob.next_node += 1;
+            mob.stuck = 0.0;
+            if mob.next_node >= path.nodes.len() {
+                mob.set_path(None);
+            }
+            continue;
+        }
+
+        let dir = to_target.normalize();
+        vel.x = dir.x * speed;
+        vel.y = dir.y * speed;
+        transform.yaw = Rad(dir.y.atan2(dir.x));
+
+        if node.movement == Movement::Jump && node.pos.z > feet.z && collider.on_ground {
+            vel.z = JUMP_SPEED;
+        }
+
+        // Walked into something the path didn't expect, e.g. a block
+        // placed since the path was found
+        mob.stuck = if last_speed < speed * 0.5 || !collider.on_ground {
+            mob.stuck + dt
+        } else {
+            0.0
+        };
+        if mob.stuck > STUCK_TIME {
+            mob.set_path(None);
+        }
+    }
+}
+
+// Keeps a few animals around the player
+pub fn spawn_system(entities: &mut Entities, ctx: &mut TickContext, dt: Duration) {
+    if !ctx.spawn_mobs || entities.mobs.len() >= MAX_ANIMALS {
+        return;
+    }
+
+    let mut rng = rand::rng();
+    if !rng.random_bool((SPAWN_CHANCE * dt.as_secs_f32()).min(1.0) as f64) {
+        return;
+    }
+
+    let angle = rng.random_range(0.0..std::f32::consts::TAU);
+    let dist = rng.random_range(MIN_SPAWN_DIST..MAX_SPAWN_DIST);
+    let x = (ctx.player_eye.x + angle.cos() * dist).floor() as i32;
+    let y = (ctx.player_eye.y + angle.sin() * dist).floor() as i32;
+
+    if let Some(feet) = find_surface(ctx.chunk_manager, x, y, ctx.player_eye.z.floor() as i32) {
+        spawn_animal(
+            entities,
+            Point3::new(feet.x as f32 + 0.5, feet.y as f32 + 0.5, feet.z as f32),
+            rng.random(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{chunk::flat_world, inventory::Inventory, world::TICK};
+
+    use super::*;
+
+    fn tick(manager: &mut EntityManager, world: &ChunkManager, seconds: f32) {
+        let mut inventory = Inventory::new();
+        let mut ctx = TickContext {
+            chunk_manager: world,
+            player_eye: Point3::new(100.0, 100.0, 100.0),
+            inventory: &mut inventory,
+            spawn_mobs: false,
+        };
+
+        for _ in 0..(seconds / TICK.as_secs_f32()).round() as usize {
+            manager.tick(&mut ctx, TICK);
+        }
+    }
+
+    #[test]
+    fn test_flees_from_attacker() {
+        let world = flat_world(&[(0, 0)]);
+        let mut manager = EntityManager::default();
+        let id = spawn_animal(&mut manager.entities, Point3::new(6.5, 8.5, 1.0), 1);
+
+        // Let it land first
+        tick(&mut manager, &world, 0.1);
+        scare(&mut manager.entities, id, Point3::new(4.5, 8.5, 2.0));
+        tick(&mut manager, &world, 1.5);
+
+        let pos = manager.entities.transforms[&id].position;
+        assert!(pos.x > 9.0, "{pos:?}");
+        assert!(matches!(
+            manager.entities.mobs[&id].state,
+            MobState::Flee { .. }
+        ));
+
+        tick(&mut manager, &world, FLEE_TIME);
+        assert!(matches!(
+            manager.entities.mobs[&id].state,
+            MobState::Idle { .. } | MobState::Wander
+        ));
+    }
+
+    #[test]
+    fn test_walking_on_flat_ground_isnt_stuck() {
+        let world = flat_world(&[(0, 0)]);
+        let mut manager = EntityManager::default();
+        let id = spawn_animal(&mut manager.entities, Point3::new(1.5, 8.5, 1.0), 3);
+        tick(&mut manager, &world, 0.1);
+
+        let mob = manager.entities.mobs.get_mut(&id).unwrap();
+        mob.state = MobState::Wander;
+        mob.set_path(plan(&world, Point3::new(1, 8, 1), Point3::new(14, 8, 1)));
+        tick(&mut manager, &world, 1.0);
+
+        let mob = &manager.entities.mobs[&id];
+        assert!(mob.path.is_some());
+        assert_eq!(mob.stuck, 0.0);
+        let vel = manager.entities.velocities[&id];
+        assert!((vel.x - WANDER_SPEED).abs() < 1e-4, "{vel:?}");
+    }
+
+    #[test]
+    fn test_wanders_on_walkable_ground() {
+        let world = flat_world(&[(0, 0)]);
+        let mut manager = EntityManager::default();
+        let start = Point3::new(8.5, 8.5, 1.0);
+        let id = spawn_animal(&mut manager.entities, start, 2);
+
+        let mut wandered = false;
+        let mut furthest = 0.0_f32;
+        for _ in 0..300 {
+            tick(&mut manager, &world, 0.1);
+
+            wandered |= manager.entities.mobs[&id].state == MobState::Wander;
+            let pos = manager.entities.transforms[&id].position;
+            furthest = furthest.max(pos.distance(start));
+
+            // Never walks off the edge of the world
+            assert!((0.0..16.0).contains(&pos.x) && (0.0..16.0).contains(&pos.y));
+            assert!((pos.z - 1.0).abs() < 1e-3);
+        }
+
+        assert!(wandered);
+        assert!(furthest > 1.0);
+    }
+}
@@ -47,7 +47,6 @@ pub struct Model {
 }
 
 pub struct Mesh {
-    pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub n_elements: u32,
@@ -58,6 +57,8 @@ pub struct RenderInstance {
     pub rotation: cgmath::Quaternion<f32>,
     pub scale: f32,
     pub label: String,
+    // Sky and block light brightness
+    pub light: [f32; 2],
 }
 
 impl RenderInstance {
@@ -68,8 +69,16 @@ impl RenderInstance {
                 * cgmath::Matrix4::from_scale(self.scale))
             .into(),
             tex_idx: texture_manger.lookup_idx(&self.label).unwrap() as u32,
+            light: self.light,
+            normal: self.normal_matrix().into(),
         }
     }
+
+    // Scale is uniform, so the rotation alone is the inverse transpose of
+    // the model matrix up to a scale factor the shader normalises away
+    fn normal_matrix(&self) -> cgmath::Matrix3<f32> {
+        cgmath::Matrix3::from(self.rotation)
+    }
 }
 
 #[repr(C)]
@@ -77,6 +86,8 @@ impl RenderInstance {
 pub struct RenderInstanceRaw {
     model: [[f32; 4]; 4],
     tex_idx: u32,
+    light: [f32; 2],
+    normal: [[f32; 3]; 3],
 }
 
 impl RenderInstanceRaw {
@@ -119,7 +130,60 @@ impl RenderInstanceRaw {
                     shader_location: 9,
                     format: wgpu::VertexFormat::Uint32,
                 },
+                // light
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 17]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                // normal matrix, a mat3 split into columns like the model matrix
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{prelude::*, Deg, Quaternion, Vector3};
+
+    use super::*;
+
+    #[test]
+    fn test_normals_follow_rotation() {
+        let instance = RenderInstance {
+            position: Vector3::new(3.0, -2.0, 5.0),
+            rotation: Quaternion::from_axis_angle(Vector3::unit_z(), Deg(90.0)),
+            scale: 0.45,
+            label: "animal".to_string(),
+            light: [1.0, 0.0],
+        };
+
+        let normal = instance.normal_matrix() * Vector3::unit_x();
+        assert!(
+            (normal - Vector3::unit_y()).magnitude() < 1e-5,
+            "{normal:?}"
+        );
+
+        // Translation and scale leave the up face pointing up
+        let normal = instance.normal_matrix() * Vector3::unit_z();
+        assert!(
+            (normal - Vector3::unit_z()).magnitude() < 1e-5,
+            "{normal:?}"
+        );
+    }
+}
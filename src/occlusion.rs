@@ -0,0 +1,268 @@
+use std::collections::{HashSet, VecDeque};
+
+use cgmath::Point3;
+
+use crate::{chunk::CHUNK_WIDTH, raycasting::BlockFace};
+
+// Which pairs of a chunk's faces can see each other through the
+// chunk, going only through blocks that aren't opaque
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChunkVisibility(u64);
+
+impl ChunkVisibility {
+    // Every face sees every other, as in a chunk with nothing opaque
+    pub const OPEN: Self = Self((1 << 36) - 1);
+
+    pub fn connected(&self, a: BlockFace, b: BlockFace) -> bool {
+        self.0 & (1 << (a as usize * 6 + b as usize)) != 0
+    }
+
+    fn connect(&mut self, a: BlockFace, b: BlockFace) {
+        self.0 |= 1 << (a as usize * 6 + b as usize);
+        self.0 |= 1 << (b as usize * 6 + a as usize);
+    }
+
+    // Flood fills each pocket of open blocks, and connects every face
+    // the pocket touches to each other
+    pub fn compute(is_open: impl Fn(usize, usize, usize) -> bool) -> Self {
+        const N: usize = CHUNK_WIDTH;
+        let index = |x: usize, y: usize, z: usize| (z * N + y) * N + x;
+
+        let mut visibility = Self::default();
+        let mut visited = vec![false; N * N * N];
+        let mut stack = Vec::new();
+
+        for z in 0..N {
+            for y in 0..N {
+                for x in 0..N {
+                    if visited[index(x, y, z)] || !is_open(x, y, z) {
+                        continue;
+                    }
+
+                    let mut touched = Vec::new();
+                    visited[index(x, y, z)] = true;
+                    stack.push((x, y, z));
+
+                    while let Some((x, y, z)) = stack.pop() {
+                        let neighbours = [
+                            (BlockFace::XNeg, x.checked_sub(1).map(|x| (x, y, z))),
+                            (BlockFace::XPos, (x + 1 < N).then_some((x + 1, y, z))),
+                            (BlockFace::YNeg, y.checked_sub(1).map(|y| (x, y, z))),
+                            (BlockFace::YPos, (y + 1 < N).then_some((x, y + 1, z))),
+                            (BlockFace::ZNeg, z.checked_sub(1).map(|z| (x, y, z))),
+                            (BlockFace::ZPos, (z + 1 < N).then_some((x, y, z + 1))),
+                        ];
+
+                        for (face, neighbour) in neighbours {
+                            match neighbour {
+                                // Off the edge of the chunk
+                                None => {
+                                    if !touched.contains(&face) {
+                                        touched.push(face);
+                                    }
+                                }
+                                Some((nx, ny, nz)) => {
+                                    let i = index(nx, ny, nz);
+                                    if !visited[i] && is_open(nx, ny, nz) {
+                                        visited[i] = true;
+                                        stack.push((nx, ny, nz));
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    for a in &touched {
+                        for b in &touched {
+                            visibility.connect(*a, *b);
+                        }
+                    }
+                }
+            }
+        }
+
+        visibility
+    }
+}
+
+// The chunk next to `key` across one of its faces
+pub fn step(key: Point3<i32>, face: BlockFace) -> Point3<i32> {
+    let width = CHUNK_WIDTH as i32;
+    match face {
+        BlockFace::XPos => Point3::new(key.x + width, key.y, key.z),
+        BlockFace::XNeg => Point3::new(key.x - width, key.y, key.z),
+        BlockFace::YPos => Point3::new(key.x, key.y + width, key.z),
+        BlockFace::YNeg => Point3::new(key.x, key.y - width, key.z),
+        BlockFace::ZPos => Point3::new(key.x, key.y, key.z + width),
+        BlockFace::ZNeg => Point3::new(key.x, key.y, key.z - width),
+    }
+}
+
+// Walks out from the camera's chunk, only going into a neighbour if
+// it can be seen through the chunk we came in by. Once the walk has
+// gone one way it never turns back the other, so it always heads away
+// from the camera. `visibility` is None for chunks that aren't there
+// or shouldn't be drawn, e.g. out of the frustum.
+pub fn visible_chunks(
+    start: Point3<i32>,
+    visibility: impl Fn(Point3<i32>) -> Option<ChunkVisibility>,
+) -> HashSet<Point3<i32>> {
+    let mut visible = HashSet::from([start]);
+    // Chunk, the face it was entered through, and which ways the walk
+    // has gone to get there
+    let mut queue = VecDeque::from([(start, None::<BlockFace>, 0_u8)]);
+
+    while let Some((key, entered, travelled)) = queue.pop_front() {
+        let Some(current) = visibility(key) else {
+            continue;
+        };
+
+        for face in BlockFace::ALL {
+            if travelled & (1 << face.opposite() as u8) != 0 {
+                continue;
+            }
+            if entered.is_some_and(|entered| !current.connected(entered, face)) {
+                continue;
+            }
+
+            let neighbour = step(key, face);
+            if visible.contains(&neighbour) || visibility(neighbour).is_none() {
+                continue;
+            }
+
+            visible.insert(neighbour);
+            queue.push_back((
+                neighbour,
+                Some(face.opposite()),
+                travelled | (1 << face as u8),
+            ));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const OPEN: ChunkVisibility = ChunkVisibility::OPEN;
+    const SOLID: ChunkVisibility = ChunkVisibility(0);
+
+    fn pairs(visibility: ChunkVisibility) -> usize {
+        BlockFace::ALL
+            .iter()
+            .flat_map(|a| BlockFace::ALL.iter().map(move |b| (a, b)))
+            .filter(|(a, b)| a != b && visibility.connected(**a, **b))
+            .count()
+            / 2
+    }
+
+    #[test]
+    fn test_open_and_solid_chunks() {
+        let open = ChunkVisibility::compute(|_, _, _| true);
+        assert_eq!(open, ChunkVisibility::OPEN);
+        assert_eq!(pairs(open), 15);
+        assert!(open.connected(BlockFace::XNeg, BlockFace::ZPos));
+
+        assert_eq!(pairs(ChunkVisibility::compute(|_, _, _| false)), 0);
+    }
+
+    #[test]
+    fn test_wall_splits_chunk() {
+        // A solid wall across the middle, facing x
+        let visibility = ChunkVisibility::compute(|x, _, _| x != 8);
+        assert!(!visibility.connected(BlockFace::XNeg, BlockFace::XPos));
+        assert!(visibility.connected(BlockFace::XNeg, BlockFace::YPos));
+        assert!(visibility.connected(BlockFace::XPos, BlockFace::ZNeg));
+        assert!(visibility.connected(BlockFace::YNeg, BlockFace::YPos));
+        assert!(visibility.connected(BlockFace::ZNeg, BlockFace::ZPos));
+    }
+
+    #[test]
+    fn test_winding_tunnel() {
+        // Solid apart from a tunnel that goes up from the bottom, turns
+        // and comes out the side
+        let visibility = ChunkVisibility::compute(|x, y, z| {
+            (x == 3 && y == 3 && z <= 10) || (z == 10 && y == 3 && x >= 3)
+        });
+        assert!(visibility.connected(BlockFace::ZNeg, BlockFace::XPos));
+        assert_eq!(pairs(visibility), 1);
+
+        // A pocket that doesn't reach any face connects nothing
+        let visibility =
+            ChunkVisibility::compute(|x, y, z| (4..8).contains(&x) && y == 5 && z == 5);
+        assert_eq!(visibility, ChunkVisibility::default());
+    }
+
+    fn walk(
+        start: Point3<i32>,
+        chunks: &HashMap<Point3<i32>, ChunkVisibility>,
+    ) -> HashSet<Point3<i32>> {
+        visible_chunks(start, |key| chunks.get(&key).copied())
+    }
+
+    #[test]
+    fn test_solid_rock_hides_caves() {
+        let key = |i: i32| Point3::new(i * CHUNK_WIDTH as i32, 0, 0);
+
+        // Camera, rock, then a cave, all in a row
+        let mut chunks = HashMap::from([(key(0), OPEN), (key(1), SOLID), (key(2), OPEN)]);
+        let visible = walk(key(0), &chunks);
+        // The face of the rock is still visible
+        assert!(visible.contains(&key(1)));
+        assert!(!visible.contains(&key(2)));
+
+        // Tunnel through the rock
+        let mut tunnel = ChunkVisibility::default();
+        tunnel.connect(BlockFace::XNeg, BlockFace::XPos);
+        chunks.insert(key(1), tunnel);
+        assert!(walk(key(0), &chunks).contains(&key(2)));
+
+        // Chunks that aren't loaded stop the walk
+        chunks.remove(&key(1));
+        assert_eq!(walk(key(0), &chunks), HashSet::from([key(0)]));
+    }
+
+    #[test]
+    fn test_caves_above_and_below() {
+        let key = |z: i32| Point3::new(0, 0, z * CHUNK_WIDTH as i32);
+        let chunks = HashMap::from([
+            (key(0), OPEN),
+            (key(1), SOLID),
+            (key(2), OPEN),
+            (key(3), OPEN),
+            (key(4), OPEN),
+        ]);
+
+        // From the middle of the open chunks, the ones above and below
+        let visible = walk(key(3), &chunks);
+        assert_eq!(visible, HashSet::from([key(1), key(2), key(3), key(4)]));
+    }
+
+    #[test]
+    fn test_walk_never_turns_back() {
+        // A tunnel going +x, up, then back -x over the top of itself
+        let key = |x: i32, z: i32| Point3::new(x * CHUNK_WIDTH as i32, 0, z * CHUNK_WIDTH as i32);
+        let tunnel = |a: BlockFace, b: BlockFace| {
+            let mut visibility = ChunkVisibility::default();
+            visibility.connect(a, b);
+            visibility
+        };
+
+        let chunks = HashMap::from([
+            (key(-1, 0), OPEN),
+            (key(0, 0), tunnel(BlockFace::XNeg, BlockFace::XPos)),
+            (key(1, 0), tunnel(BlockFace::XNeg, BlockFace::ZPos)),
+            (key(1, 1), tunnel(BlockFace::ZNeg, BlockFace::XNeg)),
+            (key(0, 1), OPEN),
+        ]);
+
+        let visible = walk(key(-1, 0), &chunks);
+        assert!(visible.contains(&key(1, 1)));
+        // Getting here means heading back towards the camera
+        assert!(!visible.contains(&key(0, 1)));
+    }
+}
@@ -0,0 +1,591 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use cgmath::{Point3, Vector3};
+
+use crate::chunk::{BlockType, ChunkManager};
+
+// Extra cost for each block climbed or dropped, on top of the horizontal
+// move. Kept at half a block so that the heuristic stays admissible.
+const VERTICAL_COST: f32 = 0.5;
+const JUMP_COST: f32 = 1.0;
+// How finely straight lines are checked when smoothing a path
+const SMOOTH_STEP: f32 = 0.1;
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+// How an entity gets into a node from the one before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    Start,
+    Walk,
+    Jump,
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathNode {
+    // The block the entity's feet are in
+    pub pos: Point3<i32>,
+    pub movement: Movement,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    pub nodes: Vec<PathNode>,
+    // False when the goal couldn't be reached, in which case the path
+    // leads to the closest node that was found instead
+    pub complete: bool,
+}
+
+impl Path {
+    #[cfg(test)]
+    pub fn end(&self) -> Point3<i32> {
+        self.nodes.last().unwrap().pos
+    }
+}
+
+// Describes the entity that will follow the path
+#[derive(Debug, Clone, Copy)]
+pub struct PathConfig {
+    pub width: f32,
+    // Blocks of clearance the entity needs above its feet
+    pub height: i32,
+    // How many blocks the entity can walk up without jumping
+    pub step_height: i32,
+    pub jump_height: i32,
+    pub max_drop: i32,
+    // Whether closed doors count as passable
+    pub open_doors: bool,
+    // Nodes expanded before giving up on reaching the goal
+    pub max_nodes: usize,
+}
+
+impl Default for PathConfig {
+    fn default() -> Self {
+        Self {
+            width: 0.6,
+            height: 2,
+            step_height: 0,
+            jump_height: 1,
+            max_drop: 3,
+            open_doors: false,
+            max_nodes: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenNode {
+    f: f32,
+    g: f32,
+    pos: Point3<i32>,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    // Reversed so the BinaryHeap pops the lowest cost first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f
+            .total_cmp(&self.f)
+            .then_with(|| self.g.total_cmp(&other.g))
+    }
+}
+
+impl PathConfig {
+    // Whether an entity can occupy the block
+    fn passable(&self, world: &ChunkManager, loc: Point3<i32>) -> bool {
+        if !world.is_loaded(loc) {
+            return false;
+        }
+
+        match world.get_block(loc).map(|block| block.block_type()) {
+            None | Some(BlockType::Door { open: true }) => true,
+            Some(BlockType::Door { open: false }) => self.open_doors,
+            Some(_) => false,
+        }
+    }
+
+    fn clear(&self, world: &ChunkManager, loc: Point3<i32>) -> bool {
+        (0..self.height).all(|h| self.passable(world, loc + Vector3::new(0, 0, h)))
+    }
+
+    // Doors are too thin to stand on, open or not
+    fn standable(&self, world: &ChunkManager, loc: Point3<i32>) -> bool {
+        world
+            .get_block(loc - Vector3::unit_z())
+            .is_some_and(|block| {
+                block.is_solid() && !matches!(block.block_type(), BlockType::Door { .. })
+            })
+    }
+
+    pub fn walkable(&self, world: &ChunkManager, loc: Point3<i32>) -> bool {
+        self.clear(world, loc) && self.standable(world, loc)
+    }
+
+    fn neighbours(&self, world: &ChunkManager, pos: Point3<i32>) -> Vec<(PathNode, f32)> {
+        let mut result = Vec::new();
+
+        for (dx, dy) in DIRECTIONS {
+            let target = pos + Vector3::new(dx, dy, 0);
+
+            if dx != 0 && dy != 0 {
+                // Diagonals only on flat ground, and never cutting a corner
+                if self.walkable(world, target)
+                    && self.walkable(world, pos + Vector3::new(dx, 0, 0))
+                    && self.walkable(world, pos + Vector3::new(0, dy, 0))
+                {
+                    result.push((node(target, Movement::Walk), std::f32::consts::SQRT_2));
+                }
+                continue;
+            }
+
+            if self.walkable(world, target) {
+                result.push((node(target, Movement::Walk), 1.0));
+                continue;
+            }
+
+            if !self.clear(world, target) {
+                // Something in the way, try to get up on top of it
+                for h in 1..=self.step_height.max(self.jump_height) {
+                    // Need head room to rise into first
+                    if !self.passable(world, pos + Vector3::new(0, 0, self.height + h - 1)) {
+                        break;
+                    }
+
+                    let up = target + Vector3::new(0, 0, h);
+                    if self.walkable(world, up) {
+                        let climb = VERTICAL_COST * h as f32;
+                        if h <= self.step_height {
+                            result.push((node(up, Movement::Walk), 1.0 + climb));
+                        } else {
+                            result.push((node(up, Movement::Jump), 1.0 + climb + JUMP_COST));
+                        }
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            // Open space with nothing to stand on, look for somewhere to land
+            for d in 1..=self.max_drop {
+                let down = target - Vector3::new(0, 0, d);
+                if !self.passable(world, down) {
+                    break;
+                }
+
+                if self.standable(world, down) {
+                    result.push((node(down, Movement::Drop), 1.0 + VERTICAL_COST * d as f32));
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+
+    // Whether the entity can walk in a straight line between two nodes
+    // on the same level, with its whole width on walkable ground
+    fn straight_walkable(&self, world: &ChunkManager, a: Point3<i32>, b: Point3<i32>) -> bool {
+        if a.z != b.z {
+            return false;
+        }
+
+        let start = Vector3::new(a.x as f32 + 0.5, a.y as f32 + 0.5, 0.0);
+        let end = Vector3::new(b.x as f32 + 0.5, b.y as f32 + 0.5, 0.0);
+        let steps = ((end - start).x.abs().max((end - start).y.abs()) / SMOOTH_STEP).ceil() as i32;
+        let half = self.width / 2.0;
+
+        (0..=steps).all(|i| {
+            let p = start + (end - start) * (i as f32 / steps.max(1) as f32);
+            [(-half, -half), (-half, half), (half, -half), (half, half)]
+                .iter()
+                .all(|(ox, oy)| {
+                    let loc =
+                        Point3::new((p.x + ox).floor() as i32, (p.y + oy).floor() as i32, a.z);
+                    self.walkable(world, loc)
+                })
+        })
+    }
+}
+
+fn node(pos: Point3<i32>, movement: Movement) -> PathNode {
+    PathNode { pos, movement }
+}
+
+// Octile distance across the ground, plus the cheapest possible cost
+// of changing level
+fn heuristic(a: Point3<i32>, b: Point3<i32>) -> f32 {
+    let dx = (a.x - b.x).abs() as f32;
+    let dy = (a.y - b.y).abs() as f32;
+    let dz = (a.z - b.z).abs() as f32;
+
+    dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy) + VERTICAL_COST * dz
+}
+
+// A* search across the tops of blocks. `start` and `goal` are the blocks
+// the entity's feet would be in. If the goal can't be reached within the
+// node budget, the path to the closest node found is returned instead,
+// so the caller can at least head in the right direction.
+pub fn find_path(
+    world: &ChunkManager,
+    start: Point3<i32>,
+    goal: Point3<i32>,
+    config: &PathConfig,
+) -> Option<Path> {
+    if !config.walkable(world, start) {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<Point3<i32>, (Point3<i32>, Movement)> = HashMap::new();
+    let mut g_scores = HashMap::new();
+
+    let mut closest = (start, heuristic(start, goal));
+    let mut expanded = 0;
+
+    g_scores.insert(start, 0.0);
+    open.push(OpenNode {
+        f: closest.1,
+        g: 0.0,
+        pos: start,
+    });
+
+    while let Some(current) = open.pop() {
+        // Skip entries that were superseded by a cheaper route
+        if current.g > g_scores[&current.pos] {
+            continue;
+        }
+
+        if current.pos == goal {
+            return Some(reconstruct(&came_from, start, goal, true));
+        }
+
+        expanded += 1;
+        if expanded > config.max_nodes {
+            break;
+        }
+
+        for (next, cost) in config.neighbours(world, current.pos) {
+            let g = current.g + cost;
+            if g_scores.get(&next.pos).is_some_and(|&old| old <= g) {
+                continue;
+            }
+
+            let h = heuristic(next.pos, goal);
+            if h < closest.1 {
+                closest = (next.pos, h);
+            }
+
+            g_scores.insert(next.pos, g);
+            came_from.insert(next.pos, (current.pos, next.movement));
+            open.push(OpenNode {
+                f: g + h,
+                g,
+                pos: next.pos,
+            });
+        }
+    }
+
+    if closest.0 == start {
+        None
+    } else {
+        Some(reconstruct(&came_from, start, closest.0, false))
+    }
+}
+
+fn reconstruct(
+    came_from: &HashMap<Point3<i32>, (Point3<i32>, Movement)>,
+    start: Point3<i32>,
+    end: Point3<i32>,
+    complete: bool,
+) -> Path {
+    let mut nodes = Vec::new();
+    let mut pos = end;
+
+    while pos != start {
+        let (prev, movement) = came_from[&pos];
+        nodes.push(node(pos, movement));
+        pos = prev;
+    }
+    nodes.push(node(start, Movement::Start));
+    nodes.reverse();
+
+    Path { nodes, complete }
+}
+
+// Removes nodes that can be skipped by walking in a straight line, so
+// entities don't zig-zag along the grid. Jumps and drops are kept.
+pub fn smooth_path(world: &ChunkManager, path: &Path, config: &PathConfig) -> Path {
+    let nodes = &path.nodes;
+    let mut smoothed = vec![nodes[0]];
+    let mut anchor = 0;
+
+    while anchor + 1 < nodes.len() {
+        let mut next = anchor + 1;
+        while next + 1 < nodes.len()
+            && nodes[next].movement == Movement::Walk
+            && nodes[next + 1].movement == Movement::Walk
+            && config.straight_walkable(world, nodes[anchor].pos, nodes[next + 1].pos)
+        {
+            next += 1;
+        }
+
+        smoothed.push(nodes[next]);
+        anchor = next;
+    }
+
+    Path {
+        nodes: smoothed,
+        complete: path.complete,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{flat_world, Block};
+
+    use super::*;
+
+    fn place(world: &mut ChunkManager, x: i32, y: i32, z: i32, block_type: BlockType) {
+        world
+            .set_block(Point3::new(x, y, z), Block::new(block_type))
+            .unwrap();
+    }
+
+    // A wall across the whole world at x = 6, with a gap at y = 8
+    fn wall_world(height: i32, gap: Option<BlockType>) -> ChunkManager {
+        let mut world = flat_world(&[(0, 0)]);
+        for y in 0..16 {
+            for z in 1..=height {
+                if y == 8 && z == 1 {
+                    if let Some(block_type) = gap {
+                        place(&mut world, 6, y, z, block_type);
+                    }
+                    continue;
+                }
+                if y == 8 && z == 2 {
+                    continue;
+                }
+                place(&mut world, 6, y, z, BlockType::Stone);
+            }
+        }
+        world
+    }
+
+    fn config() -> PathConfig {
+        PathConfig {
+            height: 1,
+            ..Default::default()
+        }
+    }
+
+    fn p(x: i32, y: i32, z: i32) -> Point3<i32> {
+        Point3::new(x, y, z)
+    }
+
+    #[test]
+    fn test_flat_path() {
+        let world = flat_world(&[(0, 0)]);
+        let path = find_path(&world, p(1, 1, 1), p(10, 4, 1), &config()).unwrap();
+
+        assert!(path.complete);
+        assert_eq!(path.nodes[0].pos, p(1, 1, 1));
+        assert_eq!(path.end(), p(10, 4, 1));
+        // 3 diagonal steps and 6 straight ones
+        assert_eq!(path.nodes.len(), 10);
+
+        let smoothed = smooth_path(&world, &path, &config());
+        assert_eq!(smoothed.nodes.len(), 2);
+        assert_eq!(smoothed.end(), p(10, 4, 1));
+    }
+
+    #[test]
+    fn test_jumps_low_walls() {
+        let mut world = flat_world(&[(0, 0)]);
+        for y in 0..16 {
+            place(&mut world, 6, y, 1, BlockType::Stone);
+        }
+
+        let path = find_path(&world, p(2, 2, 1), p(10, 2, 1), &config()).unwrap();
+        assert!(path.complete);
+        assert!(path
+            .nodes
+            .iter()
+            .any(|n| n.movement == Movement::Jump && n.pos == p(6, 2, 2)));
+        assert!(path.nodes.iter().any(|n| n.movement == Movement::Drop));
+
+        // Stepping up doesn't need a jump
+        let stepper = PathConfig {
+            step_height: 1,
+            ..config()
+        };
+        let path = find_path(&world, p(2, 2, 1), p(10, 2, 1), &stepper).unwrap();
+        assert!(path.complete);
+        assert!(path.nodes.iter().all(|n| n.movement != Movement::Jump));
+
+        let grounded = PathConfig {
+            jump_height: 0,
+            ..config()
+        };
+        let path = find_path(&world, p(2, 2, 1), p(10, 2, 1), &grounded).unwrap();
+        assert!(!path.complete);
+        assert_eq!(path.end().x, 5);
+    }
+
+    #[test]
+    fn test_high_walls_need_a_gap() {
+        let world = wall_world(3, None);
+        let path = find_path(&world, p(2, 2, 1), p(10, 2, 1), &config()).unwrap();
+
+        assert!(path.complete);
+        assert!(path.nodes.iter().any(|n| n.pos == p(6, 8, 1)));
+        assert!(path.nodes.iter().all(|n| n.movement != Movement::Jump));
+    }
+
+    #[test]
+    fn test_head_room_for_tall_entities() {
+        let mut world = flat_world(&[(0, 0)]);
+        // A tunnel one block high
+        for x in 4..8 {
+            for y in 0..16 {
+                place(&mut world, x, y, 2, BlockType::Stone);
+            }
+        }
+
+        let short = config();
+        assert!(
+            find_path(&world, p(2, 2, 1), p(10, 2, 1), &short)
+                .unwrap()
+                .complete
+        );
+
+        let tall = PathConfig {
+            height: 2,
+            ..config()
+        };
+        assert!(
+            !find_path(&world, p(2, 2, 1), p(10, 2, 1), &tall)
+                .unwrap()
+                .complete
+        );
+    }
+
+    #[test]
+    fn test_drops() {
+        let mut world = flat_world(&[(0, 0)]);
+        // A platform 4 blocks high over x < 5, and 2 high over 5..8
+        for x in 0..8 {
+            for y in 0..16 {
+                let height = if x < 5 { 4 } else { 2 };
+                for z in 1..=height {
+                    place(&mut world, x, y, z, BlockType::Stone);
+                }
+            }
+        }
+
+        let path = find_path(&world, p(2, 2, 5), p(12, 2, 1), &config()).unwrap();
+        assert!(path.complete);
+        let drops = path
+            .nodes
+            .iter()
+            .filter(|n| n.movement == Movement::Drop)
+            .count();
+        assert_eq!(drops, 2);
+
+        // Too far to drop straight down from the top
+        let timid = PathConfig {
+            max_drop: 1,
+            ..config()
+        };
+        assert!(
+            !find_path(&world, p(2, 2, 5), p(12, 2, 1), &timid)
+                .unwrap()
+                .complete
+        );
+    }
+
+    #[test]
+    fn test_doors() {
+        let goal = p(10, 8, 1);
+
+        let closed = wall_world(2, Some(BlockType::Door { open: false }));
+        assert!(
+            !find_path(&closed, p(2, 8, 1), goal, &config())
+                .unwrap()
+                .complete
+        );
+
+        let opener = PathConfig {
+            open_doors: true,
+            ..config()
+        };
+        assert!(
+            find_path(&closed, p(2, 8, 1), goal, &opener)
+                .unwrap()
+                .complete
+        );
+
+        let open = wall_world(2, Some(BlockType::Door { open: true }));
+        let path = find_path(&open, p(2, 8, 1), goal, &config()).unwrap();
+        assert!(path.complete);
+        assert!(path.nodes.iter().any(|n| n.pos == p(6, 8, 1)));
+    }
+
+    #[test]
+    fn test_node_budget() {
+        let world = wall_world(3, None);
+        let limited = PathConfig {
+            max_nodes: 5,
+            ..config()
+        };
+
+        let path = find_path(&world, p(2, 2, 1), p(10, 2, 1), &limited).unwrap();
+        assert!(!path.complete);
+        assert!(path.nodes.len() <= 6);
+    }
+
+    #[test]
+    fn test_unwalkable_start() {
+        let world = flat_world(&[(0, 0)]);
+        assert_eq!(find_path(&world, p(2, 2, 4), p(10, 2, 1), &config()), None);
+    }
+
+    #[test]
+    fn test_smoothing_keeps_corners() {
+        let world = wall_world(3, None);
+        let path = find_path(&world, p(2, 2, 1), p(10, 2, 1), &config()).unwrap();
+        let smoothed = smooth_path(&world, &path, &config());
+
+        assert!(smoothed.nodes.len() < path.nodes.len());
+        assert_eq!(smoothed.end(), p(10, 2, 1));
+        for pair in smoothed.nodes.windows(2) {
+            assert!(config().straight_walkable(&world, pair[0].pos, pair[1].pos));
+        }
+    }
+}
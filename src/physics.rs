@@ -0,0 +1,283 @@
+use cgmath::{Point3, Vector3};
+
+use crate::chunk::ChunkManager;
+
+// The largest distance an entity is moved in one collision step. Keeping
+// this under a block means fast movers can't tunnel through terrain.
+const MAX_STEP: f32 = 0.5;
+pub const TERMINAL_VELOCITY: f32 = 60.0; // blocks / s
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    // Builds a box from the point at the centre of its base, which is
+    // how entity positions are stored
+    pub fn from_base(base: Point3<f32>, width: f32, height: f32) -> Self {
+        let half = width / 2.0;
+        Self {
+            min: Point3::new(base.x - half, base.y - half, base.z),
+            max: Point3::new(base.x + half, base.y + half, base.z + height),
+        }
+    }
+
+    pub fn translate(&self, v: Vector3<f32>) -> Self {
+        Self {
+            min: self.min + v,
+            max: self.max + v,
+        }
+    }
+
+    pub fn expand(&self, x: f32, y: f32, z: f32) -> Self {
+        Self {
+            min: Point3::new(self.min.x - x, self.min.y - y, self.min.z - z),
+            max: Point3::new(self.max.x + x, self.max.y + y, self.max.z + z),
+        }
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && self.max.x > other.min.x
+            && self.min.y < other.max.y
+            && self.max.y > other.min.y
+            && self.min.z < other.max.z
+            && self.max.z > other.min.z
+    }
+
+    // Slab test. Returns the distance along the ray to where it enters
+    // the box, or None if it misses. `dir` should be normalised.
+    pub fn ray_intersection(&self, origin: Point3<f32>, dir: Vector3<f32>) -> Option<f32> {
+        let mut t_min = 0.0_f32;
+        let mut t_max = f32::INFINITY;
+
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t1 = (self.min[axis] - origin[axis]) / dir[axis];
+            let t2 = (self.max[axis] - origin[axis]) / dir[axis];
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+
+        (t_min <= t_max).then_some(t_min)
+    }
+
+    // All the block locations this box overlaps
+    fn blocks(&self) -> impl Iterator<Item = Point3<i32>> {
+        let min = Point3::new(
+            self.min.x.floor() as i32,
+            self.min.y.floor() as i32,
+            self.min.z.floor() as i32,
+        );
+        let max = Point3::new(
+            self.max.x.ceil() as i32,
+            self.max.y.ceil() as i32,
+            self.max.z.ceil() as i32,
+        );
+
+        (min.x..max.x).flat_map(move |x| {
+            (min.y..max.y).flat_map(move |y| (min.z..max.z).map(move |z| Point3::new(x, y, z)))
+        })
+    }
+}
+
+fn block_aabb(loc: Point3<i32>) -> Aabb {
+    let min = loc.cast::<f32>().unwrap();
+    Aabb::new(min, min + Vector3::new(1.0, 1.0, 1.0))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Collision {
+    pub x: bool,
+    pub y: bool,
+    // Hit something while moving down
+    pub ground: bool,
+    // Hit something while moving up
+    pub ceiling: bool,
+}
+
+// Moves the box along a single axis, stopping at the first solid block.
+// Returns the distance actually moved.
+fn sweep_axis(aabb: &Aabb, axis: usize, delta: f32, chunk_manager: &ChunkManager) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
+
+    let mut offset = Vector3::new(0.0, 0.0, 0.0);
+    offset[axis] = delta;
+    let moved = aabb.translate(offset);
+
+    let mut allowed = delta;
+    for loc in moved.blocks() {
+        if !chunk_manager.is_solid(loc) {
+            continue;
+        }
+
+        let block = block_aabb(loc);
+        if !moved.intersects(&block) {
+            continue;
+        }
+
+        if delta > 0.0 {
+            allowed = allowed.min(block.min[axis] - aabb.max[axis]);
+        } else {
+            allowed = allowed.max(block.max[axis] - aabb.min[axis]);
+        }
+    }
+
+    // Never move backwards out of a block we're already stuck in
+    if delta > 0.0 {
+        allowed.max(0.0)
+    } else {
+        allowed.min(0.0)
+    }
+}
+
+// Moves an axis aligned box through the world, resolving collisions with
+// solid blocks one axis at a time. Vertical movement is resolved first so
+// that entities settle onto the ground before sliding along it.
+pub fn move_and_collide(
+    aabb: Aabb,
+    displacement: Vector3<f32>,
+    chunk_manager: &ChunkManager,
+) -> (Vector3<f32>, Collision) {
+    let steps = (displacement
+        .x
+        .abs()
+        .max(displacement.y.abs())
+        .max(displacement.z.abs())
+        / MAX_STEP)
+        .ceil()
+        .max(1.0);
+    let step = displacement / steps;
+
+    let mut current = aabb;
+    let mut total = Vector3::new(0.0, 0.0, 0.0);
+    let mut collision = Collision::default();
+
+    for _ in 0..steps as usize {
+        for axis in [2, 0, 1] {
+            let moved = sweep_axis(&current, axis, step[axis], chunk_manager);
+            if moved != step[axis] {
+                match axis {
+                    0 => collision.x = true,
+                    1 => collision.y = true,
+                    _ if step[axis] < 0.0 => collision.ground = true,
+                    _ => collision.ceiling = true,
+                }
+            }
+
+            let mut offset = Vector3::new(0.0, 0.0, 0.0);
+            offset[axis] = moved;
+            current = current.translate(offset);
+            total += offset;
+        }
+    }
+
+    (total, collision)
+}
+
+// Applies gravity to the velocity, then moves the box by it. Any axis
+// that collides has its velocity zeroed.
+pub fn integrate(
+    aabb: Aabb,
+    vel: &mut Vector3<f32>,
+    gravity: f32,
+    dt: f32,
+    chunk_manager: &ChunkManager,
+) -> (Vector3<f32>, Collision) {
+    vel.z = (vel.z - gravity * dt).max(-TERMINAL_VELOCITY);
+
+    let (moved, collision) = move_and_collide(aabb, *vel * dt, chunk_manager);
+
+    if collision.x {
+        vel.x = 0.0;
+    }
+    if collision.y {
+        vel.y = 0.0;
+    }
+    if collision.ground || collision.ceiling {
+        vel.z = 0.0;
+    }
+
+    (moved, collision)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{flat_world, Block, BlockType};
+
+    use super::*;
+
+    #[test]
+    fn test_aabb_intersects() {
+        let a = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+
+        assert!(a.intersects(&a.translate(Vector3::new(0.5, 0.5, 0.5))));
+        // touching faces don't count
+        assert!(!a.intersects(&a.translate(Vector3::new(1.0, 0.0, 0.0))));
+        assert!(!a.intersects(&a.translate(Vector3::new(0.0, 0.0, -2.0))));
+    }
+
+    #[test]
+    fn test_ray_intersection() {
+        let a = Aabb::new(Point3::new(2.0, 0.0, 0.0), Point3::new(3.0, 1.0, 1.0));
+        let x = Vector3::new(1.0, 0.0, 0.0);
+
+        assert_eq!(a.ray_intersection(Point3::new(0.0, 0.5, 0.5), x), Some(2.0));
+        assert_eq!(a.ray_intersection(Point3::new(0.0, 1.5, 0.5), x), None);
+        // pointing away
+        assert_eq!(a.ray_intersection(Point3::new(0.0, 0.5, 0.5), -x), None);
+        // starting inside
+        assert_eq!(a.ray_intersection(Point3::new(2.5, 0.5, 0.5), x), Some(0.0));
+    }
+
+    #[test]
+    fn test_lands_on_floor() {
+        let world = flat_world(&[(0, 0)]);
+        let aabb = Aabb::from_base(Point3::new(5.5, 5.5, 3.0), 0.25, 0.25);
+
+        let (moved, collision) = move_and_collide(aabb, Vector3::new(0.0, 0.0, -5.0), &world);
+
+        assert!(collision.ground);
+        assert!((moved.z - -2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_slides_along_wall() {
+        let mut world = flat_world(&[(0, 0)]);
+        let _ = world.set_block(Point3::new(7, 5, 1), Block::new(BlockType::Stone));
+        let aabb = Aabb::from_base(Point3::new(6.5, 5.5, 1.0), 0.5, 0.5);
+
+        let (moved, collision) = move_and_collide(aabb, Vector3::new(1.0, 1.0, 0.0), &world);
+
+        assert!(collision.x);
+        assert!(!collision.y);
+        assert!((moved.x - 0.25).abs() < 1e-5);
+        assert!((moved.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_no_tunnelling() {
+        let world = flat_world(&[(0, 0)]);
+        let aabb = Aabb::from_base(Point3::new(2.5, 2.5, 10.0), 0.25, 0.25);
+
+        // much further than a block in a single move
+        let (moved, collision) = move_and_collide(aabb, Vector3::new(0.0, 0.0, -50.0), &world);
+
+        assert!(collision.ground);
+        assert!((aabb.min.z + moved.z - 1.0).abs() < 1e-5);
+    }
+}
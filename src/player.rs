@@ -1,86 +1,173 @@
 use std::time::Duration;
 
-use cgmath::{Point3, Rad, Vector3, Zero};
+use cgmath::{Point3, Vector3, Zero};
 
 use crate::{
     camera::Camera,
     chunk::{Block, BlockType, ChunkManager},
-    raycasting::{Ray, RayResult},
+    entity::Entities,
+    inventory::{GameMode, Inventory, Item},
+    item_entity, mob,
+    physics::{self, Aabb},
+    raycasting::{self, Ray, RayResult},
 };
 
 pub const GRAVITY: f32 = 9.8; // blocks / s^2
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+pub const EYE_HEIGHT: f32 = 1.62;
 
+// The player's health and hunger, shown as bars on the HUD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vitals {
+    pub health: u32,
+    pub max_health: u32,
+    pub hunger: u32,
+    pub max_hunger: u32,
+}
+
+impl Default for Vitals {
+    fn default() -> Self {
+        Self {
+            health: 20,
+            max_health: 20,
+            hunger: 20,
+            max_hunger: 20,
+        }
+    }
+}
+
+// Anything that moves around the world
 pub trait Entity {
-    //TODO: will definitely take in other stuff as well
-    fn update(&mut self, dt: Duration);
+    fn bounding_box(&self) -> Aabb;
+
+    fn update(&mut self, dt: Duration, chunk_manager: &ChunkManager);
+}
 
-    //TODO: will definitely take in other stuff as well
-    fn input(&mut self);
+// The player's bounding box, given the position of their eyes
+pub fn player_aabb(eye_pos: Point3<f32>) -> Aabb {
+    Aabb::from_base(
+        Point3::new(eye_pos.x, eye_pos.y, eye_pos.z - EYE_HEIGHT),
+        PLAYER_WIDTH,
+        PLAYER_HEIGHT,
+    )
 }
 
 pub struct Player {
+    // The centre of the base of the player's bounding box
     pos: Point3<f32>,
     vel: Vector3<f32>,
-    pitch: Rad<f32>,
-    yaw: Rad<f32>,
     on_ground: bool,
 }
 
 impl Player {
-    pub fn new(pos: Point3<f32>, pitch: Rad<f32>, yaw: Rad<f32>) -> Self {
+    pub fn new(pos: Point3<f32>) -> Self {
         Self {
             pos,
-            pitch,
-            yaw,
             on_ground: true,
             vel: Vector3::zero(),
         }
     }
 
-    fn detect_on_block(&self) -> bool {
-        true
+    fn detect_on_block(&self, chunk_manager: &ChunkManager) -> bool {
+        // Check just underneath the player's feet
+        let (moved, _) = physics::move_and_collide(
+            self.bounding_box(),
+            Vector3::new(0.0, 0.0, -0.05),
+            chunk_manager,
+        );
+        moved.z > -0.05
     }
 }
 
 impl Entity for Player {
-    fn update(&mut self, dt: Duration) {
-        self.on_ground = self.detect_on_block();
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_base(self.pos, PLAYER_WIDTH, PLAYER_HEIGHT)
+    }
+
+    fn update(&mut self, dt: Duration, chunk_manager: &ChunkManager) {
+        self.on_ground = self.detect_on_block(chunk_manager);
 
-        if !self.on_ground {
-            // update velocity
-            self.vel.z -= dt.as_secs_f32() * GRAVITY;
-        }
         // Note - if we are self.on_ground, then we
         // handle updates to the velocity from inputs
         // in the handle_input method
+        let gravity = if self.on_ground { 0.0 } else { GRAVITY };
+        let (moved, _) = physics::integrate(
+            self.bounding_box(),
+            &mut self.vel,
+            gravity,
+            dt.as_secs_f32(),
+            chunk_manager,
+        );
+        self.pos += moved;
     }
-
-    fn input(&mut self) {}
 }
 
-pub fn player_left_click(camera: &Camera, chunk_manager: &mut ChunkManager) {
+pub fn player_left_click(
+    camera: &Camera,
+    chunk_manager: &mut ChunkManager,
+    entities: &mut Entities,
+    game_mode: GameMode,
+) {
     let ray = Ray::from(camera);
-    let ray_res = chunk_manager.cast_ray(ray);
+    let ray_res = raycasting::cast_ray(ray, chunk_manager, entities);
+
+    if let RayResult::Entity { id, .. } = ray_res {
+        mob::scare(entities, id, camera.position);
+        return;
+    }
+
+    if let RayResult::Block { loc, .. } = ray_res {
+        // break block
+        if let Ok(block) = chunk_manager.remove_block(loc) {
+            // Nothing drops in creative mode
+            if game_mode == GameMode::Creative {
+                return;
+            }
 
-    match ray_res {
-        RayResult::Block { loc, .. } => {
-            // break block
-            let _ = chunk_manager.remove_block(loc);
+            if let Some(drop) = Item::dropped_by(block.block_type()) {
+                item_entity::spawn_block_drop(entities, drop, loc);
+            }
         }
-        _ => {}
     }
 }
 
-pub fn player_right_click(camera: &Camera, chunk_manager: &mut ChunkManager) {
+pub fn player_right_click(
+    camera: &Camera,
+    chunk_manager: &mut ChunkManager,
+    entities: &Entities,
+    inventory: &mut Inventory,
+    game_mode: GameMode,
+) {
     let ray = Ray::from(camera);
-    let ray_res = chunk_manager.cast_ray(ray);
+    let ray_res = raycasting::cast_ray(ray, chunk_manager, entities);
+
+    // Doors get opened and closed rather than built on
+    if let RayResult::Block { loc, .. } = ray_res {
+        if let Some(BlockType::Door { .. }) = chunk_manager.get_block(loc).map(|b| b.block_type()) {
+            chunk_manager.mutate_block(loc, |block| {
+                if let Some(block) = block {
+                    block.toggle_door();
+                }
+            });
+            return;
+        }
+    }
+
+    let Some(block_type) = inventory.selected_stack().and_then(|s| s.item.as_block()) else {
+        return;
+    };
+
+    if let RayResult::Block { loc, face, dist: _ } = ray_res {
+        // Place new block
+        let new_loc = face.adjacent_loc_from(loc);
+        let placed = chunk_manager
+            .set_block(new_loc, Block::new(block_type))
+            .is_ok();
 
-    match ray_res {
-        RayResult::Block { loc, face, dist: _ } => {
-            // Place new block
-            let new_loc = face.adjacent_loc_from(loc);
-            let _ = chunk_manager.set_block(new_loc, Block::new(BlockType::Dirt));
+        // Creative mode has an infinite supply
+        if placed && game_mode == GameMode::Survival {
+            inventory.take_selected(1);
         }
-        _ => {}
     }
 }
@@ -1,6 +1,11 @@
-use cgmath::{num_traits::float::TotalOrder, Point3, Vector3};
+use cgmath::{num_traits::float::TotalOrder, InnerSpace, Point3, Vector3};
+use log::debug;
 
-use crate::camera::Camera;
+use crate::{
+    camera::Camera,
+    chunk::{point_to_world, ChunkManager},
+    entity::{Entities, EntityId},
+};
 
 #[derive(Debug, Clone)]
 pub struct Ray {
@@ -32,8 +37,28 @@ pub enum BlockFace {
 }
 
 impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::XPos,
+        BlockFace::XNeg,
+        BlockFace::YPos,
+        BlockFace::YNeg,
+        BlockFace::ZPos,
+        BlockFace::ZNeg,
+    ];
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            BlockFace::XPos => BlockFace::XNeg,
+            BlockFace::XNeg => BlockFace::XPos,
+            BlockFace::YPos => BlockFace::YNeg,
+            BlockFace::YNeg => BlockFace::YPos,
+            BlockFace::ZPos => BlockFace::ZNeg,
+            BlockFace::ZNeg => BlockFace::ZPos,
+        }
+    }
+
     pub fn adjacent_loc_from(&self, loc: Point3<i32>) -> Point3<i32> {
-        let mut new_loc = loc.clone();
+        let mut new_loc = loc;
         match self {
             BlockFace::XPos => new_loc.x += 1,
             BlockFace::XNeg => new_loc.x -= 1,
@@ -54,11 +79,55 @@ pub enum RayResult {
         face: BlockFace,
         dist: f32,
     },
-    #[allow(dead_code)]
-    Entity,
+    Entity {
+        id: EntityId,
+        dist: f32,
+    },
     None,
 }
 
+// Casts a ray against both terrain and entities, returning
+// whichever is hit first
+pub fn cast_ray(ray: Ray, chunk_manager: &ChunkManager, entities: &Entities) -> RayResult {
+    let entity_hit = entities.cast_ray(&ray);
+
+    match (chunk_manager.cast_ray(ray), entity_hit) {
+        (RayResult::Block { dist, .. }, Some((id, entity_dist))) if entity_dist < dist => {
+            RayResult::Entity {
+                id,
+                dist: entity_dist,
+            }
+        }
+        (RayResult::None, Some((id, dist))) => RayResult::Entity { id, dist },
+        (block, _) => block,
+    }
+}
+
+// Steps along the ray until it reaches a block `is_block` says is
+// there, or runs out of distance
+pub fn march_ray(ray: Ray, is_block: impl Fn(Point3<i32>) -> bool) -> RayResult {
+    let iter_dist = ray.max_dist / ray.n_tests as f32;
+    let iter_ray = ray.dir.normalize() * iter_dist;
+    let mut test_pos_f32 = ray.pos;
+    for _ in 0..ray.n_tests {
+        let test_pos = point_to_world(test_pos_f32);
+        if is_block(test_pos) {
+            let result = RayResult::Block {
+                loc: test_pos,
+                dist: (test_pos_f32 - ray.pos).magnitude(),
+                face: get_colliding_face(ray, test_pos_f32, test_pos).unwrap(),
+            };
+
+            debug!("{:?}", result);
+
+            return result;
+        }
+
+        test_pos_f32 += iter_ray;
+    }
+    RayResult::None
+}
+
 pub fn block_contains(block_pos: Point3<i32>, test_pos: Point3<f32>) -> bool {
     let block_pos = block_pos.cast::<f32>().unwrap();
 
@@ -127,7 +196,7 @@ pub fn get_colliding_face(
         (false, t_max_z)
     };
 
-    match argmax(&vec![x_dist, y_dist, z_dist]).unwrap() {
+    match argmax(&[x_dist, y_dist, z_dist]).unwrap() {
         0 => {
             if x_pos {
                 Some(BlockFace::XNeg)
@@ -160,29 +229,16 @@ pub fn argmax<T: TotalOrder>(v: &[T]) -> Option<usize> {
         .map(|(index, _)| index)
 }
 
-pub fn argmin<T: TotalOrder>(v: &[T]) -> Option<usize> {
-    v.iter()
-        .enumerate()
-        .min_by(|(_, a), (_, b)| a.total_cmp(b))
-        .map(|(index, _)| index)
-}
-
 #[cfg(test)]
 mod tests {
     use cgmath::Rad;
 
     use super::*;
 
-    #[test]
-    fn test_argmin() {
-        assert_eq!(argmin(&vec![0.0, 1.0, 2.0]), Some(0));
-        assert_eq!(argmin(&vec![0.0, -1.0, 2.0]), Some(1));
-    }
-
     #[test]
     fn test_argmax() {
-        assert_eq!(argmax(&vec![0.0, 1.0, 2.0]), Some(2));
-        assert_eq!(argmax(&vec![0.0, -1.0, 2.0]), Some(2));
+        assert_eq!(argmax(&[0.0, 1.0, 2.0]), Some(2));
+        assert_eq!(argmax(&[0.0, -1.0, 2.0]), Some(2));
     }
 
     #[test]
@@ -0,0 +1,331 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    animation::{Animation, AnimationMeta},
+    texture::{Texture, TextureManagerBuilder},
+};
+
+// The built in pack, always at the bottom of the stack
+pub const DEFAULT_PACK_DIR: &str = "res/packs/default";
+// Extra packs live here, and `enabled.txt` lists the ones to use,
+// highest priority first
+pub const USER_PACK_DIR: &str = "resourcepacks";
+
+const MANIFEST_FILE: &str = "pack.txt";
+const ENABLED_FILE: &str = "enabled.txt";
+const TEXTURE_DIR: &str = "textures";
+// Used when no pack in the stack asks for a resolution
+const DEFAULT_RESOLUTION: u32 = 16;
+
+// `pack.txt` at the root of a pack, one `key value` per line, e.g.
+//
+// name Faithful
+// description Sharper textures
+// resolution 32
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackManifest {
+    pub name: String,
+    pub description: String,
+    // Size every texture gets scaled to
+    pub resolution: Option<u32>,
+}
+
+impl PackManifest {
+    fn parse(contents: &str) -> Result<Self, ()> {
+        let mut name = None;
+        let mut description = String::new();
+        let mut resolution = None;
+
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line.trim().split_once(' ').ok_or(())?;
+            let value = value.trim();
+            match key {
+                "name" => name = Some(value.to_string()),
+                "description" => description = value.to_string(),
+                "resolution" => match value.parse::<u32>() {
+                    Ok(pixels) if pixels > 0 => resolution = Some(pixels),
+                    _ => return Err(()),
+                },
+                _ => log::warn!("Unknown resource pack key {key}"),
+            }
+        }
+
+        Ok(Self {
+            name: name.ok_or(())?,
+            description,
+            resolution,
+        })
+    }
+}
+
+pub struct ResourcePack {
+    pub manifest: PackManifest,
+    dir: PathBuf,
+}
+
+impl ResourcePack {
+    pub fn load(dir: &Path) -> Result<Self, ()> {
+        let contents = fs::read_to_string(dir.join(MANIFEST_FILE)).map_err(|_| ())?;
+
+        Ok(Self {
+            manifest: PackManifest::parse(&contents)?,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    // Textures are named after what uses them, e.g. `textures/dirt.png`
+    fn texture(&self, name: &str) -> Option<Texture> {
+        let path = self.dir.join(TEXTURE_DIR).join(format!("{name}.png"));
+        let bytes = fs::read(&path).ok()?;
+
+        match image::load_from_memory(&bytes) {
+            Ok(img) => Some(Texture::from_image(name, &img)),
+            Err(e) => {
+                log::warn!("Couldn't decode {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    // A texture with a sidecar, e.g. `textures/water.txt`, is animated
+    fn animation(&self, name: &str) -> Option<AnimationMeta> {
+        let path = self.dir.join(TEXTURE_DIR).join(format!("{name}.txt"));
+        let contents = fs::read_to_string(&path).ok()?;
+
+        match AnimationMeta::parse(&contents) {
+            Ok(meta) => Some(meta),
+            Err(()) => {
+                log::warn!("Couldn't parse animation {}", path.display());
+                None
+            }
+        }
+    }
+}
+
+// Packs stacked on top of each other. A texture comes from the highest
+// priority pack that has it, so a pack only needs to replace some of them.
+#[derive(Default)]
+pub struct ResourcePackStack {
+    // Highest priority first
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourcePackStack {
+    // The enabled user packs, then the built in pack underneath them
+    pub fn load(user_dir: &Path, default_dir: &Path) -> Self {
+        let mut stack = Self::default();
+
+        let enabled = fs::read_to_string(user_dir.join(ENABLED_FILE)).unwrap_or_default();
+        for name in enabled.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            match ResourcePack::load(&user_dir.join(name)) {
+                Ok(pack) => stack.push(pack),
+                Err(()) => log::warn!("Couldn't load resource pack {name}"),
+            }
+        }
+
+        match ResourcePack::load(default_dir) {
+            Ok(pack) => stack.push(pack),
+            Err(()) => log::error!("Couldn't load the default resource pack"),
+        }
+
+        stack
+    }
+
+    // Adds a pack below the ones already in the stack
+    pub fn push(&mut self, pack: ResourcePack) {
+        log::info!("Using resource pack {}", pack.manifest.name);
+        self.packs.push(pack);
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.packs
+            .iter()
+            .find_map(|pack| pack.manifest.resolution)
+            .unwrap_or(DEFAULT_RESOLUTION)
+    }
+
+    // The frames of a texture, only one unless it's animated. The
+    // animation comes from the same pack as the image.
+    pub fn texture(&self, name: &str) -> (Vec<Texture>, Option<Animation>) {
+        let resolution = self.resolution();
+
+        let Some((texture, meta)) = self
+            .packs
+            .iter()
+            .find_map(|pack| Some((pack.texture(name)?, pack.animation(name))))
+        else {
+            log::warn!("No resource pack has a texture for {name}");
+            return (vec![Texture::missing(name, resolution)], None);
+        };
+
+        let mut frames = match meta {
+            Some(_) => texture.split_frames(),
+            None => vec![texture],
+        };
+        let animation = meta.and_then(|meta| match Animation::new(&meta, frames.len()) {
+            Ok(animation) => Some(animation),
+            Err(()) => {
+                log::warn!("Animation for {name} has frames its strip doesn't");
+                frames.truncate(1);
+                None
+            }
+        });
+
+        let frames = frames
+            .into_iter()
+            .map(|frame| frame.resized(resolution, resolution))
+            .collect();
+        (frames, animation)
+    }
+
+    pub fn build(&self, names: &[&str]) -> TextureManagerBuilder {
+        let resolution = self.resolution();
+        let mut builder = TextureManagerBuilder::new(Some(resolution), Some(resolution));
+        for name in names {
+            match self.texture(name) {
+                (frames, Some(animation)) => builder.add_animated_texture(name, frames, animation),
+                (mut frames, None) => builder.add_texture(name, frames.remove(0)),
+            }
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_pack(dir: &Path, manifest: &str, textures: &[(&str, u32, [u8; 4])]) {
+        fs::create_dir_all(dir.join(TEXTURE_DIR)).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+        for (name, size, colour) in textures {
+            image::RgbaImage::from_pixel(*size, *size, image::Rgba(*colour))
+                .save(dir.join(TEXTURE_DIR).join(format!("{name}.png")))
+                .unwrap();
+        }
+    }
+
+    fn solid(name: &str, size: u32, colour: [u8; 4]) -> Texture {
+        Texture::from_image(
+            name,
+            &image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                size,
+                size,
+                image::Rgba(colour),
+            )),
+        )
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        assert_eq!(
+            PackManifest::parse("name Test\ndescription A test pack\nresolution 32\n"),
+            Ok(PackManifest {
+                name: "Test".to_string(),
+                description: "A test pack".to_string(),
+                resolution: Some(32),
+            })
+        );
+        assert_eq!(
+            PackManifest::parse("name Test\nauthor someone\n").map(|m| m.resolution),
+            Ok(None)
+        );
+        assert_eq!(PackManifest::parse("description No name\n"), Err(()));
+        assert_eq!(PackManifest::parse("name Test\nresolution 0\n"), Err(()));
+    }
+
+    #[test]
+    fn test_stacked_packs() {
+        let root = std::env::temp_dir().join(format!("mcrs_pack_test_{}", std::process::id()));
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let blue = [0, 0, 255, 255];
+
+        write_pack(
+            &root.join("default"),
+            "name Default\nresolution 8\n",
+            &[("dirt", 8, green), ("stone", 16, blue)],
+        );
+        write_pack(&root.join("user/red"), "name Red\n", &[("dirt", 4, red)]);
+        write_pack(
+            &root.join("user/broken"),
+            "resolution 4\n",
+            &[("stone", 4, red)],
+        );
+        fs::write(root.join("user/enabled.txt"), "broken\nred\nnot_there\n").unwrap();
+
+        let stack = ResourcePackStack::load(&root.join("user"), &root.join("default"));
+        let names: Vec<_> = stack
+            .packs
+            .iter()
+            .map(|p| p.manifest.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Red", "Default"]);
+
+        // The red pack doesn't set a resolution, so the default's is used
+        assert_eq!(stack.resolution(), 8);
+        // Overridden, and scaled up to match
+        assert_eq!(stack.texture("dirt"), (vec![solid("dirt", 8, red)], None));
+        // Falls through to the default pack, and scaled down
+        assert_eq!(
+            stack.texture("stone"),
+            (vec![solid("stone", 8, blue)], None)
+        );
+        // Nobody has it
+        assert_eq!(
+            stack.texture("glass"),
+            (vec![Texture::missing("glass", 8)], None)
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_empty_stack() {
+        let stack = ResourcePackStack::default();
+        assert_eq!(stack.resolution(), DEFAULT_RESOLUTION);
+        assert_eq!(
+            stack.texture("dirt"),
+            (vec![Texture::missing("dirt", DEFAULT_RESOLUTION)], None)
+        );
+    }
+
+    #[test]
+    fn test_animated_strip() {
+        let root = std::env::temp_dir().join(format!("mcrs_anim_test_{}", std::process::id()));
+        let colours = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+
+        // Three 2x2 frames stacked into a 2x6 strip
+        fs::create_dir_all(root.join(TEXTURE_DIR)).unwrap();
+        fs::write(root.join(MANIFEST_FILE), "name Animated\nresolution 4\n").unwrap();
+        image::RgbaImage::from_fn(2, 6, |_, y| image::Rgba(colours[y as usize / 2]))
+            .save(root.join(TEXTURE_DIR).join("water.png"))
+            .unwrap();
+        fs::write(root.join(TEXTURE_DIR).join("water.txt"), "frametime 0.5\n").unwrap();
+        image::RgbaImage::from_pixel(2, 4, image::Rgba(colours[0]))
+            .save(root.join(TEXTURE_DIR).join("lava.png"))
+            .unwrap();
+        fs::write(root.join(TEXTURE_DIR).join("lava.txt"), "frames 0 1 2\n").unwrap();
+
+        let mut stack = ResourcePackStack::default();
+        stack.push(ResourcePack::load(&root).unwrap());
+
+        let (frames, animation) = stack.texture("water");
+        let expected: Vec<_> = colours.iter().map(|&c| solid("water", 4, c)).collect();
+        assert_eq!(frames, expected);
+        let animation = animation.unwrap();
+        assert_eq!(animation.sample(0.75).frame, 1);
+        // Loops after three frames
+        assert_eq!(animation.sample(1.6).frame, 0);
+
+        // Only two frames in the strip, so it falls back to a still image
+        let (frames, animation) = stack.texture("lava");
+        assert_eq!(frames, vec![solid("lava", 4, colours[0])]);
+        assert_eq!(animation, None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
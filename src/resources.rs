@@ -4,15 +4,6 @@ use wgpu::util::DeviceExt;
 
 use crate::model;
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR"))
-        .join("res")
-        .join(file_name);
-    let data = std::fs::read(path)?;
-
-    Ok(data)
-}
-
 pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
     let txt = {
         let path = std::path::Path::new(env!("OUT_DIR"))
@@ -29,7 +20,8 @@ pub async fn load_model(file_name: &str, device: &wgpu::Device) -> anyhow::Resul
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
-    let (models, obj_materials) = tobj::load_obj_buf_async(
+    #[allow(deprecated)]
+    let (models, _obj_materials) = tobj::load_obj_buf_async(
         &mut obj_reader,
         &tobj::LoadOptions {
             triangulate: true,
@@ -94,7 +86,6 @@ pub async fn load_model(file_name: &str, device: &wgpu::Device) -> anyhow::Resul
             });
 
             model::Mesh {
-                name: file_name.to_string(),
                 vertex_buffer,
                 index_buffer,
                 n_elements: m.mesh.indices.len() as u32,
@@ -1,13 +1,18 @@
-// Vertex shader
+// Vertex shader, world_lighting.wgsl gets prepended
 struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
     @location(9) tex_idx: u32,
+    @location(10) light: vec2<f32>,
+    @location(11) normal_matrix_0: vec3<f32>,
+    @location(12) normal_matrix_1: vec3<f32>,
+    @location(13) normal_matrix_2: vec3<f32>,
 }
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
 };
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
@@ -15,12 +20,17 @@ var<uniform> camera: CameraUniform;
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
-    @location(1) tex_idx: u32,
+    @location(1) @interpolate(flat) tex_idx: u32,
+    @location(2) light: vec2<f32>,
+    @location(3) world_normal: vec3<f32>,
+    @location(4) view_offset: vec3<f32>,
+    @location(5) world_position: vec3<f32>,
 };
 
 @vertex
@@ -34,10 +44,20 @@ fn vs_main(
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
+    out.view_offset = world_position.xyz - camera.view_position.xyz;
+    out.world_position = world_position.xyz;
     out.tex_idx = instance.tex_idx;
+    out.light = instance.light;
+    out.world_normal = normal_matrix * model.normal;
     return out;
 }
 
@@ -50,6 +70,8 @@ var tex_sampler: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture_array, tex_sampler, in.tex_coords, in.tex_idx);
+    let colour = textureSample(texture_array, tex_sampler, in.tex_coords, in.tex_idx);
+    let lit = colour.rgb * shade(in.world_position, in.view_offset, in.world_normal, in.light);
+    return vec4<f32>(apply_fog(lit, in.view_offset), colour.a);
 }
 
@@ -0,0 +1,476 @@
+use bytemuck::Zeroable;
+use cgmath::{prelude::*, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::{Camera, Projection, OPENGL_TO_WGPU_MATRIX},
+    mesh::BlockVertex,
+    model::Vertex,
+};
+
+pub const CASCADES: usize = 3;
+// Blends between evenly spaced cascade splits (0) and logarithmic ones (1)
+const SPLIT_LAMBDA: f32 = 0.6;
+// How far towards the sun to look for things casting shadows into a
+// cascade, beyond the cascade itself
+pub const CASTER_MARGIN: f32 = 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    // How far from the camera shadows are drawn, in blocks
+    pub distance: f32,
+    // Width and height of each cascade's shadow map
+    pub resolution: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            distance: 48.0,
+            resolution: 2048,
+        }
+    }
+}
+
+// Where each cascade ends, as a distance along the view direction. Close
+// cascades are kept small so shadows near the camera stay sharp.
+pub fn cascade_splits(near: f32, distance: f32) -> [f32; CASCADES] {
+    std::array::from_fn(|i| {
+        let t = (i + 1) as f32 / CASCADES as f32;
+        let log = near * (distance / near).powf(t);
+        let uniform = near + (distance - near) * t;
+        SPLIT_LAMBDA * log + (1.0 - SPLIT_LAMBDA) * uniform
+    })
+}
+
+// The corners of the part of the view frustum between two view depths
+pub fn frustum_slice_corners(
+    camera: &Camera,
+    projection: &Projection,
+    near: f32,
+    far: f32,
+) -> [Point3<f32>; 8] {
+    let front = camera.front();
+    // Taken from the yaw so looking straight up or down still works
+    let (sin_yaw, cos_yaw) = camera.yaw.sin_cos();
+    let right = Vector3::new(sin_yaw, -cos_yaw, 0.0);
+    let up = right.cross(front).normalize();
+    let tan_half_fov = (projection.fovy / 2.0).tan();
+
+    let mut corners = [Point3::origin(); 8];
+    for (i, depth) in [near, far].into_iter().enumerate() {
+        let centre = camera.position + front * depth;
+        let half_height = depth * tan_half_fov;
+        let half_width = half_height * projection.aspect;
+
+        for (j, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            corners[i * 4 + j] = centre + right * (x * half_width) + up * (y * half_height);
+        }
+    }
+
+    corners
+}
+
+// An orthographic view-projection from the sun that covers a slice of the
+// view frustum. It's fitted to a sphere around the slice so its size doesn't
+// change as the camera turns, and snapped to whole shadow map texels so
+// shadow edges don't crawl as the camera moves.
+pub fn cascade_matrix(
+    corners: &[Point3<f32>; 8],
+    sun_direction: Vector3<f32>,
+    resolution: u32,
+) -> Matrix4<f32> {
+    let centre = Point3::centroid(corners);
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(centre))
+        .fold(0.0, f32::max);
+    // Round up so small changes in the slice don't resize the cascade
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let look = -sun_direction.normalize();
+    let up = if look.z.abs() > 0.99 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_z()
+    };
+    let rotation = Matrix4::look_to_rh(Point3::origin(), look, up);
+
+    let texel = 2.0 * radius / resolution as f32;
+    let light_centre = rotation.transform_point(centre);
+    let snapped = Vector3::new(
+        (light_centre.x / texel).floor() * texel,
+        (light_centre.y / texel).floor() * texel,
+        light_centre.z,
+    );
+    let view = Matrix4::from_translation(-snapped) * rotation;
+
+    // Looking down -z, so the sun side of the slice is at +z
+    let projection = cgmath::ortho(
+        -radius,
+        radius,
+        -radius,
+        radius,
+        -(radius + CASTER_MARGIN),
+        radius,
+    );
+
+    OPENGL_TO_WGPU_MATRIX * projection * view
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[[f32; 4]; 4]; CASCADES],
+    // Far end of each cascade, the last one unused
+    splits: [f32; 4],
+    view_forward: [f32; 3],
+    enabled: u32,
+    // Size of one shadow map texel in uv space
+    texel: f32,
+    _pad: [f32; 3],
+}
+
+// Depth maps of the world from the sun, one per cascade
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+    pipeline: wgpu::RenderPipeline,
+    // One per cascade, each holding that cascade's light matrix
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+    layer_views: Vec<wgpu::TextureView>,
+    pub array_view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub uniform_buffer: wgpu::Buffer,
+}
+
+impl ShadowMaps {
+    pub fn new(device: &wgpu::Device, settings: ShadowSettings) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+
+        let cascade_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("cascade_bind_group_layout"),
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&cascade_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Depth only, there's nothing to colour
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[BlockVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Pushes the depth back a little to stop surfaces
+                // shadowing themselves
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let cascade_buffers = (0..CASCADES)
+            .map(|_| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Cascade Buffer"),
+                    contents: bytemuck::cast_slice(&[[[0.0_f32; 4]; 4]]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let cascade_bind_groups = cascade_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &cascade_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some("cascade_bind_group"),
+                })
+            })
+            .collect();
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Buffer"),
+            contents: bytemuck::cast_slice(&[ShadowUniform::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let (layer_views, array_view) = Self::create_maps(device, settings.resolution);
+
+        Self {
+            settings,
+            pipeline,
+            cascade_buffers,
+            cascade_bind_groups,
+            layer_views,
+            array_view,
+            sampler,
+            uniform_buffer,
+        }
+    }
+
+    fn create_maps(
+        device: &wgpu::Device,
+        resolution: u32,
+    ) -> (Vec<wgpu::TextureView>, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: CASCADES as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let layer_views = (0..CASCADES as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map Array"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        (layer_views, array_view)
+    }
+
+    // Returns true if the maps were recreated, and anything bound to them
+    // needs rebinding
+    pub fn set_settings(&mut self, device: &wgpu::Device, settings: ShadowSettings) -> bool {
+        let resized = settings.resolution != self.settings.resolution;
+        self.settings = settings;
+        if resized {
+            (self.layer_views, self.array_view) = Self::create_maps(device, settings.resolution);
+        }
+        resized
+    }
+
+    // Fits the cascades to the camera's view
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        projection: &Projection,
+        sun_direction: Vector3<f32>,
+    ) {
+        let splits = cascade_splits(projection.znear, self.settings.distance);
+
+        let mut uniform = ShadowUniform::zeroed();
+        let mut near = projection.znear;
+        for (i, far) in splits.into_iter().enumerate() {
+            let corners = frustum_slice_corners(camera, projection, near, far);
+            let matrix = cascade_matrix(&corners, sun_direction, self.settings.resolution);
+
+            uniform.light_view_proj[i] = matrix.into();
+            uniform.splits[i] = far;
+            queue.write_buffer(
+                &self.cascade_buffers[i],
+                0,
+                bytemuck::cast_slice(&[uniform.light_view_proj[i]]),
+            );
+            near = far;
+        }
+
+        uniform.view_forward = camera.front().into();
+        uniform.enabled = self.settings.enabled as u32;
+        uniform.texel = 1.0 / self.settings.resolution as f32;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Draws the shadow casters into each cascade. `draw` issues the draw
+    // calls for the world, once per cascade.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        mut draw: impl FnMut(&mut wgpu::RenderPass),
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        for (view, bind_group) in self.layer_views.iter().zip(&self.cascade_bind_groups) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            draw(&mut render_pass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Vector4};
+
+    use super::*;
+
+    fn camera(pitch: f32) -> (Camera, Projection) {
+        (
+            Camera::new((3.0, -7.0, 20.0), Deg(30.0), Deg(pitch)),
+            Projection::new(1600, 900, Deg(45.0), 0.1, 100.0),
+        )
+    }
+
+    #[test]
+    fn test_cascade_splits() {
+        let splits = cascade_splits(0.1, 48.0);
+        assert!(splits[0] > 0.1);
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((splits[CASCADES - 1] - 48.0).abs() < 1e-3);
+        // Nearer cascades cover less than an even split would
+        assert!(splits[0] < 48.0 / CASCADES as f32);
+    }
+
+    #[test]
+    fn test_slice_corners_on_view_frustum() {
+        let (camera, projection) = camera(-20.0);
+        let corners = frustum_slice_corners(&camera, &projection, 1.0, 10.0);
+        let view_proj = crate::camera::view_proj(&camera, &projection);
+
+        // Every corner lands on a corner of the screen
+        for corner in corners {
+            let clip = view_proj * corner.to_homogeneous();
+            let ndc = clip.truncate() / clip.w;
+            assert!((ndc.x.abs() - 1.0).abs() < 1e-3, "{ndc:?}");
+            assert!((ndc.y.abs() - 1.0).abs() < 1e-3, "{ndc:?}");
+        }
+    }
+
+    #[test]
+    fn test_cascades_cover_their_slices() {
+        let sun = Vector3::new(0.4, 0.3, 0.8).normalize();
+
+        for pitch in [-80.0, -20.0, 0.0, 45.0] {
+            let (camera, projection) = camera(pitch);
+            let splits = cascade_splits(projection.znear, 48.0);
+
+            let mut near = projection.znear;
+            for far in splits {
+                let corners = frustum_slice_corners(&camera, &projection, near, far);
+                let matrix = cascade_matrix(&corners, sun, 2048);
+
+                for corner in corners {
+                    let clip = matrix * corner.to_homogeneous();
+                    assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{clip:?}");
+                    assert!((0.0..=1.0).contains(&clip.z), "{clip:?}");
+                }
+
+                // Something between the slice and the sun still lands in
+                // the map, so it can cast a shadow into it
+                let caster = corners[0] + sun * 32.0;
+                let clip = matrix * caster.to_homogeneous();
+                assert!((0.0..=1.0).contains(&clip.z), "{clip:?}");
+
+                near = far;
+            }
+        }
+    }
+
+    #[test]
+    fn test_cascades_snap_to_texels() {
+        let sun = Vector3::new(0.4, 0.3, 0.8).normalize();
+        let (mut camera, projection) = camera(-20.0);
+        let resolution = 1024;
+
+        let corners = frustum_slice_corners(&camera, &projection, 0.1, 8.0);
+        let before = cascade_matrix(&corners, sun, resolution);
+        camera.position += Vector3::new(0.013, 0.021, 0.0);
+        let corners = frustum_slice_corners(&camera, &projection, 0.1, 8.0);
+        let after = cascade_matrix(&corners, sun, resolution);
+
+        // A fixed point in the world moves by whole texels, if at all
+        let point = Vector4::new(1.0, 2.0, 18.0, 1.0);
+        let shift = (after * point - before * point) * resolution as f32 / 2.0;
+        assert!((shift.x - shift.x.round()).abs() < 1e-2, "{shift:?}");
+        assert!((shift.y - shift.y.round()).abs() < 1e-2, "{shift:?}");
+    }
+}
@@ -0,0 +1,12 @@
+// Renders the depth of the world from the sun into one shadow cascade
+
+struct Cascade {
+    light_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> cascade: Cascade;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return cascade.light_view_proj * vec4<f32>(position, 1.0);
+}
@@ -0,0 +1,181 @@
+use cgmath::{Matrix4, SquareMatrix};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    clock::WorldClock,
+    fog::{Fog, FogMode},
+    texture,
+};
+
+// vec3s are 16 byte aligned in uniforms, so they get padded out
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    zenith: [f32; 3],
+    star_visibility: f32,
+    horizon: [f32; 3],
+    // How much the sky is covered by fog, only when inside a fluid
+    fog_amount: f32,
+    sun_direction: [f32; 3],
+    _pad0: f32,
+    fog_colour: [f32; 3],
+    _pad1: f32,
+}
+
+impl SkyUniform {
+    fn new(clock: &WorldClock, fog: &Fog, view_proj: Matrix4<f32>) -> Self {
+        let colours = clock.sky_colours();
+        let fog_amount = match fog.mode {
+            FogMode::Linear { .. } => 0.0,
+            FogMode::Exponential { .. } => 1.0,
+        };
+        Self {
+            inv_view_proj: view_proj.invert().unwrap_or(Matrix4::identity()).into(),
+            zenith: colours.zenith,
+            star_visibility: clock.star_visibility(),
+            horizon: colours.horizon,
+            fog_amount,
+            sun_direction: clock.sun_direction().into(),
+            _pad0: 0.0,
+            fog_colour: fog.colour,
+            _pad1: 0.0,
+        }
+    }
+}
+
+// Draws the sky behind the world, with the colours coming from the clock
+pub struct Sky {
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    clear_colour: [f32; 3],
+}
+
+impl Sky {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        clock: &WorldClock,
+        fog: &Fog,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("sky.wgsl").into()),
+        });
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::cast_slice(&[SkyUniform::new(clock, fog, Matrix4::identity())]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("sky_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("sky_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Shares the world's render pass, but never touches the depth
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            buffer,
+            bind_group,
+            clear_colour: fog.colour,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        clock: &WorldClock,
+        fog: &Fog,
+        view_proj: Matrix4<f32>,
+    ) {
+        let uniform = SkyUniform::new(clock, fog, view_proj);
+        self.clear_colour = fog.colour;
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn clear_colour(&self) -> wgpu::Color {
+        wgpu::Color {
+            r: self.clear_colour[0] as f64,
+            g: self.clear_colour[1] as f64,
+            b: self.clear_colour[2] as f64,
+            a: 1.0,
+        }
+    }
+
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,79 @@
+// Sky gradient, sun, moon and stars, drawn behind everything else
+
+struct SkyUniform {
+    inv_view_proj: mat4x4<f32>,
+    zenith: vec3<f32>,
+    star_visibility: f32,
+    horizon: vec3<f32>,
+    fog_amount: f32,
+    sun_direction: vec3<f32>,
+    fog_colour: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> sky: SkyUniform;
+
+const SUN_SIZE: f32 = 0.08;
+const MOON_SIZE: f32 = 0.06;
+const SUN_COLOUR: vec3<f32> = vec3<f32>(1.0, 0.95, 0.7);
+const MOON_COLOUR: vec3<f32> = vec3<f32>(0.85, 0.87, 0.95);
+const STAR_DENSITY: f32 = 120.0;
+const STAR_CHANCE: f32 = 0.004;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32(index & 1u) * 4.0 - 1.0, f32(index >> 1u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+fn unproject(ndc: vec3<f32>) -> vec3<f32> {
+    let world = sky.inv_view_proj * vec4<f32>(ndc, 1.0);
+    return world.xyz / world.w;
+}
+
+fn hash(cell: vec3<f32>) -> f32 {
+    return fract(sin(dot(cell, vec3<f32>(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// Whether the ray passes through a square facing the camera from `dir`
+fn in_quad(ray: vec3<f32>, dir: vec3<f32>, right: vec3<f32>, up: vec3<f32>, size: f32) -> bool {
+    let d = dot(ray, dir);
+    if d <= 0.0 {
+        return false;
+    }
+    return max(abs(dot(ray, right)), abs(dot(ray, up))) / d < size;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ray = normalize(unproject(vec3<f32>(in.ndc, 1.0)) - unproject(vec3<f32>(in.ndc, 0.0)));
+
+    let height = clamp(ray.z, 0.0, 1.0);
+    var colour = mix(sky.horizon, sky.zenith, sqrt(height));
+
+    // A frame that turns with the sun, so the stars wheel across the sky
+    // along with it
+    let sun = sky.sun_direction;
+    let right = normalize(cross(vec3<f32>(0.0, 1.0, 0.0), sun));
+    let up = cross(sun, right);
+
+    let star_pos = vec3<f32>(dot(ray, right), dot(ray, up), dot(ray, sun));
+    let star = step(1.0 - STAR_CHANCE, hash(floor(star_pos * STAR_DENSITY)));
+    colour += vec3<f32>(star * sky.star_visibility * height);
+
+    if in_quad(ray, sun, right, up, SUN_SIZE) {
+        colour = SUN_COLOUR;
+    } else if in_quad(ray, -sun, right, up, MOON_SIZE) {
+        colour = mix(colour, MOON_COLOUR, sky.star_visibility * 0.8 + 0.2);
+    }
+
+    return vec4<f32>(mix(colour, sky.fog_colour, sky.fog_amount), 1.0);
+}
@@ -0,0 +1,139 @@
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::shadow::ShadowMaps;
+
+// Directional light from the sun, applied on top of the voxel sky light
+pub struct Sun {
+    // Points from the world towards the sun
+    pub direction: Vector3<f32>,
+    pub colour: [f32; 3],
+    pub ambient: [f32; 3],
+    // Multiplier on the voxel sky light, dimmed at night
+    pub sky_light: f32,
+}
+
+impl Sun {
+    fn to_uniform(&self) -> SunUniform {
+        let direction = self.direction.normalize();
+        SunUniform {
+            direction: direction.into(),
+            sky_light: self.sky_light,
+            colour: self.colour,
+            _pad0: 0.0,
+            ambient: self.ambient,
+            _pad1: 0.0,
+        }
+    }
+}
+
+// vec3s are 16 byte aligned in uniforms, so they get padded out
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SunUniform {
+    direction: [f32; 3],
+    sky_light: f32,
+    colour: [f32; 3],
+    _pad0: f32,
+    ambient: [f32; 3],
+    _pad1: f32,
+}
+
+pub struct SunBinding {
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl SunBinding {
+    pub fn new(device: &wgpu::Device, sun: &Sun, shadows: &ShadowMaps) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sun Buffer"),
+            contents: bytemuck::cast_slice(&[sun.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        // The sun's shadow maps live alongside it
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                uniform_entry(0),
+                uniform_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("sun_bind_group_layout"),
+        });
+
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &buffer, shadows);
+
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        shadows: &ShadowMaps,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: shadows.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&shadows.array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&shadows.sampler),
+                },
+            ],
+            label: Some("sun_bind_group"),
+        })
+    }
+
+    // The shadow maps are replaced when their resolution changes
+    pub fn rebind_shadows(&mut self, device: &wgpu::Device, shadows: &ShadowMaps) {
+        self.bind_group =
+            Self::create_bind_group(device, &self.bind_group_layout, &self.buffer, shadows);
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, sun: &Sun) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[sun.to_uniform()]));
+    }
+}
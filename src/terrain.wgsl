@@ -0,0 +1,71 @@
+// Vertex shader, world_lighting.wgsl gets prepended
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tex_idx: u32,
+    @location(4) light: vec2<f32>,
+    @location(5) ao: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) tex_idx: u32,
+    @location(2) light: vec2<f32>,
+    @location(3) ao: f32,
+    @location(4) normal: vec3<f32>,
+    @location(5) view_offset: vec3<f32>,
+    @location(6) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.view_offset = model.position - camera.view_position.xyz;
+    out.world_position = model.position;
+    out.tex_idx = model.tex_idx;
+    out.light = model.light;
+    out.ao = model.ao;
+    // Chunk meshes are already in world space
+    out.normal = model.normal;
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var texture_array: texture_2d_array<f32>;
+@group(0) @binding(1)
+var tex_sampler: sampler;
+
+fn lit_colour(in: VertexOutput, colour: vec4<f32>) -> vec4<f32> {
+    let brightness = shade(in.world_position, in.view_offset, in.normal, in.light) * in.ao;
+    return vec4<f32>(apply_fog(colour.rgb * brightness, in.view_offset), colour.a);
+}
+
+// Opaque and translucent blocks, the alpha only matters when blending
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let colour = textureSample(texture_array, tex_sampler, in.tex_coords, in.tex_idx);
+    return lit_colour(in, colour);
+}
+
+// Cutout blocks are either solid or have holes right through them
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let colour = textureSample(texture_array, tex_sampler, in.tex_coords, in.tex_idx);
+    if colour.a < 0.5 {
+        discard;
+    }
+    return lit_colour(in, vec4<f32>(colour.rgb, 1.0));
+}
@@ -2,10 +2,11 @@ use std::collections::HashMap;
 
 use image::GenericImageView;
 
+use crate::animation::Animation;
+
 pub struct DepthTexture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
 }
 
 impl DepthTexture {
@@ -31,28 +32,11 @@ impl DepthTexture {
         };
         let texture = device.create_texture(&desc);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            // 4.
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual), // 5.
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 100.0,
-            ..Default::default()
-        });
-
-        Self {
-            texture,
-            view,
-            sampler,
-        }
+        Self { texture, view }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Texture {
     raw: Vec<u8>,
     width: u32,
@@ -72,6 +56,64 @@ impl Texture {
             label: label.to_string(),
         }
     }
+
+    // Magenta and black squares, so a missing texture is obvious in game
+    pub fn missing(label: &str, size: u32) -> Self {
+        let half = (size / 2).max(1);
+        let img = image::RgbaImage::from_fn(size, size, |x, y| {
+            if (x / half + y / half).is_multiple_of(2) {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+
+        Self::from_image(label, &image::DynamicImage::ImageRgba8(img))
+    }
+
+    // Cuts a vertical strip into square frames, top to bottom. Anything
+    // left over at the bottom that isn't a whole frame is dropped.
+    pub fn split_frames(self) -> Vec<Self> {
+        let count = self.height / self.width.max(1);
+        if count <= 1 {
+            return vec![self];
+        }
+
+        let frame_len = (self.width * self.width * 4) as usize;
+        self.raw
+            .chunks_exact(frame_len)
+            .take(count as usize)
+            .map(|raw| Self {
+                raw: raw.to_vec(),
+                width: self.width,
+                height: self.width,
+                label: self.label.clone(),
+            })
+            .collect()
+    }
+
+    // Pixel art is scaled up with nearest so it stays crisp, bigger
+    // textures are filtered on the way down
+    pub fn resized(self, width: u32, height: u32) -> Self {
+        if self.width == width && self.height == height {
+            return self;
+        }
+
+        let filter = if width >= self.width && height >= self.height {
+            image::imageops::FilterType::Nearest
+        } else {
+            image::imageops::FilterType::Triangle
+        };
+        let img = image::RgbaImage::from_raw(self.width, self.height, self.raw).unwrap();
+        let img = image::imageops::resize(&img, width, height, filter);
+
+        Self {
+            raw: img.into_raw(),
+            width,
+            height,
+            label: self.label,
+        }
+    }
 }
 
 // We need to load all the textures onto the GPU at once. So,
@@ -81,6 +123,7 @@ impl Texture {
 // actual use.
 pub struct TextureManagerBuilder {
     map: HashMap<String, Texture>,
+    animations: HashMap<String, (Vec<Texture>, Animation)>,
     width: Option<u32>,
     height: Option<u32>,
 }
@@ -89,24 +132,82 @@ impl TextureManagerBuilder {
     pub fn new(width: Option<u32>, height: Option<u32>) -> Self {
         Self {
             map: Default::default(),
+            animations: Default::default(),
             width,
             height,
         }
     }
 
+    // The first frame goes in the array like any other texture, and gets
+    // swapped out as the animation plays
+    pub fn add_animated_texture(&mut self, name: &str, frames: Vec<Texture>, animation: Animation) {
+        self.add_texture(name, frames[0].clone());
+        let (width, height) = (self.width.unwrap(), self.height.unwrap());
+        let frames = frames
+            .into_iter()
+            .map(|frame| frame.resized(width, height))
+            .collect();
+        let _ = self
+            .animations
+            .insert(name.to_string(), (frames, animation));
+    }
+
+    // Every layer of the texture array has to be the same size, so
+    // textures that don't match get rescaled
     pub fn add_texture(&mut self, name: &str, texture: Texture) {
-        if let Some(width) = self.width {
-            if let Some(height) = self.height {
-                if texture.width != width || texture.height != height {
-                    //TODO: better error handling
-                    panic!("Mismatched texture shapes");
-                }
-            }
-        } else {
-            self.width = Some(texture.width);
-            self.height = Some(texture.height);
+        let width = *self.width.get_or_insert(texture.width);
+        let height = *self.height.get_or_insert(texture.height);
+        if texture.width != width || texture.height != height {
+            log::warn!(
+                "Rescaling texture {name} from {}x{} to {width}x{height}",
+                texture.width,
+                texture.height
+            );
         }
-        let _ = self.map.insert(name.to_string(), texture);
+        let _ = self
+            .map
+            .insert(name.to_string(), texture.resized(width, height));
+    }
+}
+
+// How finely interpolated frames are blended. The layer only gets
+// rewritten when the step changes, rather than every frame.
+const BLEND_STEPS: f32 = 16.0;
+
+struct AnimatedLayer {
+    index: usize,
+    frames: Vec<Texture>,
+    animation: Animation,
+    // Frame, next frame and blend step currently in the array
+    shown: Option<(usize, usize, u32)>,
+}
+
+impl AnimatedLayer {
+    // The pixels to show at this point in the animation, or None if
+    // they're already up
+    fn advance(&mut self, time: f32) -> Option<Vec<u8>> {
+        let sample = self.animation.sample(time);
+        let step = (sample.blend * BLEND_STEPS).round() as u32;
+        let shown = (sample.frame, sample.next, step);
+        if self.shown == Some(shown) {
+            return None;
+        }
+        self.shown = Some(shown);
+
+        let frame = &self.frames[sample.frame].raw;
+        if step == 0 {
+            return Some(frame.clone());
+        }
+
+        let blend = step as f32 / BLEND_STEPS;
+        let next = &self.frames[sample.next].raw;
+        Some(
+            frame
+                .iter()
+                .zip(next)
+                .map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * blend).round() as u8)
+                .collect(),
+        )
     }
 }
 
@@ -122,32 +223,191 @@ pub struct TextureManager {
     map: HashMap<String, TMVal>,
     width: u32,
     height: u32,
+    animations: Vec<AnimatedLayer>,
+    // Kept so animated layers can be rewritten
+    texture_array: Option<wgpu::Texture>,
+    // Kept so the sampler can be swapped out later
+    array_view: Option<wgpu::TextureView>,
+    bind_group_layout: Option<wgpu::BindGroupLayout>,
 }
 
 impl From<TextureManagerBuilder> for TextureManager {
     fn from(value: TextureManagerBuilder) -> Self {
         let mut map = HashMap::default();
-        let mut index = 0;
-
-        for (k, v) in value.map {
+        for (index, (k, v)) in value.map.into_iter().enumerate() {
             map.insert(k, TMVal { texture: v, index });
-            index += 1;
         }
 
+        let animations = value
+            .animations
+            .into_iter()
+            .map(|(k, (frames, animation))| AnimatedLayer {
+                index: map[&k].index,
+                frames,
+                animation,
+                shown: None,
+            })
+            .collect();
+
         Self {
             map,
             width: value.width.unwrap(),
             height: value.height.unwrap(),
+            animations,
+            texture_array: None,
+            array_view: None,
+            bind_group_layout: None,
+        }
+    }
+}
+
+pub const MAX_ANISOTROPY: u16 = 16;
+
+// One level of a mip chain, as tightly packed RGBA8
+#[derive(Debug, Clone, PartialEq)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub raw: Vec<u8>,
+}
+
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+// Halves an sRGB image with a 2x2 box filter. Colours are averaged in
+// linear space so the result isn't too dark, and weighted by alpha so
+// see-through pixels don't bleed their colour into the edges. Odd sizes
+// reuse the last row or column.
+fn downsample(level: &MipLevel) -> MipLevel {
+    let width = (level.width / 2).max(1);
+    let height = (level.height / 2).max(1);
+    let mut raw = Vec::with_capacity((width * height * 4) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut colour = [0.0_f32; 3];
+            let mut alpha = 0.0;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let sx = (x * 2 + dx).min(level.width - 1);
+                let sy = (y * 2 + dy).min(level.height - 1);
+                let i = ((sy * level.width + sx) * 4) as usize;
+                let a = level.raw[i + 3] as f32 / 255.0;
+                for (sum, &c) in colour.iter_mut().zip(&level.raw[i..i + 3]) {
+                    *sum += srgb_to_linear(c) * a;
+                }
+                alpha += a;
+            }
+
+            for c in colour {
+                raw.push(if alpha > 0.0 {
+                    linear_to_srgb(c / alpha)
+                } else {
+                    0
+                });
+            }
+            raw.push((alpha / 4.0 * 255.0).round() as u8);
         }
     }
+
+    MipLevel { width, height, raw }
+}
+
+// The full chain down to 1x1, starting with the image itself
+pub fn generate_mips(raw: &[u8], width: u32, height: u32) -> Vec<MipLevel> {
+    let mut levels = vec![MipLevel {
+        width,
+        height,
+        raw: raw.to_vec(),
+    }];
+    while levels.len() < mip_level_count(width, height) as usize {
+        levels.push(downsample(levels.last().unwrap()));
+    }
+    levels
+}
+
+// Nearest magnification keeps pixel art sharp up close, and mipmaps keep
+// distant blocks from shimmering. wgpu only allows anisotropic filtering
+// when every filter is linear, so magnification goes linear with it.
+pub fn sampler_descriptor(anisotropy: u16) -> wgpu::SamplerDescriptor<'static> {
+    let anisotropy = anisotropy.clamp(1, MAX_ANISOTROPY);
+    wgpu::SamplerDescriptor {
+        label: Some("texture_array_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: if anisotropy > 1 {
+            wgpu::FilterMode::Linear
+        } else {
+            wgpu::FilterMode::Nearest
+        },
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        anisotropy_clamp: anisotropy,
+        ..Default::default()
+    }
+}
+
+// Uploads one layer of the texture array along with its mip chain
+fn write_layer(
+    queue: &wgpu::Queue,
+    texture_array: &wgpu::Texture,
+    index: usize,
+    raw: &[u8],
+    width: u32,
+    height: u32,
+) {
+    for (mip_level, mip) in generate_mips(raw, width, height).iter().enumerate() {
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: texture_array,
+                mip_level: mip_level as u32,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: index as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &mip.raw,
+            wgpu::TexelCopyBufferLayout {
+                bytes_per_row: Some(4 * mip.width),
+                rows_per_image: Some(mip.height),
+                offset: 0,
+            },
+            wgpu::Extent3d {
+                width: mip.width,
+                height: mip.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
 }
 
 impl TextureManager {
-    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
     pub fn create_and_submit_texture_array(
-        &self,
+        &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        anisotropy: u16,
     ) -> (wgpu::BindGroup, wgpu::BindGroupLayout) {
         let texture_array = device.create_texture(&wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
@@ -155,7 +415,7 @@ impl TextureManager {
                 height: self.height,
                 depth_or_array_layers: self.map.keys().len() as u32,
             },
-            mip_level_count: 1,
+            mip_level_count: mip_level_count(self.width, self.height),
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -169,16 +429,6 @@ impl TextureManager {
             ..Default::default()
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
         let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
@@ -201,52 +451,63 @@ impl TextureManager {
             label: Some("texture_bind_group_layout"),
         });
 
-        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bgl,
+        for entry in self.map.values() {
+            write_layer(
+                queue,
+                &texture_array,
+                entry.index,
+                &entry.texture.raw,
+                self.width,
+                self.height,
+            );
+        }
+
+        self.texture_array = Some(texture_array);
+        self.array_view = Some(texture_array_view);
+        self.bind_group_layout = Some(bgl.clone());
+
+        (self.create_bind_group(device, anisotropy), bgl)
+    }
+
+    // A bind group for the texture array with a fresh sampler, so the
+    // anisotropy can change without re-uploading the textures
+    pub fn create_bind_group(&self, device: &wgpu::Device, anisotropy: u16) -> wgpu::BindGroup {
+        let sampler = device.create_sampler(&sampler_descriptor(anisotropy));
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: self.bind_group_layout.as_ref().unwrap(),
             label: Some("texture_bind_group"),
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture_array_view),
+                    resource: wgpu::BindingResource::TextureView(self.array_view.as_ref().unwrap()),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&sampler),
                 },
             ],
-        });
-
-        for entry in self.map.values() {
-            self.add_individual_texture(entry, queue, &texture_array);
-        }
-
-        (bg, bgl)
+        })
     }
 
-    fn add_individual_texture(&self, entry: &TMVal, queue: &wgpu::Queue, tex_arr: &wgpu::Texture) {
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: tex_arr,
-                mip_level: 0,
-                origin: wgpu::Origin3d {
-                    x: 0,
-                    y: 0,
-                    z: entry.index as u32,
-                },
-                aspect: wgpu::TextureAspect::All,
-            },
-            &entry.texture.raw,
-            wgpu::TexelCopyBufferLayout {
-                bytes_per_row: Some(4 * self.width),
-                rows_per_image: Some(self.height),
-                offset: 0,
-            },
-            wgpu::Extent3d {
-                width: self.width,
-                height: self.height,
-                depth_or_array_layers: 1,
-            },
-        );
+    // Puts the current frame of every animated texture into the array
+    pub fn animate(&mut self, queue: &wgpu::Queue, time: f32) {
+        let Some(texture_array) = &self.texture_array else {
+            return;
+        };
+
+        for layer in &mut self.animations {
+            if let Some(raw) = layer.advance(time) {
+                write_layer(
+                    queue,
+                    texture_array,
+                    layer.index,
+                    &raw,
+                    self.width,
+                    self.height,
+                );
+            }
+        }
     }
 
     pub fn lookup_idx(&self, key: &str) -> Option<usize> {
@@ -257,3 +518,153 @@ impl TextureManager {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> Vec<u8> {
+        rgba.repeat((width * height) as usize)
+    }
+
+    #[test]
+    fn test_builder_rescales_mismatched_textures() {
+        let mut builder = TextureManagerBuilder::new(None, None);
+        builder.add_texture("missing", Texture::missing("missing", 4));
+        builder.add_texture("big", Texture::missing("big", 16));
+        builder.add_texture("small", Texture::missing("small", 2));
+
+        for name in ["missing", "big", "small"] {
+            let texture = &builder.map[name];
+            assert_eq!((texture.width, texture.height), (4, 4));
+            assert_eq!(texture.raw.len(), 4 * 4 * 4);
+        }
+        // Scaling a checkerboard up with nearest keeps it exact
+        assert_eq!(builder.map["small"].raw, builder.map["missing"].raw);
+    }
+
+    #[test]
+    fn test_split_frames() {
+        let strip = Texture {
+            raw: (0..2 * 5 * 4).map(|i| (i / 8) as u8).collect(),
+            width: 2,
+            height: 5,
+            label: "strip".to_string(),
+        };
+
+        // Two whole 2x2 frames, the last row is dropped
+        let frames = strip.split_frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[0].raw,
+            [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1]
+        );
+        assert_eq!(
+            frames[1].raw,
+            [2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3, 3]
+        );
+        assert_eq!((frames[1].width, frames[1].height), (2, 2));
+
+        // A single square frame isn't touched
+        assert_eq!(Texture::missing("single", 4).split_frames().len(), 1);
+    }
+
+    #[test]
+    fn test_animated_layer_only_changes_when_needed() {
+        let meta =
+            crate::animation::AnimationMeta::parse("frametime 1\ninterpolate true\n").unwrap();
+        let frame = |v| Texture {
+            raw: vec![v; 4],
+            width: 1,
+            height: 1,
+            label: "frame".to_string(),
+        };
+        let mut layer = AnimatedLayer {
+            index: 0,
+            frames: vec![frame(0), frame(160)],
+            animation: Animation::new(&meta, 2).unwrap(),
+            shown: None,
+        };
+
+        assert_eq!(layer.advance(0.0), Some(vec![0; 4]));
+        // Still on the same blend step
+        assert_eq!(layer.advance(0.01), None);
+        assert_eq!(layer.advance(0.25), Some(vec![40; 4]));
+        assert_eq!(layer.advance(1.5), Some(vec![80; 4]));
+    }
+
+    #[test]
+    fn test_mip_chain_sizes() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(16, 16), 5);
+        assert_eq!(mip_level_count(256, 256), 9);
+
+        let sizes = |w, h| {
+            generate_mips(&solid(w, h, [0; 4]), w, h)
+                .iter()
+                .map(|mip| (mip.width, mip.height, mip.raw.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(4, 4), vec![(4, 4, 64), (2, 2, 16), (1, 1, 4)]);
+        // Non-square and odd sizes still end at 1x1
+        assert_eq!(
+            sizes(8, 2),
+            vec![(8, 2, 64), (4, 1, 16), (2, 1, 8), (1, 1, 4)]
+        );
+        assert_eq!(sizes(3, 3), vec![(3, 3, 36), (1, 1, 4)]);
+    }
+
+    #[test]
+    fn test_solid_colour_is_kept() {
+        let mips = generate_mips(&solid(8, 8, [200, 100, 50, 255]), 8, 8);
+        for mip in &mips {
+            assert_eq!(mip.raw, solid(mip.width, mip.height, [200, 100, 50, 255]));
+        }
+    }
+
+    #[test]
+    fn test_averages_in_linear_space() {
+        // A black and white checkerboard averages to a mid grey in linear
+        // space, which is brighter than halfway in sRGB
+        let mut raw = Vec::new();
+        for i in 0..4 {
+            let v = if i % 3 == 0 { 255 } else { 0 };
+            raw.extend([v, v, v, 255]);
+        }
+
+        let mips = generate_mips(&raw, 2, 2);
+        assert_eq!(mips[1].raw, vec![188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn test_transparent_pixels_dont_bleed() {
+        // Red next to fully transparent black, like the edge of a leaf
+        let raw = [
+            [255, 0, 0, 255],
+            [0, 0, 0, 0],
+            [255, 0, 0, 255],
+            [0, 0, 0, 0],
+        ]
+        .concat();
+
+        let mips = generate_mips(&raw, 2, 2);
+        assert_eq!(mips[1].raw, vec![255, 0, 0, 128]);
+
+        // Nothing but transparent pixels stays transparent
+        let mips = generate_mips(&solid(2, 2, [0; 4]), 2, 2);
+        assert_eq!(mips[1].raw, vec![0; 4]);
+    }
+
+    #[test]
+    fn test_sampler_filters() {
+        let sampler = sampler_descriptor(1);
+        assert_eq!(sampler.mag_filter, wgpu::FilterMode::Nearest);
+        assert_eq!(sampler.min_filter, wgpu::FilterMode::Linear);
+        assert_eq!(sampler.mipmap_filter, wgpu::FilterMode::Linear);
+        assert_eq!(sampler.anisotropy_clamp, 1);
+
+        let sampler = sampler_descriptor(64);
+        assert_eq!(sampler.anisotropy_clamp, MAX_ANISOTROPY);
+        assert_eq!(sampler.mag_filter, wgpu::FilterMode::Linear);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use log::warn;
+
+// Turns real time into a whole number of fixed length ticks. Whatever
+// is left over is how far the next frame is between the last tick and
+// the one after it.
+pub struct FixedTimestep {
+    step: Duration,
+    // Ticks to run in one go at most. Any more and a slow tick could
+    // fall further behind every frame, so the rest is dropped.
+    max_ticks: u32,
+    lag: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration, max_ticks: u32) -> Self {
+        Self {
+            step,
+            max_ticks,
+            lag: Duration::ZERO,
+        }
+    }
+
+    // Adds on the time since the last frame, and returns how many
+    // ticks are due
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.lag += elapsed;
+
+        let due = (self.lag.as_nanos() / self.step.as_nanos()) as u32;
+        let ticks = due.min(self.max_ticks);
+        self.lag -= self.step * ticks;
+
+        if due > ticks {
+            warn!("Running {} ticks behind, skipping them", due - ticks);
+            self.lag = Duration::from_nanos((self.lag.as_nanos() % self.step.as_nanos()) as u64);
+        }
+
+        ticks
+    }
+
+    // How far through the current tick we are, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        self.lag.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn test_ticks_at_a_fixed_rate() {
+        let mut timestep = FixedTimestep::new(STEP, 5);
+
+        // Fast frames only tick every so often
+        assert_eq!(timestep.advance(Duration::from_millis(20)), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-4);
+        assert_eq!(timestep.advance(Duration::from_millis(20)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(20)), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-4);
+
+        // A slow frame catches up with several
+        assert_eq!(timestep.advance(Duration::from_millis(140)), 3);
+        assert!(timestep.alpha() < 1e-4);
+    }
+
+    #[test]
+    fn test_long_stall_is_skipped() {
+        let mut timestep = FixedTimestep::new(STEP, 5);
+
+        assert_eq!(timestep.advance(Duration::from_millis(2010)), 5);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-4);
+        // Back to normal afterwards, with nothing left to make up
+        assert_eq!(timestep.advance(STEP), 1);
+    }
+}
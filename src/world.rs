@@ -0,0 +1,446 @@
+use std::{path::Path, time::Duration};
+
+use cgmath::{Deg, Point3, Vector2, Vector3, Zero};
+
+use crate::{
+    camera::Camera,
+    chunk::{BlockType, ChunkManager, ChunkManagerConfig},
+    clock::WorldClock,
+    entity::{EntityManager, TickContext},
+    input::{Action, ActionState},
+    inventory::{GameMode, Inventory, InventoryScreen, Item, ItemStack},
+    item_entity, mob,
+    player::{player_left_click, player_right_click, Entity, Player, Vitals},
+};
+
+// The world moves on in steps of this long, 20 times a second
+pub const TICK: Duration = Duration::from_millis(50);
+// Blocks / s
+const FLY_SPEED: f32 = 1.0;
+// Ticks between blocks while Break is held down
+const BREAK_REPEAT: u32 = 5;
+
+// Everything the player did over one tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inputs {
+    pub actions: ActionState,
+    // How far to turn, in radians of yaw and pitch
+    pub look: Vector2<f32>,
+    // Positive scrolls the hotbar selection left
+    pub scroll: f32,
+    // Slots clicked on while the inventory screen is open, and whether
+    // it was with the right button
+    pub slot_clicks: Vec<(usize, bool)>,
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Self {
+            actions: ActionState::default(),
+            look: Vector2::zero(),
+            scroll: 0.0,
+            slot_clicks: Vec::new(),
+        }
+    }
+}
+
+// The game itself, without anything to draw it with. Nothing in here
+// needs a window or a GPU, so it can be run on its own.
+pub struct World {
+    pub chunk_manager: ChunkManager,
+    pub entities: EntityManager,
+    pub clock: WorldClock,
+    // The player's eyes
+    pub camera: Camera,
+    // Where the camera was before the last tick
+    previous_camera: Camera,
+    // Doesn't do anything yet, the camera flies around on its own
+    player: Player,
+    pub inventory: Inventory,
+    pub inventory_screen: InventoryScreen,
+    // Ticks until the next block goes while Break is held
+    break_timer: Option<u32>,
+    pub game_mode: GameMode,
+    pub vitals: Vitals,
+    // Animals wander in around the player. Tests turn this off so
+    // nothing appears out of nowhere.
+    pub spawn_mobs: bool,
+}
+
+impl World {
+    pub fn new(config: ChunkManagerConfig, clock: WorldClock) -> Self {
+        let mut inventory = Inventory::new();
+        inventory.add(ItemStack::new(Item::Block(BlockType::Dirt), 64));
+        inventory.add(ItemStack::new(Item::Block(BlockType::Stone), 64));
+        inventory.add(ItemStack::new(
+            Item::Block(BlockType::Door { open: false }),
+            16,
+        ));
+        inventory.add(ItemStack::new(Item::Block(BlockType::Lamp), 16));
+        inventory.add(ItemStack::new(Item::Block(BlockType::Water), 16));
+        inventory.add(ItemStack::new(Item::Block(BlockType::Glass), 16));
+        inventory.add(ItemStack::new(Item::Block(BlockType::Leaves), 16));
+
+        let mut entities = EntityManager::default();
+        entities.add_system("mob_spawn", mob::spawn_system);
+
+        Self {
+            chunk_manager: ChunkManager::new(config),
+            entities,
+            clock,
+            camera: Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0)),
+            previous_camera: Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0)),
+            player: Player::new(Point3::new(0.0, 0.0, 0.0)),
+            inventory,
+            inventory_screen: InventoryScreen::default(),
+            break_timer: None,
+            game_mode: GameMode::Survival,
+            vitals: Vitals::default(),
+            spawn_mobs: true,
+        }
+    }
+
+    pub fn tick(&mut self, inputs: &Inputs) {
+        let dt = TICK;
+        self.previous_camera = self.camera;
+
+        self.act(inputs);
+
+        // Relative to where the player is facing
+        let actions = &inputs.actions;
+        let movement = Vector3::new(
+            actions.axis(Action::MoveForward, Action::MoveBack),
+            actions.axis(Action::MoveRight, Action::MoveLeft),
+            actions.axis(Action::Jump, Action::Sneak),
+        );
+
+        self.chunk_manager.generate_around(self.camera.position);
+        self.camera.fly(movement, inputs.look, FLY_SPEED, dt);
+        self.player.update(dt, &self.chunk_manager);
+        self.entities.tick(
+            &mut TickContext {
+                chunk_manager: &self.chunk_manager,
+                player_eye: self.camera.position,
+                inventory: &mut self.inventory,
+                spawn_mobs: self.spawn_mobs,
+            },
+            dt,
+        );
+        self.clock.advance(dt);
+    }
+
+    // Everything the player does in one go rather than over time
+    fn act(&mut self, inputs: &Inputs) {
+        let actions = &inputs.actions;
+
+        if actions.released(Action::Break) {
+            self.break_timer = None;
+        }
+
+        // Pause backs out of the inventory, but never opens it
+        let backing_out = actions.pressed(Action::Pause) && self.inventory_screen.open;
+        if backing_out || actions.pressed(Action::ToggleInventory) {
+            if let Some(left) = self.inventory_screen.toggle(&mut self.inventory) {
+                self.drop_stack(left);
+            }
+        }
+
+        for (slot, action) in Action::HOTBAR.into_iter().enumerate() {
+            if actions.pressed(action) {
+                self.inventory.select(slot);
+            }
+        }
+        if inputs.scroll != 0.0 {
+            self.inventory.scroll(inputs.scroll);
+        }
+
+        // The mouse is busy with the inventory while it's open
+        if self.inventory_screen.open {
+            for &(slot, right) in &inputs.slot_clicks {
+                let held = &mut self.inventory_screen.held;
+                if right {
+                    self.inventory.right_click_slot(slot, held);
+                } else {
+                    self.inventory.click_slot(slot, held);
+                }
+            }
+            return;
+        }
+
+        // Holding Break keeps digging, a block every few ticks
+        let held = actions.held(Action::Break);
+        let break_now = if actions.pressed(Action::Break) {
+            true
+        } else if let Some(timer) = self.break_timer.as_mut().filter(|_| held) {
+            *timer -= 1;
+            *timer == 0
+        } else {
+            false
+        };
+        if break_now {
+            self.break_timer = held.then_some(BREAK_REPEAT);
+            player_left_click(
+                &self.camera,
+                &mut self.chunk_manager,
+                &mut self.entities.entities,
+                self.game_mode,
+            );
+        }
+        if actions.pressed(Action::Place) {
+            player_right_click(
+                &self.camera,
+                &mut self.chunk_manager,
+                &self.entities.entities,
+                &mut self.inventory,
+                self.game_mode,
+            );
+        }
+    }
+
+    // The camera somewhere between the last two ticks, `alpha` of the
+    // way from the one before to the latest
+    pub fn camera_at(&self, alpha: f32) -> Camera {
+        self.previous_camera.lerp(&self.camera, alpha)
+    }
+
+    // Throws a stack out in front of the player
+    fn drop_stack(&mut self, stack: ItemStack) {
+        let front = self.camera.front();
+        item_entity::spawn_item(
+            &mut self.entities.entities,
+            stack,
+            self.camera.position + front * 0.5,
+            front * 3.0,
+        );
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), ()> {
+        self.clock.save(dir)
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new(ChunkManagerConfig::default(), WorldClock::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Rad;
+    use winit::{
+        event::{ElementState, MouseButton},
+        keyboard::KeyCode,
+    };
+
+    use crate::input::{Bindings, Controls};
+
+    use super::*;
+
+    const DIRT: Item = Item::Block(BlockType::Dirt);
+
+    // A small world, with the player standing over the middle of the
+    // first chunk looking straight down. Returns the top of the ground.
+    fn standing_world() -> (World, Point3<i32>) {
+        let mut world = World::new(ChunkManagerConfig::new(1, 1), WorldClock::default());
+        world.spawn_mobs = false;
+        world.camera = Camera::new((8.5, 8.5, 0.0), Deg(0.0), Deg(-90.0));
+        world.tick(&Inputs::default());
+
+        let column = Point3::new(8, 8, 0);
+        let ground = column_top(&world, column);
+        world.camera.position.z = ground.z as f32 + 1.0 + crate::player::EYE_HEIGHT;
+        (world, ground)
+    }
+
+    fn column_top(world: &World, column: Point3<i32>) -> Point3<i32> {
+        let height = world.chunk_manager.column_height(column).unwrap();
+        Point3::new(column.x, column.y, height)
+    }
+
+    // Inputs with the actions all pressed down this tick
+    fn pressing(actions: &[Action]) -> Inputs {
+        let mut inputs = Inputs::default();
+        for action in actions {
+            inputs.actions.press(*action);
+        }
+        inputs
+    }
+
+    fn run(world: &mut World, inputs: &Inputs, ticks: u32) {
+        for _ in 0..ticks {
+            world.tick(inputs);
+        }
+    }
+
+    #[test]
+    fn test_break_block_and_pick_it_up() {
+        let (mut world, ground) = standing_world();
+        let block = world.chunk_manager.get_block(ground).unwrap();
+        let drop = Item::dropped_by(block.block_type()).unwrap().item;
+        let before = world.inventory.count(drop);
+
+        world.tick(&pressing(&[Action::Break]));
+        assert!(world.chunk_manager.get_block(ground).is_none());
+        assert_eq!(world.entities.entities.items.len(), 1);
+
+        // Drop down into the hole after it
+        run(&mut world, &pressing(&[Action::Sneak]), 20);
+        assert!(world.entities.entities.items.is_empty());
+        assert_eq!(world.inventory.count(drop), before + 1);
+    }
+
+    #[test]
+    fn test_dig_through_own_drops() {
+        let (mut world, ground) = standing_world();
+
+        // The first block's drop lands in the hole, right in the way
+        world.tick(&pressing(&[Action::Break]));
+        assert_eq!(world.entities.entities.items.len(), 1);
+        world.tick(&pressing(&[Action::Break]));
+        assert!(world
+            .chunk_manager
+            .get_block(ground - Vector3::unit_z())
+            .is_none());
+    }
+
+    #[test]
+    fn test_holding_break_keeps_digging() {
+        let (mut world, ground) = standing_world();
+        let mut controls = Controls::new(Bindings::default());
+        let tick = |world: &mut World, controls: &mut Controls| {
+            world.tick(&Inputs {
+                actions: controls.take_tick(),
+                ..Default::default()
+            })
+        };
+        let below = |world: &World, depth: i32| {
+            world
+                .chunk_manager
+                .get_block(ground - Vector3::unit_z() * depth)
+                .is_some()
+        };
+
+        controls.process_mouse_button(MouseButton::Left, ElementState::Pressed);
+        tick(&mut world, &mut controls);
+        assert!(!below(&world, 0));
+        for _ in 0..BREAK_REPEAT - 1 {
+            tick(&mut world, &mut controls);
+        }
+        assert!(below(&world, 1));
+        tick(&mut world, &mut controls);
+        assert!(!below(&world, 1));
+
+        // Letting go stops it
+        controls.process_mouse_button(MouseButton::Left, ElementState::Released);
+        for _ in 0..BREAK_REPEAT * 2 {
+            tick(&mut world, &mut controls);
+        }
+        assert!(below(&world, 2));
+    }
+
+    #[test]
+    fn test_place_block_uses_one_up() {
+        let (mut world, ground) = standing_world();
+        let place = pressing(&[Action::Hotbar1, Action::Place]);
+
+        world.tick(&place);
+        let placed = world.chunk_manager.get_block(ground + Vector3::unit_z());
+        assert_eq!(placed.map(|b| b.block_type()), Some(BlockType::Dirt));
+        assert_eq!(world.inventory.count(DIRT), 63);
+
+        // Creative mode has an endless supply
+        world.game_mode = GameMode::Creative;
+        world.tick(&place);
+        assert_eq!(world.inventory.count(DIRT), 63);
+    }
+
+    #[test]
+    fn test_inventory_screen_blocks_clicks() {
+        let (mut world, ground) = standing_world();
+        let toggle = pressing(&[Action::ToggleInventory]);
+
+        world.tick(&toggle);
+        assert!(world.inventory_screen.open);
+        world.tick(&Inputs {
+            slot_clicks: vec![(0, false)],
+            ..pressing(&[Action::Break])
+        });
+        assert!(world.chunk_manager.get_block(ground).is_some());
+        assert_eq!(world.inventory_screen.held.map(|s| s.item), Some(DIRT));
+        assert_eq!(world.inventory.count(DIRT), 0);
+
+        // Closing puts the stack in hand back
+        world.tick(&toggle);
+        assert!(!world.inventory_screen.open);
+        assert_eq!(world.inventory.count(DIRT), 64);
+        assert!(world.entities.entities.items.is_empty());
+    }
+
+    #[test]
+    fn test_pause_backs_out_of_inventory() {
+        let (mut world, _) = standing_world();
+
+        world.tick(&pressing(&[Action::ToggleInventory]));
+        world.tick(&pressing(&[Action::Pause]));
+        assert!(!world.inventory_screen.open);
+        // And doesn't open it again
+        world.tick(&pressing(&[Action::Pause]));
+        assert!(!world.inventory_screen.open);
+    }
+
+    #[test]
+    fn test_fly_and_look() {
+        let (mut world, _) = standing_world();
+        world.camera.pitch = Rad(0.0);
+        let start = world.camera.position;
+
+        // Holding the key down moves along +x with no yaw, one block
+        // for a second at full speed
+        let mut controls = Controls::new(Bindings::default());
+        controls.process_key(KeyCode::KeyW, ElementState::Pressed);
+        for _ in 0..20 {
+            world.tick(&Inputs {
+                actions: controls.take_tick(),
+                ..Default::default()
+            });
+        }
+        assert!((world.camera.position.x - start.x - FLY_SPEED).abs() < 1e-4);
+        assert_eq!(world.camera.position.y, start.y);
+
+        // Looking doesn't carry over to the next tick
+        let look = Inputs {
+            look: Vector2::new(0.1, 0.0),
+            ..Default::default()
+        };
+        world.tick(&look);
+        let yaw = world.camera.yaw;
+        assert!(yaw > Rad(0.0));
+        world.tick(&Inputs::default());
+        assert_eq!(world.camera.yaw, yaw);
+    }
+
+    #[test]
+    fn test_camera_between_ticks() {
+        let (mut world, _) = standing_world();
+        let forward = pressing(&[Action::MoveForward]);
+        world.tick(&forward);
+        let before = world.camera_at(0.0).position;
+        let after = world.camera_at(1.0).position;
+        assert_eq!(after, world.camera.position);
+
+        let step = FLY_SPEED * TICK.as_secs_f32();
+        assert!((after.x - before.x - step).abs() < 1e-4);
+        let halfway = world.camera_at(0.5).position;
+        assert!((halfway.x - before.x - step / 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_clock_ticks() {
+        let mut world = World::new(ChunkManagerConfig::new(0, 0), WorldClock::default());
+        world.spawn_mobs = false;
+        world.clock.day_length = 1.0;
+        run(&mut world, &Inputs::default(), 30);
+        assert_eq!(world.clock.day, 1);
+    }
+}
@@ -0,0 +1,101 @@
+// Sun, shadows and fog, shared by everything drawn in the world
+
+struct SunUniform {
+    direction: vec3<f32>,
+    sky_light: f32,
+    colour: vec3<f32>,
+    ambient: vec3<f32>,
+};
+@group(2) @binding(0)
+var<uniform> sun: SunUniform;
+
+struct ShadowUniform {
+    light_view_proj: array<mat4x4<f32>, 3>,
+    splits: vec4<f32>,
+    view_forward: vec3<f32>,
+    enabled: u32,
+    texel: f32,
+};
+@group(2) @binding(1)
+var<uniform> shadow: ShadowUniform;
+@group(2) @binding(2)
+var shadow_maps: texture_depth_2d_array;
+@group(2) @binding(3)
+var shadow_sampler: sampler_comparison;
+
+// Pushes the lookup off the surface, more for the coarser cascades
+const SHADOW_NORMAL_OFFSET: f32 = 0.04;
+
+// How much of the sun reaches a point, from 0 in full shadow to 1
+fn sun_visibility(world_position: vec3<f32>, view_offset: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if shadow.enabled == 0u {
+        return 1.0;
+    }
+
+    // Pick the nearest cascade that reaches this far
+    let depth = dot(view_offset, shadow.view_forward);
+    var cascade = 0u;
+    while cascade < 3u && depth > shadow.splits[cascade] {
+        cascade += 1u;
+    }
+    if cascade == 3u {
+        return 1.0;
+    }
+
+    let offset = normal * SHADOW_NORMAL_OFFSET * f32(cascade + 1u);
+    let light = shadow.light_view_proj[cascade] * vec4<f32>(world_position + offset, 1.0);
+    let ndc = light.xyz / light.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    // 3x3 percentage closer filtering softens the edges
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x += 1) {
+        for (var y = -1; y <= 1; y += 1) {
+            let sample_uv = uv + vec2<f32>(f32(x), f32(y)) * shadow.texel;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, sample_uv, cascade, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Lambertian sun shading scales the sky light, block light is unaffected
+fn shade(
+    world_position: vec3<f32>,
+    view_offset: vec3<f32>,
+    normal: vec3<f32>,
+    light: vec2<f32>,
+) -> vec3<f32> {
+    let n = normalize(normal);
+    var diffuse = max(dot(n, sun.direction), 0.0);
+    if diffuse > 0.0 {
+        diffuse *= sun_visibility(world_position, view_offset, n);
+    }
+    let sky = light.x * sun.sky_light * (sun.ambient + sun.colour * diffuse);
+    return max(sky, vec3<f32>(light.y));
+}
+
+struct FogUniform {
+    colour: vec3<f32>,
+    mode: u32,
+    start: f32,
+    end: f32,
+    density: f32,
+};
+@group(3) @binding(0)
+var<uniform> fog: FogUniform;
+
+// Linear fog in air, exponential inside fluids
+fn apply_fog(colour: vec3<f32>, view_offset: vec3<f32>) -> vec3<f32> {
+    let distance = length(view_offset);
+    var amount: f32;
+    if fog.mode == 0u {
+        amount = clamp((distance - fog.start) / (fog.end - fog.start), 0.0, 1.0);
+    } else {
+        amount = 1.0 - exp(-fog.density * distance);
+    }
+    return mix(colour, fog.colour, amount);
+}
+